@@ -166,6 +166,7 @@ fn send_stream_data(
 fn log_events(
     mut connected_r: EventReader<Connected>,
     mut disconnected_r: EventReader<Disconnected>,
+    mut connect_failed_r: EventReader<ConnectFailed>,
 ) {
     for &Connected {
         endpoint_entity,
@@ -175,14 +176,27 @@ fn log_events(
         info!("{:?} connected on {:?}", connection_entity, endpoint_entity);
     }
 
-    for &Disconnected {
+    for Disconnected {
         endpoint_entity,
         connection_entity,
+        reason,
     } in disconnected_r.read()
     {
         info!(
-            "{:?} disconnected on {:?}",
-            connection_entity, endpoint_entity
+            "{:?} disconnected on {:?}: {:?}",
+            connection_entity, endpoint_entity, reason
+        );
+    }
+
+    for ConnectFailed {
+        endpoint_entity,
+        connection_entity,
+        reason,
+    } in connect_failed_r.read()
+    {
+        warn!(
+            "{:?} failed to connect on {:?}: {:?}",
+            connection_entity, endpoint_entity, reason
         );
     }
 }
@@ -0,0 +1,199 @@
+use bevy::prelude::*;
+use bevy_interface::prelude::*;
+use nevy_loopback::prelude::*;
+
+/// runs a client and a server in the same app, connected through an in memory loopback network
+fn main() {
+    let mut app = App::new();
+
+    app.add_plugins(MinimalPlugins);
+    app.add_plugins(bevy::log::LogPlugin {
+        level: bevy::log::Level::DEBUG,
+        ..default()
+    });
+
+    app.add_plugins(EndpointPlugin::default());
+    app.add_plugins(StreamHeaderPlugin::default());
+
+    app.add_systems(Startup, (spawn_endpoints, apply_deferred, connect).chain());
+    app.add_systems(Update, (log_events, send_message, receive_message));
+
+    app.run();
+}
+
+#[derive(Component)]
+struct ServerEndpoint;
+
+#[derive(Component)]
+struct ClientEndpoint;
+
+#[derive(Component)]
+struct ServerAddress(LoopbackAddress);
+
+fn spawn_endpoints(mut commands: Commands) {
+    let network = LoopbackNetwork::new();
+
+    let server = LoopbackEndpoint::new(&network, None);
+    let server_address = server.local_addr();
+
+    commands.spawn((
+        ServerEndpoint,
+        EndpointStreamHeaders,
+        BevyEndpoint::new(server),
+    ));
+
+    commands.spawn((
+        ClientEndpoint,
+        ServerAddress(server_address),
+        BevyEndpoint::new(LoopbackEndpoint::new(&network, None)),
+    ));
+}
+
+fn connect(
+    endpoint_q: Query<(Entity, &ServerAddress), With<ClientEndpoint>>,
+    mut connections: Connections,
+) {
+    let (endpoint_entity, &ServerAddress(server_address)) = endpoint_q.single();
+
+    connections
+        .connect(
+            endpoint_entity,
+            Description::new_connect_description::<LoopbackEndpoint>(server_address),
+        )
+        .unwrap()
+        .unwrap();
+}
+
+fn send_message(
+    mut connected_r: EventReader<Connected>,
+    endpoint_q: Query<(), With<ClientEndpoint>>,
+    mut connections: Connections,
+) {
+    for &Connected {
+        endpoint_entity,
+        connection_entity,
+    } in connected_r.read()
+    {
+        if !endpoint_q.contains(endpoint_entity) {
+            continue;
+        }
+
+        let mut endpoint = connections
+            .connection_endpoint_mut(connection_entity)
+            .unwrap();
+
+        let mut connection = endpoint.connection_mut(connection_entity).unwrap();
+
+        let mut stream_id = HeaderStreamId::new(
+            &mut connection,
+            Description::new_open_description::<LoopbackStreamId>(LoopbackDir::Uni),
+            96,
+        )
+        .unwrap()
+        .unwrap();
+
+        // the loopback stream window is large enough that the header and message are written immediately
+        let stream_id = stream_id.poll_ready(&mut connection).unwrap().unwrap();
+
+        let mut stream = connection.send_stream(stream_id).unwrap().unwrap();
+        stream.send(b"Hello Bevy!").unwrap();
+        stream
+            .close(Description::new_send_close_description::<LoopbackStreamId>(
+                None,
+            ))
+            .unwrap()
+            .unwrap();
+    }
+}
+
+fn receive_message(
+    mut stream_event_r: EventReader<HeaderStreamEvent>,
+    endpoint_q: Query<(), With<ServerEndpoint>>,
+    mut streams: Local<Vec<(Entity, BevyStreamId, Vec<u8>)>>,
+    mut connections: Connections,
+) {
+    for HeaderStreamEvent {
+        endpoint_entity,
+        connection_entity,
+        stream_id,
+        event_type,
+        ..
+    } in stream_event_r.read()
+    {
+        if !endpoint_q.contains(*endpoint_entity) {
+            continue;
+        }
+
+        if let HeaderStreamEventType::NewRecvStream(header) = event_type {
+            info!("new recv stream with header {}", header);
+
+            streams.push((*connection_entity, stream_id.clone(), Vec::new()));
+        }
+    }
+
+    streams.retain_mut(|(connection_entity, stream_id, buffer)| {
+        let mut endpoint = connections
+            .connection_endpoint_mut(*connection_entity)
+            .unwrap();
+
+        let mut connection = endpoint.connection_mut(*connection_entity).unwrap();
+
+        let mut stream = connection.recv_stream(stream_id.clone()).unwrap().unwrap();
+
+        loop {
+            match stream.recv(usize::MAX) {
+                Ok(data) => buffer.extend(data.as_ref()),
+                Err(err) => {
+                    if err.is_fatal() {
+                        panic!("fatal error reading stream");
+                    }
+
+                    if !stream.is_open() {
+                        info!("message received: {:?}", String::from_utf8_lossy(buffer));
+                        return false;
+                    }
+
+                    return true;
+                }
+            }
+        }
+    });
+}
+
+fn log_events(
+    mut connected_r: EventReader<Connected>,
+    mut disconnected_r: EventReader<Disconnected>,
+    mut connect_failed_r: EventReader<ConnectFailed>,
+) {
+    for &Connected {
+        endpoint_entity,
+        connection_entity,
+    } in connected_r.read()
+    {
+        info!("{:?} connected on {:?}", connection_entity, endpoint_entity);
+    }
+
+    for Disconnected {
+        endpoint_entity,
+        connection_entity,
+        reason,
+    } in disconnected_r.read()
+    {
+        info!(
+            "{:?} disconnected on {:?}: {:?}",
+            connection_entity, endpoint_entity, reason
+        );
+    }
+
+    for ConnectFailed {
+        endpoint_entity,
+        connection_entity,
+        reason,
+    } in connect_failed_r.read()
+    {
+        warn!(
+            "{:?} failed to connect on {:?}: {:?}",
+            connection_entity, endpoint_entity, reason
+        );
+    }
+}
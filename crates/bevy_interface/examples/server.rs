@@ -84,6 +84,10 @@ fn spawn_endpoint(mut commands: Commands) {
         ExampleEndpoint,
         EndpointStreamHeaders,
         BevyEndpoint::new(endpoint),
+        IncomingConnectionFilter::new::<QuinnEndpoint>(|incoming| {
+            info!("connection request from {}", incoming.info.remote_address());
+            true
+        }),
     ));
 }
 
@@ -175,14 +179,15 @@ fn log_events(
         info!("{:?} connected on {:?}", connection_entity, endpoint_entity);
     }
 
-    for &Disconnected {
+    for Disconnected {
         endpoint_entity,
         connection_entity,
+        reason,
     } in disconnected_r.read()
     {
         info!(
-            "{:?} disconnected on {:?}",
-            connection_entity, endpoint_entity
+            "{:?} disconnected on {:?}: {:?}",
+            connection_entity, endpoint_entity, reason
         );
     }
 }
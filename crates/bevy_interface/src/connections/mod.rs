@@ -1,7 +1,9 @@
 use transport_interface::*;
 
+mod stats;
 mod stream_access;
 mod stream_id;
+pub use stats::*;
 pub use stream_access::*;
 pub use stream_id::*;
 
@@ -24,6 +26,20 @@ trait BevyConnectionInner<'c> {
     ) -> Result<Option<BevyRecvStream>, MismatchedType>;
 
     fn poll_stream_events(&mut self) -> Option<BevyStreamEvent>;
+
+    fn send_datagram(&mut self, data: &[u8]) -> Result<(), SendDatagramError>;
+
+    fn recv_datagram(&mut self) -> Option<Box<[u8]>>;
+
+    fn peek_datagram(&mut self) -> Option<&[u8]>;
+
+    fn max_datagram_size(&mut self) -> Option<usize>;
+
+    fn get_stats(&self) -> BevyConnectionStats;
+
+    fn disconnect(&mut self);
+
+    fn disconnect_with_reason(&mut self, code: u64, reason: &[u8]);
 }
 
 /// type erased mutable access to a connection
@@ -77,6 +93,34 @@ where
     fn poll_stream_events(&mut self) -> Option<BevyStreamEvent> {
         self.poll_stream_events().map(Into::into)
     }
+
+    fn send_datagram(&mut self, data: &[u8]) -> Result<(), SendDatagramError> {
+        self.send_datagram(data)
+    }
+
+    fn recv_datagram(&mut self) -> Option<Box<[u8]>> {
+        self.recv_datagram()
+    }
+
+    fn peek_datagram(&mut self) -> Option<&[u8]> {
+        self.peek_datagram()
+    }
+
+    fn max_datagram_size(&mut self) -> Option<usize> {
+        self.max_datagram_size()
+    }
+
+    fn get_stats(&self) -> BevyConnectionStats {
+        BevyConnectionStats::new(self.get_stats())
+    }
+
+    fn disconnect(&mut self) {
+        self.disconnect()
+    }
+
+    fn disconnect_with_reason(&mut self, code: u64, reason: &[u8]) {
+        self.disconnect_with_reason(code, reason)
+    }
 }
 
 impl<'c> BevyConnectionMut<'c> {
@@ -113,6 +157,45 @@ impl<'c> BevyConnectionMut<'c> {
     pub fn poll_stream_events(&mut self) -> Option<BevyStreamEvent> {
         self.inner.poll_stream_events()
     }
+
+    /// queues an unreliable and unordered datagram to be sent
+    pub fn send_datagram(&mut self, data: &[u8]) -> Result<(), SendDatagramError> {
+        self.inner.send_datagram(data)
+    }
+
+    /// receives the next datagram if one is available
+    pub fn recv_datagram(&mut self) -> Option<Box<[u8]>> {
+        self.inner.recv_datagram()
+    }
+
+    /// returns the next datagram without receiving it, if one is available
+    pub fn peek_datagram(&mut self) -> Option<&[u8]> {
+        self.inner.peek_datagram()
+    }
+
+    /// the maximum size of a datagram that can currently be sent
+    ///
+    /// returns [None] if datagrams are not supported by the transport or the peer
+    pub fn max_datagram_size(&mut self) -> Option<usize> {
+        self.inner.max_datagram_size()
+    }
+
+    /// gets the type erased stats of the connection
+    pub fn get_stats(&self) -> BevyConnectionStats {
+        self.inner.get_stats()
+    }
+
+    /// closes the connection
+    pub fn disconnect(&mut self) {
+        self.inner.disconnect()
+    }
+
+    /// closes the connection with an application error code and reason
+    ///
+    /// the peer will receive them in the [DisconnectReason] of it's [Disconnected](crate::Disconnected) event
+    pub fn disconnect_with_reason(&mut self, code: u64, reason: &[u8]) {
+        self.inner.disconnect_with_reason(code, reason)
+    }
 }
 
 /// type erased stream event
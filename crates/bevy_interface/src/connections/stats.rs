@@ -0,0 +1,59 @@
+use std::any::Any;
+
+use crate::MismatchedType;
+
+trait BevyConnectionStatsInner: std::fmt::Debug + Send + Sync {
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// type erased connection stats returned by [BevyConnectionMut::get_stats](super::BevyConnectionMut::get_stats)
+///
+/// downcast to the `ConnectionStats` type of the endpoint's connections to read them,
+/// for example `QuinnConnectionStats`
+pub struct BevyConnectionStats {
+    inner: Box<dyn BevyConnectionStatsInner>,
+}
+
+impl std::fmt::Debug for BevyConnectionStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl<T: std::fmt::Debug + Send + Sync + 'static> BevyConnectionStatsInner for T {
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl BevyConnectionStats {
+    pub(crate) fn new<T: std::fmt::Debug + Send + Sync + 'static>(stats: T) -> Self {
+        BevyConnectionStats {
+            inner: Box::new(stats),
+        }
+    }
+
+    pub fn downcast<T: 'static>(self) -> Result<T, MismatchedType> {
+        match self.inner.into_any().downcast() {
+            Ok(downcasted) => Ok(*downcasted),
+            Err(_) => Err(MismatchedType {
+                expected: std::any::type_name::<T>(),
+            }),
+        }
+    }
+
+    pub fn downcast_ref<T: 'static>(&self) -> Result<&T, MismatchedType> {
+        match (*self.inner).as_any().downcast_ref() {
+            Some(downcasted) => Ok(downcasted),
+            None => Err(MismatchedType {
+                expected: std::any::type_name::<T>(),
+            }),
+        }
+    }
+}
@@ -57,7 +57,7 @@ impl Description {
     }
 }
 
-trait CloneableDescriptionInner {
+trait CloneableDescriptionInner: Send + Sync {
     fn clone(&self) -> Box<dyn CloneableDescriptionInner>;
 
     fn into_any(self: Box<Self>) -> Box<dyn Any>;
@@ -72,7 +72,7 @@ pub struct CloneableDescription {
     description: Box<dyn CloneableDescriptionInner>,
 }
 
-impl<T: Clone + 'static> CloneableDescriptionInner for T {
+impl<T: Clone + Send + Sync + 'static> CloneableDescriptionInner for T {
     fn clone(&self) -> Box<dyn CloneableDescriptionInner> {
         Box::new(self.clone())
     }
@@ -101,7 +101,31 @@ impl From<CloneableDescription> for Description {
 impl CloneableDescription {
     pub fn new<S: StreamId>(description: S::OpenDescription) -> Self
     where
-        S::OpenDescription: Clone + 'static,
+        S::OpenDescription: Clone + Send + Sync + 'static,
+    {
+        CloneableDescription {
+            description: Box::new(description),
+        }
+    }
+
+    /// creates a new description used for closing a send stream of `S`
+    pub fn new_send_close<'s, S: StreamId>(
+        description: <S::SendMut<'s> as SendStreamMut<'s>>::CloseDescription,
+    ) -> Self
+    where
+        <S::SendMut<'s> as SendStreamMut<'s>>::CloseDescription: Clone + Send + Sync + 'static,
+    {
+        CloneableDescription {
+            description: Box::new(description),
+        }
+    }
+
+    /// creates a new description used for closing a recv stream of `S`
+    pub fn new_recv_close<'s, S: StreamId>(
+        description: <S::RecvMut<'s> as RecvStreamMut<'s>>::CloseDescription,
+    ) -> Self
+    where
+        <S::RecvMut<'s> as RecvStreamMut<'s>>::CloseDescription: Clone + Send + Sync + 'static,
     {
         CloneableDescription {
             description: Box::new(description),
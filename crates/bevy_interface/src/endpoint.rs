@@ -1,9 +1,15 @@
-use bevy::{prelude::*, utils::HashMap};
+use std::any::Any;
+
+use bevy::{
+    ecs::{component::ComponentId, world::DeferredWorld},
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
 use transport_interface::*;
 
 use crate::{
-    connections::BevyConnectionMut, description::Description, Connected, Disconnected,
-    MismatchedType,
+    connections::BevyConnectionMut, description::Description, ConnectFailed, Connected,
+    ConnectionRequest, Disconnected, MismatchedType,
 };
 
 /// the component that holds state and represents a networking endpoint
@@ -18,7 +24,12 @@ pub struct BevyEndpoint {
 }
 
 trait BevyEndpointType: Send + Sync {
-    fn update(&mut self, endpoint_entity: Entity, params: &mut UpdateHandlerParams);
+    fn update(
+        &mut self,
+        endpoint_entity: Entity,
+        params: &mut UpdateHandlerParams,
+        filter: Option<&mut IncomingConnectionFilter>,
+    );
 
     fn connect(
         &mut self,
@@ -41,6 +52,8 @@ where
     ///
     /// entities are used as connection ids so that the application doesn't need to specify generics
     connections: ConnectionMap<E::ConnectionId>,
+    /// connections that were opened locally and haven't connected yet
+    connecting: HashSet<E::ConnectionId>,
 }
 
 /// a two way map of connection ids to entities
@@ -55,23 +68,119 @@ struct ConnectionMap<C: std::hash::Hash + Eq + Copy> {
 ///
 /// will exist on all [BevyConnectionState]s,
 /// but has no generic so it can be queried without that type info
+///
+/// despawning a connection entity or removing this component will gracefully close the connection.
+/// a [Disconnected] event will still be fired once the transport has closed it
 #[derive(Component)]
+#[component(on_remove = disconnect_removed_connection)]
 pub struct BevyConnection;
 
+/// the stage of the connection lifecycle a connection is in
+///
+/// will exist on all connection entities along with [BevyConnection]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    /// the connection was opened locally and is still handshaking
+    Connecting,
+    /// the connection has been established
+    Connected,
+    /// the connection was closed using [Connections] or [CloseConnection]
+    /// and is waiting for the transport to finish closing it
+    Disconnecting,
+}
+
+/// insert onto a connection entity to gracefully close the connection
+/// with an application error code and reason
+///
+/// the entity will be despawned once the transport has closed the connection
+#[derive(Component, Default)]
+#[component(on_insert = close_connection)]
+pub struct CloseConnection {
+    pub code: u64,
+    pub reason: Box<[u8]>,
+}
+
+/// insert onto an endpoint to decide which incoming connections are accepted
+///
+/// endpoints without this component will accept all incoming connections
+#[derive(Component)]
+pub struct IncomingConnectionFilter {
+    /// values will be a [Box<ConnectionFilterFn<E>>] of the endpoint type
+    filter: Box<dyn Any + Send + Sync>,
+}
+
+/// the callback held by an [IncomingConnectionFilter] for an endpoint `E`
+type ConnectionFilterFn<E> = dyn for<'i> FnMut(IncomingConnection<'i, E>) -> bool + Send + Sync;
+
+/// an incoming connection given to an [IncomingConnectionFilter]
+pub struct IncomingConnection<'i, E: Endpoint> {
+    pub endpoint_entity: Entity,
+    /// the number of connections the endpoint currently has
+    pub connection_count: usize,
+    /// endpoint specific information about the connection, such as the remote address
+    pub info: E::IncomingConnectionInfo<'i>,
+}
+
+/// the type erased address of a peer that requested a connection, given by [ConnectionRequest]
+///
+/// downcast to the `RemoteAddress` type of the endpoint to read it,
+/// for example `SocketAddr` for quic endpoints
+pub struct BevyRemoteAddress {
+    inner: Box<dyn RemoteAddressInner>,
+}
+
+trait RemoteAddressInner: std::fmt::Debug + Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: std::fmt::Debug + Send + Sync + 'static> RemoteAddressInner for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl std::fmt::Debug for BevyRemoteAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl BevyRemoteAddress {
+    fn new<T: std::fmt::Debug + Send + Sync + 'static>(address: T) -> Self {
+        BevyRemoteAddress {
+            inner: Box::new(address),
+        }
+    }
+
+    pub fn downcast_ref<T: 'static>(&self) -> Result<&T, MismatchedType> {
+        match (*self.inner).as_any().downcast_ref() {
+            Some(downcasted) => Ok(downcasted),
+            None => Err(MismatchedType {
+                expected: std::any::type_name::<T>(),
+            }),
+        }
+    }
+}
+
 /// system params used by [UpdateHandler]
 #[derive(bevy::ecs::system::SystemParam)]
 pub(crate) struct UpdateHandlerParams<'w, 's> {
     commands: Commands<'w, 's>,
     connected_w: EventWriter<'w, Connected>,
     disconnected_w: EventWriter<'w, Disconnected>,
+    connect_failed_w: EventWriter<'w, ConnectFailed>,
+    connection_request_w: EventWriter<'w, ConnectionRequest>,
 }
 
 /// the endpoint event handler for updating endpoints in bevy
 struct UpdateHandler<'a, 'w, 's, E: Endpoint> {
     params: &'a mut UpdateHandlerParams<'w, 's>,
-    accept_inoming: bool,
+    filter: Option<&'a mut ConnectionFilterFn<E>>,
+    /// whether to accept incoming connections when there is no filter
+    accept_incoming: bool,
     endpoint_entity: Entity,
     connections: &'a mut ConnectionMap<E::ConnectionId>,
+    connecting: &'a mut HashSet<E::ConnectionId>,
 }
 
 #[derive(bevy::ecs::system::SystemParam)]
@@ -92,6 +201,7 @@ impl BevyEndpoint {
             state: Box::new(BevyEndpointState {
                 endpoint,
                 connections: ConnectionMap::new(),
+                connecting: HashSet::new(),
             }),
         }
     }
@@ -107,12 +217,31 @@ where
     E::ConnectionId: Send + Sync,
     for<'a> <E::Connection<'a> as ConnectionMut<'a>>::StreamType: Send + Sync,
 {
-    fn update(&mut self, endpoint_entity: Entity, params: &mut UpdateHandlerParams) {
+    fn update(
+        &mut self,
+        endpoint_entity: Entity,
+        params: &mut UpdateHandlerParams,
+        filter: Option<&mut IncomingConnectionFilter>,
+    ) {
+        let (filter, accept_incoming) = match filter.map(|filter| filter.downcast_mut::<E>()) {
+            None => (None, true),
+            Some(Ok(filter)) => (Some(filter), false),
+            Some(Err(err)) => {
+                error!(
+                    "endpoint {:?}'s connection filter is for a different endpoint type, expected {}. all incoming connections will be rejected",
+                    endpoint_entity, err.expected
+                );
+                (None, false)
+            }
+        };
+
         self.endpoint.update(&mut UpdateHandler {
             params,
-            accept_inoming: true, // TODO: add api
+            filter,
+            accept_incoming,
             endpoint_entity,
             connections: &mut self.connections,
+            connecting: &mut self.connecting,
         });
     }
 
@@ -129,7 +258,7 @@ where
         };
 
         let entity = commands
-            .spawn(BevyConnection)
+            .spawn((BevyConnection, ConnectionStatus::Connecting))
             .set_parent(endpoint_entity)
             .id();
 
@@ -141,6 +270,8 @@ where
             );
         }
 
+        self.connecting.insert(connection_id);
+
         Ok(Some(entity))
     }
 
@@ -213,6 +344,36 @@ impl<C: std::hash::Hash + Eq + Copy> ConnectionMap<C> {
     fn get_connection_entity(&self, connection_id: C) -> Option<Entity> {
         self.connection_entities.get(&connection_id).copied()
     }
+
+    fn len(&self) -> usize {
+        self.connection_entities.len()
+    }
+}
+
+impl IncomingConnectionFilter {
+    /// creates a new filter for an endpoint of type `E`
+    ///
+    /// the callback returns `true` to accept a connection
+    pub fn new<E: Endpoint + 'static>(
+        filter: impl for<'i> FnMut(IncomingConnection<'i, E>) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        let filter: Box<ConnectionFilterFn<E>> = Box::new(filter);
+
+        IncomingConnectionFilter {
+            filter: Box::new(filter),
+        }
+    }
+
+    fn downcast_mut<E: Endpoint + 'static>(
+        &mut self,
+    ) -> Result<&mut ConnectionFilterFn<E>, MismatchedType> {
+        match self.filter.downcast_mut::<Box<ConnectionFilterFn<E>>>() {
+            Some(filter) => Ok(filter.as_mut()),
+            None => Err(MismatchedType {
+                expected: std::any::type_name::<E>(),
+            }),
+        }
+    }
 }
 
 impl<'a, 'w, 's, E: Endpoint> EndpointEventHandler<E> for UpdateHandler<'a, 'w, 's, E>
@@ -221,19 +382,53 @@ where
 {
     fn connection_request<'i>(
         &mut self,
-        _request: <E as Endpoint>::IncomingConnectionInfo<'i>,
+        info: <E as Endpoint>::IncomingConnectionInfo<'i>,
     ) -> bool {
-        self.accept_inoming
+        let remote_address = BevyRemoteAddress::new(E::remote_address(&info));
+
+        let accepted = match &mut self.filter {
+            None => self.accept_incoming,
+            Some(filter) => filter(IncomingConnection {
+                endpoint_entity: self.endpoint_entity,
+                connection_count: self.connections.len(),
+                info,
+            }),
+        };
+
+        self.params.connection_request_w.send(ConnectionRequest {
+            endpoint_entity: self.endpoint_entity,
+            remote_address,
+            accepted,
+        });
+
+        accepted
     }
 
     fn connected(&mut self, connection_id: <E as Endpoint>::ConnectionId) {
+        self.connecting.remove(&connection_id);
+
         let connection_entity = match self.connections.get_connection_entity(connection_id) {
-            Some(connection_entity) => connection_entity,
+            Some(connection_entity) => {
+                if let Some(mut connection_commands) =
+                    self.params.commands.get_entity(connection_entity)
+                {
+                    // the connection may have been closed while it was connecting
+                    connection_commands.queue(|mut entity: EntityWorldMut| {
+                        if let Some(mut status) = entity.get_mut::<ConnectionStatus>() {
+                            if *status == ConnectionStatus::Connecting {
+                                *status = ConnectionStatus::Connected;
+                            }
+                        }
+                    });
+                }
+
+                connection_entity
+            }
             None => {
                 let connection_entity = self
                     .params
                     .commands
-                    .spawn(BevyConnection)
+                    .spawn((BevyConnection, ConnectionStatus::Connected))
                     .set_parent(self.endpoint_entity)
                     .id();
 
@@ -249,14 +444,30 @@ where
         });
     }
 
-    fn disconnected(&mut self, connection_id: <E as Endpoint>::ConnectionId) {
+    fn disconnected(
+        &mut self,
+        connection_id: <E as Endpoint>::ConnectionId,
+        reason: DisconnectReason,
+    ) {
         if let Some(connection_entity) = self.connections.remove_connection(connection_id) {
-            self.params.commands.entity(connection_entity).despawn_recursive();
+            // the entity may have already been despawned to close the connection
+            if let Some(connection_commands) = self.params.commands.get_entity(connection_entity) {
+                connection_commands.despawn_recursive();
+            }
 
-            self.params.disconnected_w.send(Disconnected {
-                endpoint_entity: self.endpoint_entity,
-                connection_entity,
-            });
+            if self.connecting.remove(&connection_id) {
+                self.params.connect_failed_w.send(ConnectFailed {
+                    endpoint_entity: self.endpoint_entity,
+                    connection_entity,
+                    reason,
+                });
+            } else {
+                self.params.disconnected_w.send(Disconnected {
+                    endpoint_entity: self.endpoint_entity,
+                    connection_entity,
+                    reason,
+                });
+            }
         }
     }
 }
@@ -304,13 +515,122 @@ impl<'w, 's> Connections<'w, 's> {
 
         Some(endpoint)
     }
+
+    /// gracefully closes a connection
+    ///
+    /// returns `false` if the entity isn't a connection
+    pub fn disconnect(&mut self, connection_entity: Entity) -> bool {
+        self.close_connection(connection_entity, |connection| connection.disconnect())
+    }
+
+    /// gracefully closes a connection with an application error code and reason
+    ///
+    /// returns `false` if the entity isn't a connection
+    pub fn disconnect_with_reason(
+        &mut self,
+        connection_entity: Entity,
+        code: u64,
+        reason: &[u8],
+    ) -> bool {
+        self.close_connection(connection_entity, |connection| {
+            connection.disconnect_with_reason(code, reason)
+        })
+    }
+
+    /// closes a connection with `close` and marks it as [ConnectionStatus::Disconnecting]
+    fn close_connection(
+        &mut self,
+        connection_entity: Entity,
+        close: impl FnOnce(&mut BevyConnectionMut),
+    ) -> bool {
+        {
+            let Some(mut endpoint) = self.connection_endpoint_mut(connection_entity) else {
+                return false;
+            };
+
+            let Some(mut connection) = endpoint.connection_mut(connection_entity) else {
+                return false;
+            };
+
+            close(&mut connection);
+        }
+
+        self.commands
+            .entity(connection_entity)
+            .insert(ConnectionStatus::Disconnecting);
+
+        true
+    }
+}
+
+/// gets the [BevyEndpoint] component of a connection's parent from inside a component hook
+fn hook_connection_endpoint<'w>(
+    world: &'w mut DeferredWorld,
+    connection_entity: Entity,
+) -> Option<Mut<'w, BevyEndpoint>> {
+    let connection_parent = world.get::<Parent>(connection_entity)?.get();
+
+    world.get_mut::<BevyEndpoint>(connection_parent)
+}
+
+/// [BevyConnection] remove hook that closes the connection
+fn disconnect_removed_connection(
+    mut world: DeferredWorld,
+    connection_entity: Entity,
+    _: ComponentId,
+) {
+    let Some(mut endpoint) = hook_connection_endpoint(&mut world, connection_entity) else {
+        return;
+    };
+
+    // the connection will already be removed if the transport closed it
+    let Some(mut connection) = endpoint.connection_mut(connection_entity) else {
+        return;
+    };
+
+    connection.disconnect();
+}
+
+/// [CloseConnection] insert hook that closes the connection
+fn close_connection(mut world: DeferredWorld, connection_entity: Entity, _: ComponentId) {
+    let Some(close) = world.get::<CloseConnection>(connection_entity) else {
+        return;
+    };
+    let code = close.code;
+    let reason = close.reason.clone();
+
+    {
+        let Some(mut endpoint) = hook_connection_endpoint(&mut world, connection_entity) else {
+            warn!(
+                "inserted CloseConnection on {:?} which isn't a connection",
+                connection_entity
+            );
+            return;
+        };
+
+        let Some(mut connection) = endpoint.connection_mut(connection_entity) else {
+            return;
+        };
+
+        connection.disconnect_with_reason(code, &reason);
+    }
+
+    if let Some(mut status) = world.get_mut::<ConnectionStatus>(connection_entity) {
+        *status = ConnectionStatus::Disconnecting;
+    }
 }
 
 pub(crate) fn update_endpoints(
     mut params: UpdateHandlerParams,
-    mut endpoint_q: Query<(Entity, &mut BevyEndpoint)>,
+    mut endpoint_q: Query<(
+        Entity,
+        &mut BevyEndpoint,
+        Option<&mut IncomingConnectionFilter>,
+    )>,
 ) {
-    for (endpoint_entity, mut endpoint) in endpoint_q.iter_mut() {
-        endpoint.state.update(endpoint_entity, &mut params);
+    for (endpoint_entity, mut endpoint, filter) in endpoint_q.iter_mut() {
+        endpoint
+            .state
+            .update(endpoint_entity, &mut params, filter.map(Mut::into_inner));
     }
 }
@@ -2,6 +2,8 @@ use bevy::{
     ecs::{intern::Interned, schedule::ScheduleLabel},
     prelude::*,
 };
+use endpoint::BevyRemoteAddress;
+use transport_interface::DisconnectReason;
 
 pub mod connections;
 pub mod description;
@@ -10,16 +12,25 @@ pub mod stream_headers;
 
 pub mod prelude {
     pub use crate::connections::{
-        BevyConnectionMut, BevyRecvStream, BevySendStream, BevyStreamEvent, BevyStreamId,
+        BevyConnectionMut, BevyConnectionStats, BevyRecvStream, BevySendStream, BevyStreamEvent,
+        BevyStreamId,
     };
     pub use crate::description::{CloneableDescription, Description};
-    pub use crate::endpoint::{BevyConnection, BevyEndpoint, ConnectError, Connections};
+    pub use crate::endpoint::{
+        BevyConnection, BevyEndpoint, BevyRemoteAddress, CloseConnection, ConnectError,
+        ConnectionStatus, Connections, IncomingConnection, IncomingConnectionFilter,
+    };
     pub use crate::stream_headers::{
         EndpointStreamHeaders, HeaderStreamEvent, HeaderStreamEventType, HeaderStreamId,
         StreamHeaderPlugin,
     };
-    pub use crate::{Connected, Disconnected, EndpointPlugin, MismatchedType, UpdateEndpoints};
-    pub use transport_interface::StreamEventType;
+    pub use crate::{
+        ConnectFailed, Connected, ConnectionRequest, Disconnected, EndpointPlugin, MismatchedType,
+        UpdateEndpoints,
+    };
+    pub use transport_interface::{
+        DisconnectReason, SendDatagramError, StreamEventType, PROTOCOL_MISMATCH_CODE,
+    };
 }
 
 #[derive(Debug)]
@@ -56,6 +67,8 @@ impl Plugin for EndpointPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<Connected>();
         app.add_event::<Disconnected>();
+        app.add_event::<ConnectFailed>();
+        app.add_event::<ConnectionRequest>();
 
         app.add_systems(
             self.schedule,
@@ -73,9 +86,37 @@ pub struct Connected {
 
 /// fired when an existing connection has disconnected
 ///
-/// a matching [Connected] event will not have been fired if this was a connection attempt that failed
+/// a matching [Connected] event will have been fired,
+/// connection attempts that fail fire [ConnectFailed] instead
 #[derive(Event)]
 pub struct Disconnected {
     pub endpoint_entity: Entity,
     pub connection_entity: Entity,
+    pub reason: DisconnectReason,
+}
+
+/// fired when a connection opened with [Connections::connect](crate::endpoint::Connections::connect)
+/// fails before it is established
+#[derive(Event)]
+pub struct ConnectFailed {
+    pub endpoint_entity: Entity,
+    pub connection_entity: Entity,
+    pub reason: DisconnectReason,
+}
+
+/// fired when an endpoint receives an incoming connection request
+///
+/// insert an [IncomingConnectionFilter](crate::endpoint::IncomingConnectionFilter)
+/// on the endpoint to decide which requests are accepted,
+/// this event is fired after the filter has made it's decision
+#[derive(Event)]
+pub struct ConnectionRequest {
+    pub endpoint_entity: Entity,
+    /// the address of the peer that requested the connection,
+    /// which can be logged or used to update the endpoint's filter
+    pub remote_address: BevyRemoteAddress,
+    /// whether the connection was accepted
+    ///
+    /// if accepted a [Connected] event will be fired once the connection is established
+    pub accepted: bool,
 }
@@ -84,11 +84,16 @@ fn initialize_clients(
         connection_entity,
     } in connected_r.read()
     {
-        if endpoint_q.contains(endpoint_entity) {
-            commands
-                .entity(connection_entity)
-                .insert(ConnectionStreamHeaders::default());
+        if !endpoint_q.contains(endpoint_entity) {
+            continue;
         }
+
+        // the connection may have been closed before this system ran
+        let Some(mut connection_commands) = commands.get_entity(connection_entity) else {
+            continue;
+        };
+
+        connection_commands.try_insert(ConnectionStreamHeaders::default());
     }
 }
 
@@ -0,0 +1,126 @@
+use bevy::{ecs::system::RunSystemOnce, prelude::*};
+use bevy_interface::prelude::*;
+use nevy_loopback::prelude::*;
+
+/// the address of a test endpoint
+#[derive(Component)]
+struct TestAddress(LoopbackAddress);
+
+/// an app with a client endpoint that connects to a server endpoint over a loopback network
+fn loopback_app() -> (App, Entity, Entity) {
+    let mut app = App::new();
+
+    app.add_plugins(EndpointPlugin::default());
+
+    let network = LoopbackNetwork::new();
+    let server_entity = spawn_endpoint(&mut app, &network);
+    let client_entity = spawn_endpoint(&mut app, &network);
+
+    let server_address = app.world().get::<TestAddress>(server_entity).unwrap().0;
+
+    app.world_mut()
+        .run_system_once(move |mut connections: Connections| {
+            connections
+                .connect(
+                    client_entity,
+                    Description::new_connect_description::<LoopbackEndpoint>(server_address),
+                )
+                .unwrap()
+                .unwrap();
+        })
+        .unwrap();
+
+    (app, client_entity, server_entity)
+}
+
+fn spawn_endpoint(app: &mut App, network: &LoopbackNetwork) -> Entity {
+    let endpoint = LoopbackEndpoint::new(network, None);
+
+    app.world_mut()
+        .spawn((
+            TestAddress(endpoint.local_addr()),
+            BevyEndpoint::new(endpoint),
+        ))
+        .id()
+}
+
+/// the first connection of an endpoint
+fn endpoint_connection(app: &mut App, endpoint_entity: Entity) -> Entity {
+    app.world_mut()
+        .query_filtered::<(Entity, &Parent), With<BevyConnection>>()
+        .iter(app.world())
+        .find(|(_, parent)| parent.get() == endpoint_entity)
+        .map(|(connection_entity, _)| connection_entity)
+        .unwrap()
+}
+
+#[test]
+fn disconnected_event_has_reason() {
+    let (mut app, client_entity, server_entity) = loopback_app();
+
+    for _ in 0..10 {
+        app.update();
+    }
+
+    let client_connection = endpoint_connection(&mut app, client_entity);
+
+    app.world_mut()
+        .entity_mut(client_connection)
+        .insert(CloseConnection {
+            code: 3,
+            reason: b"closed".as_slice().into(),
+        });
+
+    assert_eq!(
+        app.world().get::<ConnectionStatus>(client_connection),
+        Some(&ConnectionStatus::Disconnecting)
+    );
+
+    let mut disconnected = Vec::new();
+    for _ in 0..10 {
+        app.update();
+
+        let events = app.world().resource::<Events<Disconnected>>();
+        disconnected.extend(
+            events
+                .iter_current_update_events()
+                .map(|event| (event.endpoint_entity, event.reason.clone())),
+        );
+    }
+
+    assert!(disconnected.contains(&(client_entity, DisconnectReason::LocallyClosed)));
+    assert!(disconnected.contains(&(
+        server_entity,
+        DisconnectReason::ApplicationClosed {
+            code: 3,
+            reason: b"closed".as_slice().into(),
+        }
+    )));
+}
+
+#[test]
+fn connection_request_has_remote_address() {
+    let (mut app, client_entity, server_entity) = loopback_app();
+
+    let &TestAddress(client_address) = app.world().get(client_entity).unwrap();
+
+    let mut requests = Vec::new();
+    for _ in 0..10 {
+        app.update();
+
+        let events = app.world().resource::<Events<ConnectionRequest>>();
+        requests.extend(events.iter_current_update_events().map(|request| {
+            (
+                request.endpoint_entity,
+                request.accepted,
+                request
+                    .remote_address
+                    .downcast_ref::<LoopbackAddress>()
+                    .ok()
+                    .copied(),
+            )
+        }));
+    }
+
+    assert_eq!(requests, vec![(server_entity, true, Some(client_address))]);
+}
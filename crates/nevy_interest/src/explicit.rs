@@ -0,0 +1,77 @@
+//! entities that list the connections they are relevant to
+
+use bevy::{
+    ecs::{entity::EntityHashSet, intern::Interned, schedule::ScheduleLabel},
+    prelude::*,
+};
+
+use crate::{ConnectionInterest, InterestStrategy, InterestSystems};
+
+/// makes entities with a [VisibleTo] relevant to the connections it contains
+pub struct ExplicitInterest;
+
+impl<C: Component> InterestStrategy<C> for ExplicitInterest {
+    fn build(&self, schedule: Interned<dyn ScheduleLabel>, app: &mut App) {
+        app.add_systems(
+            schedule,
+            explicit_interest::<C>.in_set(InterestSystems::Compute),
+        );
+    }
+}
+
+/// Insert onto an entity to make it relevant to some connections
+#[derive(Component, Default)]
+pub struct VisibleTo {
+    connections: EntityHashSet,
+}
+
+impl VisibleTo {
+    pub fn new(connections: impl IntoIterator<Item = Entity>) -> Self {
+        VisibleTo {
+            connections: connections.into_iter().collect(),
+        }
+    }
+
+    /// makes the entity visible to a connection
+    ///
+    /// returns `false` if it was already visible
+    pub fn insert(&mut self, connection_entity: Entity) -> bool {
+        self.connections.insert(connection_entity)
+    }
+
+    /// hides the entity from a connection
+    ///
+    /// returns `false` if it wasn't visible
+    pub fn remove(&mut self, connection_entity: Entity) -> bool {
+        self.connections.remove(&connection_entity)
+    }
+
+    pub fn contains(&self, connection_entity: Entity) -> bool {
+        self.connections.contains(&connection_entity)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.connections.iter().copied()
+    }
+}
+
+fn explicit_interest<C: Component>(
+    mut connection_q: Query<(&mut ConnectionInterest, &Parent)>,
+    endpoint_q: Query<(), With<C>>,
+    visible_q: Query<(Entity, &VisibleTo)>,
+) {
+    for (entity, visible_to) in visible_q.iter() {
+        for connection_entity in visible_to.iter() {
+            let Ok((mut interest, connection_parent)) = connection_q.get_mut(connection_entity)
+            else {
+                continue;
+            };
+
+            if !endpoint_q.contains(connection_parent.get()) {
+                continue;
+            }
+
+            interest.insert(entity);
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! decides which entities are relevant to each connection
+//!
+//! every update the [ConnectionInterest] of each connection is cleared
+//! and filled by the [InterestStrategy]s of the [InterestPlugin].
+//! an entity is relevant to a connection if any strategy marks it as relevant.
+//! the replication layer only replicates relevant entities,
+//! and [SendRelevant] sends messages about an entity to the connections it's relevant to
+
+use std::marker::PhantomData;
+
+use bevy::{
+    ecs::{entity::EntityHashSet, intern::Interned, schedule::ScheduleLabel, system::SystemParam},
+    prelude::*,
+};
+use bevy_interface::prelude::*;
+use nevy_messaging::serialize::{QueueMessageError, SendMessages};
+use serde::Serialize;
+
+pub mod explicit;
+pub mod room;
+pub mod spatial;
+
+#[cfg(test)]
+mod tests;
+
+pub mod prelude {
+    pub use crate::explicit::{ExplicitInterest, VisibleTo};
+    pub use crate::room::{RoomInterest, Rooms};
+    pub use crate::spatial::{SpatialGrid, SpatialInterest, SpatialViewer};
+
+    pub use crate::{
+        AlwaysRelevant, ConnectionInterest, InterestPlugin, InterestStrategy, InterestSystems,
+        SendRelevant,
+    };
+}
+
+/// keeps the [ConnectionInterest] of connections of endpoints with the marker `C` up to date
+pub struct InterestPlugin<C> {
+    _p: PhantomData<C>,
+    schedule: Interned<dyn ScheduleLabel>,
+    strategies: Vec<Box<dyn InterestStrategy<C>>>,
+}
+
+/// decides which entities are relevant to the connections of endpoints with the marker `C`
+///
+/// strategies add systems to [InterestSystems::Compute] that insert relevant entities
+/// into the [ConnectionInterest] of each of those connections,
+/// leaving the connections of other endpoints alone
+pub trait InterestStrategy<C>: Send + Sync + 'static {
+    fn build(&self, schedule: Interned<dyn ScheduleLabel>, app: &mut App);
+}
+
+impl<C> InterestPlugin<C> {
+    pub fn new(schedule: impl ScheduleLabel) -> Self {
+        InterestPlugin {
+            _p: PhantomData,
+            schedule: schedule.intern(),
+            strategies: Vec::new(),
+        }
+    }
+
+    /// adds a strategy that marks entities as relevant
+    pub fn add_strategy(&mut self, strategy: impl InterestStrategy<C>) -> &mut Self {
+        self.strategies.push(Box::new(strategy));
+        self
+    }
+}
+
+impl<C: Component> Plugin for InterestPlugin<C> {
+    fn build(&self, app: &mut App) {
+        app.configure_sets(
+            self.schedule,
+            (InterestSystems::Clear, InterestSystems::Compute).chain(),
+        );
+
+        app.add_systems(
+            self.schedule,
+            (
+                insert_connection_interest::<C>.before(InterestSystems::Clear),
+                clear_interest::<C>.in_set(InterestSystems::Clear),
+                always_relevant::<C>.in_set(InterestSystems::Compute),
+            ),
+        );
+
+        for strategy in self.strategies.iter() {
+            strategy.build(self.schedule, app);
+        }
+    }
+}
+
+/// system sets where the interest of connections is computed
+#[derive(Clone, PartialEq, Eq, Debug, std::hash::Hash, SystemSet)]
+pub enum InterestSystems {
+    /// every [ConnectionInterest] is cleared
+    Clear,
+    /// strategies insert relevant entities into each [ConnectionInterest]
+    Compute,
+}
+
+/// the entities that are relevant to a connection
+///
+/// inserted onto connections by the [InterestPlugin] and recomputed every update
+#[derive(Component, Default)]
+pub struct ConnectionInterest {
+    entities: EntityHashSet,
+}
+
+impl ConnectionInterest {
+    /// marks an entity as relevant
+    pub fn insert(&mut self, entity: Entity) {
+        self.entities.insert(entity);
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entities.contains(&entity)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.entities.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+}
+
+/// Insert onto an entity to make it relevant to every connection
+#[derive(Component)]
+pub struct AlwaysRelevant;
+
+fn insert_connection_interest<C: Component>(
+    mut commands: Commands,
+    mut connected_r: EventReader<Connected>,
+    endpoint_q: Query<(), With<C>>,
+) {
+    for &Connected {
+        endpoint_entity,
+        connection_entity,
+    } in connected_r.read()
+    {
+        if !endpoint_q.contains(endpoint_entity) {
+            continue;
+        }
+
+        // the connection may have been closed before this system ran
+        let Some(mut connection_commands) = commands.get_entity(connection_entity) else {
+            continue;
+        };
+
+        connection_commands.try_insert(ConnectionInterest::default());
+    }
+}
+
+fn clear_interest<C: Component>(
+    mut connection_q: Query<(&mut ConnectionInterest, &Parent)>,
+    endpoint_q: Query<(), With<C>>,
+) {
+    for (mut interest, connection_parent) in connection_q.iter_mut() {
+        if !endpoint_q.contains(connection_parent.get()) {
+            continue;
+        }
+
+        interest.entities.clear();
+    }
+}
+
+fn always_relevant<C: Component>(
+    mut connection_q: Query<(&mut ConnectionInterest, &Parent)>,
+    endpoint_q: Query<(), With<C>>,
+    relevant_q: Query<Entity, With<AlwaysRelevant>>,
+) {
+    for (mut interest, connection_parent) in connection_q.iter_mut() {
+        if !endpoint_q.contains(connection_parent.get()) {
+            continue;
+        }
+
+        interest.entities.extend(relevant_q.iter());
+    }
+}
+
+/// sends messages about an entity to the connections it's relevant to
+#[derive(SystemParam)]
+pub struct SendRelevant<'w, 's, C: Component> {
+    messages: SendMessages<'w, 's, C>,
+    connection_q: Query<'w, 's, (Entity, &'static ConnectionInterest, &'static Parent)>,
+}
+
+impl<'w, 's, C: Component> SendRelevant<'w, 's, C> {
+    /// serializes a message once and queues it for every connection of an endpoint that an entity is relevant to
+    ///
+    /// returns the number of connections the message was queued for,
+    /// see [SendMessages::multicast]
+    pub fn send<T: Serialize + Send + Sync + 'static>(
+        &mut self,
+        endpoint_entity: Entity,
+        entity: Entity,
+        message: &T,
+    ) -> Result<usize, QueueMessageError> {
+        let connections: Vec<Entity> = self
+            .connection_q
+            .iter()
+            .filter(|(_, interest, connection_parent)| {
+                connection_parent.get() == endpoint_entity && interest.contains(entity)
+            })
+            .map(|(connection_entity, _, _)| connection_entity)
+            .collect();
+
+        if connections.is_empty() {
+            return Ok(0);
+        }
+
+        self.messages.multicast(connections, message)
+    }
+}
@@ -0,0 +1,86 @@
+//! entities and connections that are grouped into rooms
+
+use std::collections::{HashMap, HashSet};
+
+use bevy::{
+    ecs::{intern::Interned, schedule::ScheduleLabel},
+    prelude::*,
+};
+
+use crate::{ConnectionInterest, InterestStrategy, InterestSystems};
+
+/// makes entities relevant to the connections that share one of their [Rooms]
+pub struct RoomInterest;
+
+impl<C: Component> InterestStrategy<C> for RoomInterest {
+    fn build(&self, schedule: Interned<dyn ScheduleLabel>, app: &mut App) {
+        app.add_systems(
+            schedule,
+            room_interest::<C>.in_set(InterestSystems::Compute),
+        );
+    }
+}
+
+/// the rooms that an entity or connection is in
+///
+/// insert onto connections and the entities that should be relevant to them
+#[derive(Component, Default)]
+pub struct Rooms {
+    rooms: HashSet<u64>,
+}
+
+impl Rooms {
+    pub fn new(rooms: impl IntoIterator<Item = u64>) -> Self {
+        Rooms {
+            rooms: rooms.into_iter().collect(),
+        }
+    }
+
+    /// joins a room, returning `false` if it was already in the room
+    pub fn join(&mut self, room: u64) -> bool {
+        self.rooms.insert(room)
+    }
+
+    /// leaves a room, returning `false` if it wasn't in the room
+    pub fn leave(&mut self, room: u64) -> bool {
+        self.rooms.remove(&room)
+    }
+
+    pub fn contains(&self, room: u64) -> bool {
+        self.rooms.contains(&room)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        self.rooms.iter().copied()
+    }
+}
+
+fn room_interest<C: Component>(
+    mut connection_q: Query<(&mut ConnectionInterest, &Rooms, &Parent)>,
+    endpoint_q: Query<(), With<C>>,
+    entity_q: Query<(Entity, &Rooms), Without<ConnectionInterest>>,
+) {
+    let mut members = HashMap::<u64, Vec<Entity>>::new();
+
+    for (entity, rooms) in entity_q.iter() {
+        for room in rooms.iter() {
+            members.entry(room).or_default().push(entity);
+        }
+    }
+
+    for (mut interest, rooms, connection_parent) in connection_q.iter_mut() {
+        if !endpoint_q.contains(connection_parent.get()) {
+            continue;
+        }
+
+        for room in rooms.iter() {
+            let Some(entities) = members.get(&room) else {
+                continue;
+            };
+
+            for &entity in entities {
+                interest.insert(entity);
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! entities that are relevant to connections with a viewer near them
+
+use std::{collections::HashMap, marker::PhantomData};
+
+use bevy::{
+    ecs::{intern::Interned, schedule::ScheduleLabel},
+    prelude::*,
+};
+
+use crate::{ConnectionInterest, InterestStrategy, InterestSystems};
+
+/// makes entities with [SpatialInterest] relevant to connections with a [SpatialViewer] near them
+///
+/// entities are sorted into a grid of cubic cells,
+/// and a viewer sees every cell within `view_distance` cells of its own.
+/// each [InterestPlugin](crate::InterestPlugin) can have it's own grid
+pub struct SpatialGrid {
+    pub cell_size: f32,
+    pub view_distance: u32,
+}
+
+impl<C: Component> InterestStrategy<C> for SpatialGrid {
+    fn build(&self, schedule: Interned<dyn ScheduleLabel>, app: &mut App) {
+        if self.cell_size <= 0. {
+            panic!("spatial grid cell size must be positive");
+        }
+
+        app.insert_resource(SpatialGridSettings::<C> {
+            _p: PhantomData,
+            cell_size: self.cell_size,
+            view_distance: self.view_distance.min(i32::MAX as u32) as i32,
+        });
+
+        app.add_systems(
+            schedule,
+            spatial_interest::<C>.in_set(InterestSystems::Compute),
+        );
+    }
+}
+
+/// Insert onto an entity with a [GlobalTransform] to make it relevant to nearby viewers
+#[derive(Component)]
+pub struct SpatialInterest;
+
+/// Insert onto an entity with a [GlobalTransform] to make the entities near it relevant to a connection
+#[derive(Component)]
+pub struct SpatialViewer {
+    pub connection_entity: Entity,
+}
+
+/// the grid of the [InterestPlugin](crate::InterestPlugin) with the marker `C`
+#[derive(Resource)]
+struct SpatialGridSettings<C> {
+    _p: PhantomData<C>,
+    cell_size: f32,
+    view_distance: i32,
+}
+
+impl<C> SpatialGridSettings<C> {
+    fn cell(&self, transform: &GlobalTransform) -> IVec3 {
+        (transform.translation() / self.cell_size)
+            .floor()
+            .as_ivec3()
+    }
+}
+
+fn spatial_interest<C: Component>(
+    settings: Res<SpatialGridSettings<C>>,
+    mut connection_q: Query<(&mut ConnectionInterest, &Parent)>,
+    endpoint_q: Query<(), With<C>>,
+    entity_q: Query<(Entity, &GlobalTransform), With<SpatialInterest>>,
+    viewer_q: Query<(&SpatialViewer, &GlobalTransform)>,
+) {
+    let mut cells = HashMap::<IVec3, Vec<Entity>>::new();
+
+    for (entity, transform) in entity_q.iter() {
+        cells
+            .entry(settings.cell(transform))
+            .or_default()
+            .push(entity);
+    }
+
+    let distance = settings.view_distance;
+
+    // large view distances check every occupied cell instead of every cell in view
+    let view_cells = (2 * distance as u64 + 1).saturating_pow(3);
+    let check_occupied = view_cells > cells.len() as u64;
+
+    for (viewer, transform) in viewer_q.iter() {
+        let Ok((mut interest, connection_parent)) = connection_q.get_mut(viewer.connection_entity)
+        else {
+            continue;
+        };
+
+        if !endpoint_q.contains(connection_parent.get()) {
+            continue;
+        }
+
+        let center = settings.cell(transform);
+
+        if check_occupied {
+            for (&cell, entities) in cells.iter() {
+                let offset = (cell.as_i64vec3() - center.as_i64vec3()).abs();
+
+                if offset.max_element() <= distance as i64 {
+                    for &entity in entities {
+                        interest.insert(entity);
+                    }
+                }
+            }
+
+            continue;
+        }
+
+        for x in -distance..=distance {
+            for y in -distance..=distance {
+                for z in -distance..=distance {
+                    let Some(entities) = cells.get(&(center + IVec3::new(x, y, z))) else {
+                        continue;
+                    };
+
+                    for &entity in entities {
+                        interest.insert(entity);
+                    }
+                }
+            }
+        }
+    }
+}
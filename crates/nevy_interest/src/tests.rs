@@ -0,0 +1,296 @@
+use bevy::prelude::*;
+use bevy_interface::prelude::*;
+
+use crate::prelude::*;
+
+#[derive(Component)]
+struct ServerEndpoint;
+
+#[derive(Component)]
+struct OtherEndpoint;
+
+fn interest_app(plugin: InterestPlugin<ServerEndpoint>) -> App {
+    let mut app = App::new();
+    app.add_event::<Connected>();
+    app.add_plugins(plugin);
+    app
+}
+
+fn spawn_connection(app: &mut App, endpoint_entity: Entity) -> Entity {
+    app.world_mut()
+        .spawn(ConnectionInterest::default())
+        .set_parent(endpoint_entity)
+        .id()
+}
+
+fn is_relevant(app: &App, connection_entity: Entity, entity: Entity) -> bool {
+    app.world()
+        .get::<ConnectionInterest>(connection_entity)
+        .unwrap()
+        .contains(entity)
+}
+
+#[test]
+fn connected_connections_get_interest() {
+    let mut app = interest_app(InterestPlugin::new(Update));
+    let endpoint_entity = app.world_mut().spawn(ServerEndpoint).id();
+    let other_endpoint_entity = app.world_mut().spawn(OtherEndpoint).id();
+    let connection_entity = app.world_mut().spawn_empty().id();
+    let other_connection_entity = app.world_mut().spawn_empty().id();
+
+    app.world_mut().send_event(Connected {
+        endpoint_entity,
+        connection_entity,
+    });
+    app.world_mut().send_event(Connected {
+        endpoint_entity: other_endpoint_entity,
+        connection_entity: other_connection_entity,
+    });
+    app.update();
+
+    assert!(app
+        .world()
+        .get::<ConnectionInterest>(connection_entity)
+        .is_some());
+    assert!(app
+        .world()
+        .get::<ConnectionInterest>(other_connection_entity)
+        .is_none());
+}
+
+#[test]
+fn always_relevant_only_affects_its_endpoint() {
+    let mut app = interest_app(InterestPlugin::new(Update));
+    let endpoint_entity = app.world_mut().spawn(ServerEndpoint).id();
+    let other_endpoint_entity = app.world_mut().spawn(OtherEndpoint).id();
+    let connection_entity = spawn_connection(&mut app, endpoint_entity);
+    let other_connection_entity = spawn_connection(&mut app, other_endpoint_entity);
+
+    let entity = app.world_mut().spawn(AlwaysRelevant).id();
+    app.update();
+
+    assert!(is_relevant(&app, connection_entity, entity));
+    assert!(!is_relevant(&app, other_connection_entity, entity));
+
+    app.world_mut()
+        .entity_mut(entity)
+        .remove::<AlwaysRelevant>();
+    app.update();
+
+    assert!(!is_relevant(&app, connection_entity, entity));
+}
+
+#[test]
+fn explicit_interest_uses_visible_to() {
+    let mut plugin = InterestPlugin::new(Update);
+    plugin.add_strategy(ExplicitInterest);
+    let mut app = interest_app(plugin);
+    let endpoint_entity = app.world_mut().spawn(ServerEndpoint).id();
+    let visible_connection = spawn_connection(&mut app, endpoint_entity);
+    let hidden_connection = spawn_connection(&mut app, endpoint_entity);
+
+    let entity = app
+        .world_mut()
+        .spawn(VisibleTo::new([visible_connection]))
+        .id();
+    app.update();
+
+    assert!(is_relevant(&app, visible_connection, entity));
+    assert!(!is_relevant(&app, hidden_connection, entity));
+}
+
+#[test]
+fn rooms_share_entities_with_their_members() {
+    let mut plugin = InterestPlugin::new(Update);
+    plugin.add_strategy(RoomInterest);
+    let mut app = interest_app(plugin);
+    let endpoint_entity = app.world_mut().spawn(ServerEndpoint).id();
+    let lobby_connection = spawn_connection(&mut app, endpoint_entity);
+    let game_connection = spawn_connection(&mut app, endpoint_entity);
+    app.world_mut()
+        .entity_mut(lobby_connection)
+        .insert(Rooms::new([0]));
+    app.world_mut()
+        .entity_mut(game_connection)
+        .insert(Rooms::new([1, 2]));
+
+    let lobby_entity = app.world_mut().spawn(Rooms::new([0])).id();
+    let game_entity = app.world_mut().spawn(Rooms::new([2, 3])).id();
+    let empty_entity = app.world_mut().spawn(Rooms::new([4])).id();
+    app.update();
+
+    assert!(is_relevant(&app, lobby_connection, lobby_entity));
+    assert!(!is_relevant(&app, lobby_connection, game_entity));
+    assert!(is_relevant(&app, game_connection, game_entity));
+    assert!(!is_relevant(&app, game_connection, lobby_entity));
+    assert!(!is_relevant(&app, game_connection, empty_entity));
+
+    // connections are members of rooms, not entities relevant to them
+    assert!(!is_relevant(&app, game_connection, lobby_connection));
+
+    app.world_mut()
+        .get_mut::<Rooms>(lobby_connection)
+        .unwrap()
+        .join(2);
+    app.update();
+
+    assert!(is_relevant(&app, lobby_connection, game_entity));
+}
+
+#[test]
+fn spatial_grid_shows_nearby_cells() {
+    let mut plugin = InterestPlugin::new(Update);
+    plugin.add_strategy(SpatialGrid {
+        cell_size: 10.,
+        view_distance: 1,
+    });
+    let mut app = interest_app(plugin);
+    let endpoint_entity = app.world_mut().spawn(ServerEndpoint).id();
+    let connection_entity = spawn_connection(&mut app, endpoint_entity);
+
+    let viewer = app
+        .world_mut()
+        .spawn((
+            SpatialViewer { connection_entity },
+            GlobalTransform::from_translation(Vec3::new(5., 5., 5.)),
+        ))
+        .id();
+
+    let mut spawn_at = |translation: Vec3| {
+        app.world_mut()
+            .spawn((
+                SpatialInterest,
+                GlobalTransform::from_translation(translation),
+            ))
+            .id()
+    };
+
+    let same_cell = spawn_at(Vec3::new(1., 9., 0.));
+    let neighbour_cell = spawn_at(Vec3::new(-5., 15., 19.));
+    let far_cell = spawn_at(Vec3::new(25., 5., 5.));
+    let negative_far_cell = spawn_at(Vec3::new(-10.5, 5., 5.));
+    app.update();
+
+    assert!(is_relevant(&app, connection_entity, same_cell));
+    assert!(is_relevant(&app, connection_entity, neighbour_cell));
+    assert!(!is_relevant(&app, connection_entity, far_cell));
+    assert!(!is_relevant(&app, connection_entity, negative_far_cell));
+
+    // moving the viewer changes what it sees
+    *app.world_mut().get_mut::<GlobalTransform>(viewer).unwrap() =
+        GlobalTransform::from_translation(Vec3::new(25., 5., 5.));
+    app.update();
+
+    assert!(is_relevant(&app, connection_entity, far_cell));
+    assert!(!is_relevant(&app, connection_entity, same_cell));
+}
+
+#[test]
+fn closed_connections_are_ignored() {
+    let mut app = interest_app(InterestPlugin::new(Update));
+    let endpoint_entity = app.world_mut().spawn(ServerEndpoint).id();
+    let connection_entity = app.world_mut().spawn_empty().id();
+
+    app.world_mut().despawn(connection_entity);
+    app.world_mut().send_event(Connected {
+        endpoint_entity,
+        connection_entity,
+    });
+    app.update();
+
+    assert!(app.world().get_entity(connection_entity).is_err());
+}
+
+#[test]
+fn strategies_only_affect_their_endpoint() {
+    let mut plugin = InterestPlugin::new(Update);
+    plugin.add_strategy(ExplicitInterest);
+    plugin.add_strategy(RoomInterest);
+    plugin.add_strategy(SpatialGrid {
+        cell_size: 10.,
+        view_distance: 0,
+    });
+    let mut app = interest_app(plugin);
+
+    let mut other_plugin = InterestPlugin::<OtherEndpoint>::new(Update);
+    other_plugin.add_strategy(SpatialGrid {
+        cell_size: 100.,
+        view_distance: 0,
+    });
+    app.add_plugins(other_plugin);
+
+    let endpoint_entity = app.world_mut().spawn(ServerEndpoint).id();
+    let other_endpoint_entity = app.world_mut().spawn(OtherEndpoint).id();
+    let connection_entity = spawn_connection(&mut app, endpoint_entity);
+    let other_connection_entity = spawn_connection(&mut app, other_endpoint_entity);
+
+    for connection_entity in [connection_entity, other_connection_entity] {
+        app.world_mut().spawn((
+            SpatialViewer { connection_entity },
+            GlobalTransform::from_translation(Vec3::new(5., 5., 5.)),
+        ));
+
+        app.world_mut()
+            .entity_mut(connection_entity)
+            .insert(Rooms::new([0]));
+    }
+
+    let spatial_entity = app
+        .world_mut()
+        .spawn((
+            SpatialInterest,
+            GlobalTransform::from_translation(Vec3::new(50., 5., 5.)),
+        ))
+        .id();
+    let room_entity = app.world_mut().spawn(Rooms::new([0])).id();
+    let visible_entity = app
+        .world_mut()
+        .spawn(VisibleTo::new([connection_entity, other_connection_entity]))
+        .id();
+    app.update();
+
+    // each endpoint uses it's own grid
+    assert!(!is_relevant(&app, connection_entity, spatial_entity));
+    assert!(is_relevant(&app, other_connection_entity, spatial_entity));
+
+    // the other endpoint doesn't have the room and explicit strategies
+    assert!(is_relevant(&app, connection_entity, room_entity));
+    assert!(!is_relevant(&app, other_connection_entity, room_entity));
+    assert!(is_relevant(&app, connection_entity, visible_entity));
+    assert!(!is_relevant(&app, other_connection_entity, visible_entity));
+}
+
+#[test]
+fn spatial_grid_with_a_large_view_distance() {
+    let mut plugin = InterestPlugin::new(Update);
+    plugin.add_strategy(SpatialGrid {
+        cell_size: 1.,
+        view_distance: 1_000_000,
+    });
+    let mut app = interest_app(plugin);
+    let endpoint_entity = app.world_mut().spawn(ServerEndpoint).id();
+    let connection_entity = spawn_connection(&mut app, endpoint_entity);
+
+    app.world_mut().spawn((
+        SpatialViewer { connection_entity },
+        GlobalTransform::from_translation(Vec3::new(0.5, 0.5, 0.5)),
+    ));
+
+    let mut spawn_at = |translation: Vec3| {
+        app.world_mut()
+            .spawn((
+                SpatialInterest,
+                GlobalTransform::from_translation(translation),
+            ))
+            .id()
+    };
+
+    let near = spawn_at(Vec3::new(3., -4., 5.));
+    let edge = spawn_at(Vec3::new(-999_999.5, 0., 1_000_000.5));
+    let far = spawn_at(Vec3::new(0., 1_000_002., 0.));
+    app.update();
+
+    assert!(is_relevant(&app, connection_entity, near));
+    assert!(is_relevant(&app, connection_entity, edge));
+    assert!(!is_relevant(&app, connection_entity, far));
+}
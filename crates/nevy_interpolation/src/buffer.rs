@@ -0,0 +1,211 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::prelude::*;
+
+/// the default number of snapshots a [SnapshotBuffer] holds
+pub const DEFAULT_SNAPSHOT_CAPACITY: usize = 32;
+
+/// the received states of a component and the server time they were taken at
+///
+/// inserted onto entities when their first [Snapshot](crate::Snapshot) is received
+#[derive(Component)]
+pub struct SnapshotBuffer<T> {
+    /// snapshots ordered by time
+    snapshots: VecDeque<(Duration, T)>,
+    capacity: usize,
+    /// the time of the last snapshot that was interpolated past
+    consumed: Option<Duration>,
+    late: u64,
+    starved: bool,
+}
+
+impl<T> SnapshotBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        SnapshotBuffer {
+            snapshots: VecDeque::new(),
+            capacity: capacity.max(2),
+            consumed: None,
+            late: 0,
+            starved: false,
+        }
+    }
+
+    /// adds a snapshot taken at a server time
+    ///
+    /// snapshots that arrive after they would have been displayed are dropped,
+    /// returns `false` if the snapshot was dropped
+    pub fn push(&mut self, time: Duration, state: T) -> bool {
+        if self.consumed.is_some_and(|consumed| time <= consumed) {
+            self.late += 1;
+            return false;
+        }
+
+        // snapshots usually arrive in order
+        let index = self
+            .snapshots
+            .iter()
+            .rposition(|&(snapshot_time, _)| snapshot_time <= time)
+            .map_or(0, |index| index + 1);
+
+        if index > 0 && self.snapshots[index - 1].0 == time {
+            self.snapshots[index - 1].1 = state;
+            return true;
+        }
+
+        self.snapshots.insert(index, (time, state));
+
+        while self.snapshots.len() > self.capacity {
+            if let Some((time, _)) = self.snapshots.pop_front() {
+                self.consumed = Some(time);
+            }
+        }
+
+        true
+    }
+
+    /// the number of buffered snapshots
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// the time of the newest snapshot
+    pub fn latest_time(&self) -> Option<Duration> {
+        self.snapshots.back().map(|&(time, _)| time)
+    }
+
+    /// the number of snapshots that were dropped because they arrived too late
+    pub fn late_snapshots(&self) -> u64 {
+        self.late
+    }
+
+    /// `true` if the last interpolation was past the newest snapshot,
+    /// which happens when snapshots are lost or delayed for longer than the interpolation delay
+    pub fn is_starved(&self) -> bool {
+        self.starved
+    }
+
+    /// samples the buffer at a server time
+    ///
+    /// snapshots that are no longer needed are removed.
+    /// times before the first snapshot give the first snapshot
+    /// and times after the last snapshot hold the last snapshot
+    pub(crate) fn sample(&mut self, time: Duration, lerp: fn(&T, &T, f32) -> T) -> Option<T>
+    where
+        T: Clone,
+    {
+        // keep the last snapshot at or before the time
+        while self.snapshots.len() >= 2 && self.snapshots[1].0 <= time {
+            if let Some((time, _)) = self.snapshots.pop_front() {
+                self.consumed = Some(time);
+            }
+        }
+
+        let (from_time, from) = self.snapshots.front()?;
+
+        if time <= *from_time {
+            self.starved = false;
+            return Some(from.clone());
+        }
+
+        let Some((to_time, to)) = self.snapshots.get(1) else {
+            self.starved = true;
+            return Some(from.clone());
+        };
+
+        self.starved = false;
+
+        let t = (time - *from_time).as_secs_f64() / (*to_time - *from_time).as_secs_f64();
+        Some(lerp(from, to, t as f32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lerp(from: &f32, to: &f32, t: f32) -> f32 {
+        from + (to - from) * t
+    }
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn samples_interpolate_between_snapshots() {
+        let mut buffer = SnapshotBuffer::new(DEFAULT_SNAPSHOT_CAPACITY);
+        buffer.push(millis(100), 0.);
+        buffer.push(millis(200), 10.);
+
+        assert_eq!(buffer.sample(millis(50), lerp), Some(0.));
+        assert_eq!(buffer.sample(millis(150), lerp), Some(5.));
+        assert!(!buffer.is_starved());
+    }
+
+    #[test]
+    fn out_of_order_snapshots_are_sorted() {
+        let mut buffer = SnapshotBuffer::new(DEFAULT_SNAPSHOT_CAPACITY);
+        buffer.push(millis(200), 10.);
+        buffer.push(millis(100), 0.);
+
+        assert_eq!(buffer.latest_time(), Some(millis(200)));
+        assert_eq!(buffer.sample(millis(150), lerp), Some(5.));
+    }
+
+    #[test]
+    fn duplicate_snapshots_replace_the_state() {
+        let mut buffer = SnapshotBuffer::new(DEFAULT_SNAPSHOT_CAPACITY);
+        buffer.push(millis(100), 0.);
+        assert!(buffer.push(millis(100), 4.));
+
+        assert_eq!(buffer.len(), 1);
+        assert_eq!(buffer.sample(millis(100), lerp), Some(4.));
+    }
+
+    #[test]
+    fn late_snapshots_are_dropped() {
+        let mut buffer = SnapshotBuffer::new(DEFAULT_SNAPSHOT_CAPACITY);
+        buffer.push(millis(100), 0.);
+        buffer.push(millis(200), 10.);
+        buffer.push(millis(300), 20.);
+
+        buffer.sample(millis(250), lerp);
+
+        assert!(!buffer.push(millis(50), 5.));
+        assert!(!buffer.push(millis(100), 0.));
+        assert_eq!(buffer.late_snapshots(), 2);
+        assert_eq!(buffer.len(), 2);
+    }
+
+    #[test]
+    fn snapshots_over_capacity_are_consumed() {
+        let mut buffer = SnapshotBuffer::new(2);
+        buffer.push(millis(100), 0.);
+        buffer.push(millis(200), 10.);
+        buffer.push(millis(300), 20.);
+
+        assert_eq!(buffer.len(), 2);
+        assert!(!buffer.push(millis(100), 0.));
+    }
+
+    #[test]
+    fn sampling_past_the_newest_snapshot_starves() {
+        let mut buffer = SnapshotBuffer::new(DEFAULT_SNAPSHOT_CAPACITY);
+        assert_eq!(buffer.sample(millis(100), lerp), None);
+
+        buffer.push(millis(100), 0.);
+        buffer.push(millis(200), 10.);
+
+        assert_eq!(buffer.sample(millis(300), lerp), Some(10.));
+        assert!(buffer.is_starved());
+
+        buffer.push(millis(400), 20.);
+
+        assert_eq!(buffer.sample(millis(300), lerp), Some(15.));
+        assert!(!buffer.is_starved());
+    }
+}
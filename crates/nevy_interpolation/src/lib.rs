@@ -0,0 +1,250 @@
+//! smooths the movement of remote entities by interpolating between received snapshots
+//!
+//! servers send the state of a component as a [Snapshot] stamped with the tick of their [NetworkClock].
+//! clients buffer snapshots in a [SnapshotBuffer] and display the state from a configurable delay in the past,
+//! interpolating between the snapshots on either side of that time
+
+use std::{marker::PhantomData, time::Duration};
+
+use bevy::{
+    ecs::{
+        entity::{EntityHashMap, EntityMapper, MapEntities},
+        intern::Interned,
+        schedule::ScheduleLabel,
+    },
+    prelude::*,
+};
+use nevy_messaging::{
+    clock::NetworkClock, codec::MessageCodec, deserialize::MessageDelivery,
+    deserialize::ReceivedMessages, ProtocolBuilder,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+pub mod buffer;
+
+use buffer::{SnapshotBuffer, DEFAULT_SNAPSHOT_CAPACITY};
+
+pub mod prelude {
+    pub use crate::buffer::{SnapshotBuffer, DEFAULT_SNAPSHOT_CAPACITY};
+
+    pub use crate::{
+        add_snapshot_message, InterpolationPlugin, Snapshot, DEFAULT_INTERPOLATION_DELAY,
+    };
+}
+
+/// the default time that interpolated entities are displayed behind the server
+pub const DEFAULT_INTERPOLATION_DELAY: Duration = Duration::from_millis(100);
+
+/// interpolates components from the snapshots received on connections of endpoints with the marker `C`
+///
+/// needs a [ClockSyncPlugin](nevy_messaging::clock::ClockSyncPlugin) for the same endpoints,
+/// and the snapshot messages of each component need to be added with [add_snapshot_message]
+pub struct InterpolationPlugin<C> {
+    _p: PhantomData<C>,
+    schedule: Interned<dyn ScheduleLabel>,
+    delay: Duration,
+    capacity: usize,
+    components: Vec<Box<dyn InterpolatedBuilder<C>>>,
+}
+
+trait InterpolatedBuilder<C>: Send + Sync + 'static {
+    fn build(&self, schedule: Interned<dyn ScheduleLabel>, capacity: usize, app: &mut App);
+}
+
+struct InterpolatedBuilderType<T> {
+    lerp: fn(&T, &T, f32) -> T,
+}
+
+impl<C> InterpolationPlugin<C> {
+    pub fn new(schedule: impl ScheduleLabel) -> Self {
+        InterpolationPlugin {
+            _p: PhantomData,
+            schedule: schedule.intern(),
+            delay: DEFAULT_INTERPOLATION_DELAY,
+            capacity: DEFAULT_SNAPSHOT_CAPACITY,
+            components: Vec::new(),
+        }
+    }
+
+    /// sets how far behind the server's clock entities are displayed
+    ///
+    /// a longer delay hides more lost and late snapshots.
+    /// defaults to [DEFAULT_INTERPOLATION_DELAY]
+    pub fn set_delay(&mut self, delay: Duration) -> &mut Self {
+        self.delay = delay;
+        self
+    }
+
+    /// sets the number of snapshots each [SnapshotBuffer] holds
+    ///
+    /// defaults to [DEFAULT_SNAPSHOT_CAPACITY]
+    pub fn set_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.capacity = capacity;
+        self
+    }
+}
+
+impl<C: Component> InterpolationPlugin<C> {
+    /// adds a component type that is interpolated with a lerp function
+    ///
+    /// the lerp function is given the snapshots on either side of the displayed time
+    /// and how far between them it is, from 0 to 1
+    pub fn interpolate<T: Component + Clone>(&mut self, lerp: fn(&T, &T, f32) -> T) -> &mut Self {
+        self.components
+            .push(Box::new(InterpolatedBuilderType { lerp }));
+        self
+    }
+}
+
+impl<C: Component> Plugin for InterpolationPlugin<C> {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InterpolationDelay::<C> {
+            _p: PhantomData,
+            delay: self.delay,
+        });
+
+        for builder in self.components.iter() {
+            builder.build(self.schedule, self.capacity, app);
+        }
+    }
+}
+
+impl<C: Component, T: Component + Clone> InterpolatedBuilder<C> for InterpolatedBuilderType<T> {
+    fn build(&self, schedule: Interned<dyn ScheduleLabel>, capacity: usize, app: &mut App) {
+        app.insert_resource(Interpolated::<C, T> {
+            _p: PhantomData,
+            lerp: self.lerp,
+            capacity,
+        });
+
+        app.add_systems(
+            schedule,
+            (receive_snapshots::<C, T>, interpolate_components::<C, T>).chain(),
+        );
+    }
+}
+
+/// adds the snapshot message for a component type to a protocol
+///
+/// the entity in each snapshot is mapped with the connection's
+/// [ConnectionEntityMap](nevy_messaging::entity_map::ConnectionEntityMap),
+/// and snapshots are always received as [ReceivedMessages] components
+pub fn add_snapshot_message<C, M, T>(protocol: &mut ProtocolBuilder<C, M>)
+where
+    C: Component,
+    M: MessageCodec,
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    protocol.add_message::<Snapshot<T>>();
+    protocol.set_message_delivery::<Snapshot<T>>(MessageDelivery::Component);
+    protocol.map_message_entities::<Snapshot<T>>();
+}
+
+/// the state of a component on an entity at a server tick
+///
+/// send this from the server with [SendMessages](nevy_messaging::serialize::SendMessages),
+/// usually for the current tick of it's [NetworkClock]
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot<T> {
+    pub entity: Entity,
+    pub tick: u32,
+    pub state: T,
+}
+
+impl<T> MapEntities for Snapshot<T> {
+    fn map_entities<E: EntityMapper>(&mut self, entity_mapper: &mut E) {
+        self.entity = entity_mapper.map_entity(self.entity);
+    }
+}
+
+/// Contains how far behind the server interpolated entities are displayed
+#[derive(Resource)]
+struct InterpolationDelay<C> {
+    _p: PhantomData<C>,
+    delay: Duration,
+}
+
+/// Contains the lerp function of an interpolated component
+#[derive(Resource)]
+struct Interpolated<C, T> {
+    _p: PhantomData<C>,
+    lerp: fn(&T, &T, f32) -> T,
+    capacity: usize,
+}
+
+fn receive_snapshots<C: Component, T: Component + Clone>(
+    mut commands: Commands,
+    interpolated: Res<Interpolated<C, T>>,
+    clock: Res<NetworkClock<C>>,
+    mut connection_q: Query<(&mut ReceivedMessages<Snapshot<T>>, &Parent)>,
+    endpoint_q: Query<(), With<C>>,
+    mut buffer_q: Query<&mut SnapshotBuffer<T>>,
+) {
+    // buffers for entities that don't have one yet
+    let mut new_buffers = EntityHashMap::<SnapshotBuffer<T>>::default();
+
+    for (mut snapshots, connection_parent) in connection_q.iter_mut() {
+        if !endpoint_q.contains(connection_parent.get()) {
+            continue;
+        }
+
+        for Snapshot {
+            entity,
+            tick,
+            state,
+        } in snapshots.drain()
+        {
+            // the entity hasn't been mapped yet
+            if entity == Entity::PLACEHOLDER {
+                continue;
+            }
+
+            let time = clock.tick_time(tick);
+
+            if let Ok(mut buffer) = buffer_q.get_mut(entity) {
+                buffer.push(time, state);
+                continue;
+            }
+
+            new_buffers
+                .entry(entity)
+                .or_insert_with(|| SnapshotBuffer::new(interpolated.capacity))
+                .push(time, state);
+        }
+    }
+
+    for (entity, buffer) in new_buffers {
+        let Some(mut entity_commands) = commands.get_entity(entity) else {
+            continue;
+        };
+
+        entity_commands.insert(buffer);
+    }
+}
+
+fn interpolate_components<C: Component, T: Component + Clone>(
+    interpolated: Res<Interpolated<C, T>>,
+    delay: Res<InterpolationDelay<C>>,
+    clock: Res<NetworkClock<C>>,
+    mut commands: Commands,
+    mut buffer_q: Query<(Entity, Option<&mut T>, &mut SnapshotBuffer<T>)>,
+) {
+    if !clock.is_synced() {
+        return;
+    }
+
+    let time = clock.time().saturating_sub(delay.delay);
+
+    for (entity, value, mut buffer) in buffer_q.iter_mut() {
+        let Some(state) = buffer.sample(time, interpolated.lerp) else {
+            continue;
+        };
+
+        match value {
+            Some(mut value) => *value = state,
+            None => {
+                commands.entity(entity).insert(state);
+            }
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use transport_interface::*;
+
+use crate::{
+    endpoint::LoopbackConfig,
+    loopback_stream::{Frame, LoopbackDir, LoopbackStreamId, RecvState, SendState},
+    network::LoopbackAddress,
+};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct LoopbackConnectionId(pub(crate) u64);
+
+/// which side of a connection an endpoint is
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum Side {
+    /// the endpoint that initiated the connection
+    Client,
+    /// the endpoint that accepted the connection
+    Server,
+}
+
+#[derive(Debug)]
+pub(crate) enum ConnectionState {
+    /// waiting for the peer to accept the connection
+    Connecting,
+    Connected {
+        /// the id of the connection on the peer's endpoint
+        peer_connection: LoopbackConnectionId,
+    },
+}
+
+pub struct LoopbackConnection {
+    pub(crate) state: ConnectionState,
+    /// set when the connection is closed locally or by the peer, it will be removed on the next update
+    pub(crate) closed: Option<DisconnectReason>,
+    pub(crate) side: Side,
+    pub(crate) peer_address: LoopbackAddress,
+    pub(crate) config: LoopbackConfig,
+    pub(crate) next_stream_index: u64,
+    pub(crate) send_streams: HashMap<LoopbackStreamId, SendState>,
+    pub(crate) recv_streams: HashMap<LoopbackStreamId, RecvState>,
+    pub(crate) stream_events: VecDeque<StreamEvent<LoopbackStreamId>>,
+    /// frames waiting to be sent to the peer on the next update
+    pub(crate) outgoing: VecDeque<Frame>,
+    datagrams: VecDeque<Box<[u8]>>,
+    pub(crate) stats: LoopbackConnectionStats,
+}
+
+/// statistics of a [LoopbackConnection], returned by it's `get_stats`
+#[derive(Debug, Clone, Copy)]
+pub struct LoopbackConnectionStats {
+    pub remote_address: LoopbackAddress,
+    /// always zero, data is exchanged within a single update
+    pub rtt: Duration,
+    /// the number of stream and datagram bytes sent
+    pub bytes_sent: u64,
+    /// the number of stream and datagram bytes received
+    pub bytes_received: u64,
+    /// the number of frames sent
+    pub packets_sent: u64,
+    /// the number of frames received
+    pub packets_received: u64,
+}
+
+impl LoopbackConnection {
+    pub(crate) fn new(
+        side: Side,
+        peer_address: LoopbackAddress,
+        config: LoopbackConfig,
+        state: ConnectionState,
+    ) -> Self {
+        LoopbackConnection {
+            state,
+            closed: None,
+            side,
+            peer_address,
+            config,
+            next_stream_index: 0,
+            send_streams: HashMap::new(),
+            recv_streams: HashMap::new(),
+            stream_events: VecDeque::new(),
+            outgoing: VecDeque::new(),
+            datagrams: VecDeque::new(),
+            stats: LoopbackConnectionStats {
+                remote_address: peer_address,
+                rtt: Duration::ZERO,
+                bytes_sent: 0,
+                bytes_received: 0,
+                packets_sent: 0,
+                packets_received: 0,
+            },
+        }
+    }
+
+    /// processes a frame received from the peer
+    pub(crate) fn process_frame(&mut self, frame: Frame) {
+        self.stats.packets_received += 1;
+        self.stats.bytes_received += frame.data_len() as u64;
+
+        match frame {
+            Frame::Accepted { .. } => unreachable!("handled by the endpoint"),
+            Frame::Close { code, reason } => {
+                self.close(DisconnectReason::application_closed(code, reason));
+                self.outgoing.clear();
+            }
+            Frame::StreamOpened { stream_id, dir } => {
+                self.recv_streams.insert(stream_id, RecvState::new());
+                self.stream_events.push_back(StreamEvent {
+                    stream_id,
+                    peer_generated: true,
+                    event_type: StreamEventType::NewRecvStream,
+                });
+
+                if let LoopbackDir::Bi = dir {
+                    self.send_streams.insert(stream_id, SendState::new());
+                    self.stream_events.push_back(StreamEvent {
+                        stream_id,
+                        peer_generated: true,
+                        event_type: StreamEventType::NewSendStream,
+                    });
+                }
+            }
+            Frame::StreamData { stream_id, data } => {
+                // the stream may have been stopped locally
+                if let Some(stream) = self.recv_streams.get_mut(&stream_id) {
+                    stream.receive(&data);
+                }
+            }
+            Frame::StreamFinished { stream_id } => {
+                if let Some(stream) = self.recv_streams.get_mut(&stream_id) {
+                    stream.finish();
+                }
+            }
+            Frame::StreamReset { stream_id } => {
+                if self.recv_streams.remove(&stream_id).is_some() {
+                    self.stream_events.push_back(StreamEvent {
+                        stream_id,
+                        peer_generated: true,
+                        event_type: StreamEventType::ClosedRecvStream,
+                    });
+                }
+            }
+            Frame::StreamStopped { stream_id } => {
+                if self.send_streams.remove(&stream_id).is_some() {
+                    self.stream_events.push_back(StreamEvent {
+                        stream_id,
+                        peer_generated: true,
+                        event_type: StreamEventType::ClosedSendStream,
+                    });
+                }
+            }
+            Frame::StreamAcknowledged { stream_id, bytes } => {
+                // the stream may have been finished locally
+                if let Some(stream) = self.send_streams.get_mut(&stream_id) {
+                    stream.acknowledge(bytes);
+                }
+            }
+            Frame::Datagram(data) => {
+                self.datagrams.push_back(data);
+            }
+        }
+    }
+
+    /// marks the connection as closed if it isn't already
+    pub(crate) fn close(&mut self, reason: DisconnectReason) {
+        if self.closed.is_none() {
+            self.closed = Some(reason);
+        }
+    }
+
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    /// the address of the peer's endpoint
+    pub fn peer_address(&self) -> LoopbackAddress {
+        self.peer_address
+    }
+}
+
+impl<'c> ConnectionMut<'c> for &'c mut LoopbackConnection {
+    type NonMut<'b>
+        = &'b LoopbackConnection
+    where
+        Self: 'b;
+
+    type StreamType = LoopbackStreamId;
+
+    fn as_ref<'b>(&'b self) -> Self::NonMut<'b> {
+        self
+    }
+
+    fn disconnect_with_reason(&mut self, code: u64, reason: &[u8]) {
+        if self.closed.is_some() {
+            return;
+        }
+
+        self.closed = Some(DisconnectReason::locally_closed(Some(code)));
+        self.outgoing.push_back(Frame::Close {
+            code,
+            reason: reason.into(),
+        });
+    }
+
+    fn send_datagram(&mut self, data: &[u8]) -> Result<(), SendDatagramError> {
+        let Some(max_size) = self.max_datagram_size() else {
+            return Err(SendDatagramError::Unsupported);
+        };
+
+        if data.len() > max_size {
+            return Err(SendDatagramError::TooLarge);
+        }
+
+        self.outgoing.push_back(Frame::Datagram(data.into()));
+
+        Ok(())
+    }
+
+    fn recv_datagram(&mut self) -> Option<Box<[u8]>> {
+        self.datagrams.pop_front()
+    }
+
+    fn peek_datagram(&mut self) -> Option<&[u8]> {
+        self.datagrams.front().map(AsRef::as_ref)
+    }
+
+    fn max_datagram_size(&mut self) -> Option<usize> {
+        match self.state {
+            ConnectionState::Connected { .. } if self.closed.is_none() => {
+                Some(self.config.max_datagram_size)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<'c> ConnectionRef<'c> for &'c LoopbackConnection {
+    type ConnectionStats = LoopbackConnectionStats;
+
+    fn get_stats(&self) -> LoopbackConnectionStats {
+        self.stats
+    }
+}
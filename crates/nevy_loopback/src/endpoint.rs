@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+
+use log::*;
+use transport_interface::*;
+
+use crate::{
+    connection::*,
+    loopback_stream::Frame,
+    network::{LoopbackAddress, LoopbackNetwork, Packet},
+};
+
+/// configuration for a [LoopbackEndpoint] and it's connections
+#[derive(Clone, Debug)]
+pub struct LoopbackConfig {
+    /// the maximum number of bytes that can be sent on a stream before the peer reads them
+    ///
+    /// sending more than this will return a blocked error
+    pub stream_window: usize,
+    /// the maximum size of a datagram
+    pub max_datagram_size: usize,
+}
+
+impl Default for LoopbackConfig {
+    fn default() -> Self {
+        LoopbackConfig {
+            stream_window: 1024 * 1024,
+            max_datagram_size: 1200,
+        }
+    }
+}
+
+/// A transport endpoint that exchanges data with other endpoints on the same [LoopbackNetwork] through in memory queues.
+///
+/// Intended for tests and examples that shouldn't bind sockets or load certificates.
+/// Data is only exchanged when endpoints are updated, so the order of events is deterministic.
+pub struct LoopbackEndpoint {
+    network: LoopbackNetwork,
+    local_addr: LoopbackAddress,
+    config: LoopbackConfig,
+    connections: HashMap<LoopbackConnectionId, LoopbackConnection>,
+    next_connection_id: u64,
+    /// packets that aren't associated with an established connection, sent on the next update
+    outgoing: Vec<(LoopbackAddress, Packet)>,
+}
+
+impl LoopbackEndpoint {
+    /// Creates a new endpoint on a network, which is assigned a new unique address.
+    ///
+    /// Incoming connections are accepted depending on the [EndpointEventHandler].
+    pub fn new(network: &LoopbackNetwork, config: Option<LoopbackConfig>) -> Self {
+        LoopbackEndpoint {
+            network: network.clone(),
+            local_addr: network.bind(),
+            config: config.unwrap_or_default(),
+            connections: HashMap::new(),
+            next_connection_id: 0,
+            outgoing: Vec::new(),
+        }
+    }
+
+    /// the address other endpoints can connect to
+    pub fn local_addr(&self) -> LoopbackAddress {
+        self.local_addr
+    }
+
+    fn new_connection_id(&mut self) -> LoopbackConnectionId {
+        let connection_id = LoopbackConnectionId(self.next_connection_id);
+        self.next_connection_id += 1;
+        connection_id
+    }
+
+    // Process a single packet received from the network.
+    fn process_packet(&mut self, packet: Packet, handler: &mut impl EndpointEventHandler<Self>) {
+        match packet {
+            Packet::Connect { source, connection } => {
+                if !handler.connection_request(source) {
+                    self.outgoing.push((source, Packet::Refused { connection }));
+                    return;
+                }
+
+                let connection_id = self.new_connection_id();
+
+                let mut loopback_connection = LoopbackConnection::new(
+                    Side::Server,
+                    source,
+                    self.config.clone(),
+                    ConnectionState::Connected {
+                        peer_connection: connection,
+                    },
+                );
+                loopback_connection.outgoing.push_back(Frame::Accepted {
+                    peer_connection: connection_id,
+                });
+
+                self.connections.insert(connection_id, loopback_connection);
+
+                handler.connected(connection_id);
+            }
+            Packet::Refused { connection } => {
+                if let Some(connection) = self.connections.get_mut(&connection) {
+                    connection.close(DisconnectReason::Refused);
+                }
+            }
+            Packet::Connection {
+                source,
+                connection: connection_id,
+                frame,
+            } => {
+                let Some(connection) = self.connections.get_mut(&connection_id) else {
+                    // the connection was closed while connecting, so tell the peer
+                    if let Frame::Accepted { peer_connection } = frame {
+                        self.network.deliver(
+                            source,
+                            Packet::Connection {
+                                source: self.local_addr,
+                                connection: peer_connection,
+                                frame: Frame::Close {
+                                    code: 0,
+                                    reason: Box::new([]),
+                                },
+                            },
+                        );
+                    }
+
+                    return;
+                };
+
+                if let Frame::Accepted { peer_connection } = frame {
+                    connection.state = ConnectionState::Connected { peer_connection };
+
+                    // a connection closed while connecting only needs to send it's close frame
+                    if connection.closed.is_none() {
+                        handler.connected(connection_id);
+                    }
+
+                    return;
+                }
+
+                connection.process_frame(frame);
+            }
+        }
+    }
+
+    // Send packets that aren't associated with an established connection.
+    fn send_packets(&mut self) {
+        for (destination, packet) in std::mem::take(&mut self.outgoing) {
+            let connecting = match packet {
+                Packet::Connect { connection, .. } => Some(connection),
+                _ => None,
+            };
+
+            if self.network.deliver(destination, packet) {
+                continue;
+            }
+
+            debug!(
+                "{:?} tried to send a packet to {:?} but no endpoint exists there",
+                self.local_addr, destination
+            );
+
+            if let Some(connection) = connecting.and_then(|id| self.connections.get_mut(&id)) {
+                connection.close(DisconnectReason::TimedOut);
+            }
+        }
+    }
+
+    // Send queued frames for all connections and remove closed connections.
+    fn update_connections(&mut self, handler: &mut impl EndpointEventHandler<Self>) {
+        let network = &self.network;
+        let local_addr = self.local_addr;
+
+        self.connections.retain(|&connection_id, connection| {
+            if let ConnectionState::Connected { peer_connection } = connection.state {
+                while let Some(frame) = connection.outgoing.pop_front() {
+                    connection.stats.packets_sent += 1;
+                    connection.stats.bytes_sent += frame.data_len() as u64;
+
+                    let delivered = network.deliver(
+                        connection.peer_address,
+                        Packet::Connection {
+                            source: local_addr,
+                            connection: peer_connection,
+                            frame,
+                        },
+                    );
+
+                    if !delivered {
+                        debug!(
+                            "{:?}'s peer {:?} no longer exists",
+                            connection_id, connection.peer_address
+                        );
+                        connection.close(DisconnectReason::TimedOut);
+                        break;
+                    }
+                }
+            }
+
+            let Some(reason) = connection.closed.take() else {
+                return true;
+            };
+
+            handler.disconnected(connection_id, reason);
+            false
+        });
+    }
+}
+
+impl Endpoint for LoopbackEndpoint {
+    type Connection<'a> = &'a mut LoopbackConnection;
+
+    type ConnectionId = LoopbackConnectionId;
+
+    type ConnectDescription = LoopbackAddress;
+
+    type IncomingConnectionInfo<'a> = LoopbackAddress;
+
+    type RemoteAddress = LoopbackAddress;
+
+    // Processes received packets and sends queued data.
+    fn update(&mut self, handler: &mut impl EndpointEventHandler<Self>) {
+        for packet in self.network.take_packets(self.local_addr) {
+            self.process_packet(packet, handler);
+        }
+
+        self.send_packets();
+        self.update_connections(handler);
+    }
+
+    fn remote_address(info: &LoopbackAddress) -> LoopbackAddress {
+        *info
+    }
+
+    fn connection<'c>(
+        &'c self,
+        id: Self::ConnectionId,
+    ) -> Option<<Self::Connection<'c> as ConnectionMut<'c>>::NonMut<'c>> {
+        self.connections.get(&id)
+    }
+
+    fn connection_mut<'c>(&'c mut self, id: Self::ConnectionId) -> Option<Self::Connection<'c>> {
+        self.connections.get_mut(&id)
+    }
+
+    /// Connect to the endpoint at an address.
+    ///
+    /// If no endpoint exists at that address the connection will fail on the next update.
+    fn connect<'c>(
+        &'c mut self,
+        address: Self::ConnectDescription,
+    ) -> Option<(Self::ConnectionId, Self::Connection<'c>)> {
+        let connection_id = self.new_connection_id();
+
+        self.outgoing.push((
+            address,
+            Packet::Connect {
+                source: self.local_addr,
+                connection: connection_id,
+            },
+        ));
+
+        let connection = self
+            .connections
+            .entry(connection_id)
+            .or_insert(LoopbackConnection::new(
+                Side::Client,
+                address,
+                self.config.clone(),
+                ConnectionState::Connecting,
+            ));
+
+        Some((connection_id, connection))
+    }
+}
+
+impl Drop for LoopbackEndpoint {
+    fn drop(&mut self) {
+        self.network.unbind(self.local_addr);
+    }
+}
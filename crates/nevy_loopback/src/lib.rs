@@ -0,0 +1,14 @@
+pub mod connection;
+pub mod endpoint;
+pub mod loopback_stream;
+pub mod network;
+
+pub mod prelude {
+    pub use crate::connection::*;
+    pub use crate::endpoint::*;
+    pub use crate::loopback_stream::*;
+    pub use crate::network::{LoopbackAddress, LoopbackNetwork};
+}
+
+#[cfg(test)]
+mod tests;
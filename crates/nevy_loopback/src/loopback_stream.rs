@@ -0,0 +1,371 @@
+use std::collections::{HashMap, VecDeque};
+
+use transport_interface::*;
+
+use crate::connection::{ConnectionState, LoopbackConnection, LoopbackConnectionId, Side};
+
+/// stream id for a loopback stream
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct LoopbackStreamId {
+    /// the side of the connection that opened the stream
+    initiator: Side,
+    index: u64,
+}
+
+/// the directionality of a stream, used when opening a [LoopbackStreamId]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum LoopbackDir {
+    /// data can only be sent by the side that opened the stream
+    Uni,
+    /// data can be sent by both sides
+    Bi,
+}
+
+/// data sent on a connection
+pub(crate) enum Frame {
+    /// the connection request was accepted by the peer
+    Accepted {
+        /// the id of the connection on the endpoint that accepted it
+        peer_connection: LoopbackConnectionId,
+    },
+    /// the connection was closed by the peer
+    Close {
+        code: u64,
+        reason: Box<[u8]>,
+    },
+    StreamOpened {
+        stream_id: LoopbackStreamId,
+        dir: LoopbackDir,
+    },
+    StreamData {
+        stream_id: LoopbackStreamId,
+        data: Box<[u8]>,
+    },
+    /// the send stream was finished, no more data will be sent
+    StreamFinished {
+        stream_id: LoopbackStreamId,
+    },
+    /// the send stream was reset, data that hasn't been read is discarded
+    StreamReset {
+        stream_id: LoopbackStreamId,
+    },
+    /// the recv stream was stopped, no more data will be read
+    StreamStopped {
+        stream_id: LoopbackStreamId,
+    },
+    /// some bytes were read from the recv stream, allowing more to be sent
+    StreamAcknowledged {
+        stream_id: LoopbackStreamId,
+        bytes: usize,
+    },
+    Datagram(Box<[u8]>),
+}
+
+impl Frame {
+    /// the number of application bytes carried by the frame
+    pub(crate) fn data_len(&self) -> usize {
+        match self {
+            Frame::StreamData { data, .. } => data.len(),
+            Frame::Datagram(data) => data.len(),
+            _ => 0,
+        }
+    }
+}
+
+pub(crate) struct SendState {
+    /// bytes that have been sent but not yet read by the peer
+    unacknowledged: usize,
+}
+
+pub(crate) struct RecvState {
+    buffer: VecDeque<u8>,
+    /// the peer has finished the stream
+    finished: bool,
+}
+
+pub struct LoopbackSendStreamMut<'s> {
+    stream_id: LoopbackStreamId,
+    stream_window: usize,
+    outgoing: &'s mut VecDeque<Frame>,
+    events: &'s mut VecDeque<StreamEvent<LoopbackStreamId>>,
+    open_streams: &'s mut HashMap<LoopbackStreamId, SendState>,
+}
+
+pub struct LoopbackRecvStreamMut<'s> {
+    stream_id: LoopbackStreamId,
+    outgoing: &'s mut VecDeque<Frame>,
+    events: &'s mut VecDeque<StreamEvent<LoopbackStreamId>>,
+    open_streams: &'s mut HashMap<LoopbackStreamId, RecvState>,
+}
+
+#[derive(Debug)]
+pub enum LoopbackSendError {
+    /// the stream is blocked because the peer hasn't read enough of the data already sent
+    Blocked,
+    /// the stream has never been opened, has been finished or was reset
+    NoStream,
+}
+
+#[derive(Debug)]
+pub enum LoopbackReadError {
+    /// the stream is blocked and there is no more data to be read
+    Blocked,
+    /// the stream has finished and no more data will be available
+    Finished,
+    /// the stream has never been opened, has been finished or was reset
+    NoStream,
+}
+
+impl ErrorFatality for LoopbackSendError {
+    fn is_fatal(&self) -> bool {
+        match self {
+            LoopbackSendError::Blocked => false,
+            LoopbackSendError::NoStream => true,
+        }
+    }
+}
+
+impl ErrorFatality for LoopbackReadError {
+    fn is_fatal(&self) -> bool {
+        match self {
+            LoopbackReadError::Blocked => false,
+            LoopbackReadError::Finished => false,
+            LoopbackReadError::NoStream => true,
+        }
+    }
+}
+
+impl SendState {
+    pub(crate) fn new() -> Self {
+        SendState { unacknowledged: 0 }
+    }
+
+    pub(crate) fn acknowledge(&mut self, bytes: usize) {
+        self.unacknowledged = self.unacknowledged.saturating_sub(bytes);
+    }
+}
+
+impl RecvState {
+    pub(crate) fn new() -> Self {
+        RecvState {
+            buffer: VecDeque::new(),
+            finished: false,
+        }
+    }
+
+    pub(crate) fn receive(&mut self, data: &[u8]) {
+        self.buffer.extend(data);
+    }
+
+    pub(crate) fn finish(&mut self) {
+        self.finished = true;
+    }
+}
+
+impl<'s> SendStreamMut<'s> for LoopbackSendStreamMut<'s> {
+    type SendError = LoopbackSendError;
+
+    /// `None` finishes the stream, `Some` resets it with an error code
+    type CloseDescription = Option<u64>;
+
+    fn send(&mut self, data: &[u8]) -> Result<usize, Self::SendError> {
+        let Some(state) = self.open_streams.get_mut(&self.stream_id) else {
+            return Err(LoopbackSendError::NoStream);
+        };
+
+        let available = self.stream_window.saturating_sub(state.unacknowledged);
+
+        if available == 0 && !data.is_empty() {
+            return Err(LoopbackSendError::Blocked);
+        }
+
+        let bytes = available.min(data.len());
+
+        if bytes > 0 {
+            state.unacknowledged += bytes;
+
+            self.outgoing.push_back(Frame::StreamData {
+                stream_id: self.stream_id,
+                data: data[..bytes].into(),
+            });
+        }
+
+        Ok(bytes)
+    }
+
+    fn close(&mut self, description: Self::CloseDescription) -> Result<(), ()> {
+        if self.open_streams.remove(&self.stream_id).is_none() {
+            return Err(());
+        }
+
+        self.outgoing.push_back(match description {
+            None => Frame::StreamFinished {
+                stream_id: self.stream_id,
+            },
+            Some(_error_code) => Frame::StreamReset {
+                stream_id: self.stream_id,
+            },
+        });
+
+        self.events.push_back(StreamEvent {
+            stream_id: self.stream_id,
+            peer_generated: false,
+            event_type: StreamEventType::ClosedSendStream,
+        });
+
+        Ok(())
+    }
+
+    fn is_open(&self) -> bool {
+        self.open_streams.contains_key(&self.stream_id)
+    }
+}
+
+impl<'s> RecvStreamMut<'s> for LoopbackRecvStreamMut<'s> {
+    type ReadError = LoopbackReadError;
+
+    /// the error code to stop the stream with
+    type CloseDescription = u64;
+
+    fn recv(&mut self, limit: usize) -> Result<Box<[u8]>, Self::ReadError> {
+        let Some(state) = self.open_streams.get_mut(&self.stream_id) else {
+            return Err(LoopbackReadError::NoStream);
+        };
+
+        if !state.buffer.is_empty() {
+            let bytes = limit.min(state.buffer.len());
+
+            self.outgoing.push_back(Frame::StreamAcknowledged {
+                stream_id: self.stream_id,
+                bytes,
+            });
+
+            return Ok(state.buffer.drain(..bytes).collect());
+        }
+
+        if state.finished {
+            self.open_streams.remove(&self.stream_id);
+
+            self.events.push_back(StreamEvent {
+                stream_id: self.stream_id,
+                peer_generated: true,
+                event_type: StreamEventType::ClosedRecvStream,
+            });
+
+            return Err(LoopbackReadError::Finished);
+        }
+
+        Err(LoopbackReadError::Blocked)
+    }
+
+    fn close(&mut self, _description: Self::CloseDescription) -> Result<(), ()> {
+        if self.open_streams.remove(&self.stream_id).is_none() {
+            return Err(());
+        }
+
+        self.outgoing.push_back(Frame::StreamStopped {
+            stream_id: self.stream_id,
+        });
+
+        self.events.push_back(StreamEvent {
+            stream_id: self.stream_id,
+            peer_generated: false,
+            event_type: StreamEventType::ClosedRecvStream,
+        });
+
+        Ok(())
+    }
+
+    fn is_open(&self) -> bool {
+        self.open_streams.contains_key(&self.stream_id)
+    }
+}
+
+impl StreamId for LoopbackStreamId {
+    type Connection<'c> = &'c mut LoopbackConnection;
+
+    type SendMut<'s> = LoopbackSendStreamMut<'s>;
+
+    type RecvMut<'s> = LoopbackRecvStreamMut<'s>;
+
+    type OpenDescription = LoopbackDir;
+
+    fn open(
+        connection: &mut &mut LoopbackConnection,
+        description: Self::OpenDescription,
+    ) -> Option<Self> {
+        if connection.closed.is_some() {
+            return None;
+        }
+
+        let ConnectionState::Connected { .. } = connection.state else {
+            return None;
+        };
+
+        let stream_id = LoopbackStreamId {
+            initiator: connection.side,
+            index: connection.next_stream_index,
+        };
+        connection.next_stream_index += 1;
+
+        connection.outgoing.push_back(Frame::StreamOpened {
+            stream_id,
+            dir: description,
+        });
+
+        connection.send_streams.insert(stream_id, SendState::new());
+        connection.stream_events.push_back(StreamEvent {
+            stream_id,
+            peer_generated: false,
+            event_type: StreamEventType::NewSendStream,
+        });
+
+        if let LoopbackDir::Bi = description {
+            connection.recv_streams.insert(stream_id, RecvState::new());
+            connection.stream_events.push_back(StreamEvent {
+                stream_id,
+                peer_generated: false,
+                event_type: StreamEventType::NewRecvStream,
+            });
+        }
+
+        Some(stream_id)
+    }
+
+    fn get_send<'s>(
+        self,
+        connection: &'s mut &mut LoopbackConnection,
+    ) -> Option<Self::SendMut<'s>> {
+        if !connection.send_streams.contains_key(&self) {
+            return None;
+        }
+
+        Some(LoopbackSendStreamMut {
+            stream_id: self,
+            stream_window: connection.config.stream_window,
+            outgoing: &mut connection.outgoing,
+            events: &mut connection.stream_events,
+            open_streams: &mut connection.send_streams,
+        })
+    }
+
+    fn get_recv<'s>(
+        self,
+        connection: &'s mut &mut LoopbackConnection,
+    ) -> Option<Self::RecvMut<'s>> {
+        if !connection.recv_streams.contains_key(&self) {
+            return None;
+        }
+
+        Some(LoopbackRecvStreamMut {
+            stream_id: self,
+            outgoing: &mut connection.outgoing,
+            events: &mut connection.stream_events,
+            open_streams: &mut connection.recv_streams,
+        })
+    }
+
+    fn poll_events(connection: &mut &mut LoopbackConnection) -> Option<StreamEvent<Self>> {
+        connection.stream_events.pop_front()
+    }
+}
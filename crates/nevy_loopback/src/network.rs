@@ -0,0 +1,95 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use crate::{connection::LoopbackConnectionId, loopback_stream::Frame};
+
+/// the address of a [LoopbackEndpoint](crate::endpoint::LoopbackEndpoint) on a [LoopbackNetwork]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct LoopbackAddress(pub(crate) u64);
+
+/// An in memory network that links loopback endpoints inside one process.
+///
+/// This type is cheap to clone, all clones refer to the same network.
+/// Packets are only exchanged when endpoints are updated, so the order of events is deterministic.
+#[derive(Clone, Default)]
+pub struct LoopbackNetwork {
+    state: Arc<Mutex<NetworkState>>,
+}
+
+#[derive(Default)]
+struct NetworkState {
+    next_address: u64,
+    inboxes: HashMap<LoopbackAddress, VecDeque<Packet>>,
+}
+
+/// data sent between endpoints
+pub(crate) enum Packet {
+    /// a request to open a connection
+    Connect {
+        source: LoopbackAddress,
+        /// the id of the connection on the source endpoint
+        connection: LoopbackConnectionId,
+    },
+    /// a response to a connection request that was rejected
+    Refused {
+        /// the id of the connection on the destination endpoint
+        connection: LoopbackConnectionId,
+    },
+    /// data for an existing connection
+    Connection {
+        source: LoopbackAddress,
+        /// the id of the connection on the destination endpoint
+        connection: LoopbackConnectionId,
+        frame: Frame,
+    },
+}
+
+impl LoopbackNetwork {
+    pub fn new() -> Self {
+        LoopbackNetwork::default()
+    }
+
+    /// allocates a new address with an empty inbox
+    pub(crate) fn bind(&self) -> LoopbackAddress {
+        let mut state = self.state.lock().unwrap();
+
+        let address = LoopbackAddress(state.next_address);
+        state.next_address += 1;
+
+        state.inboxes.insert(address, VecDeque::new());
+
+        address
+    }
+
+    /// removes an address, any packets sent to it will fail to be delivered
+    pub(crate) fn unbind(&self, address: LoopbackAddress) {
+        self.state.lock().unwrap().inboxes.remove(&address);
+    }
+
+    /// takes all packets that have been delivered to an address
+    pub(crate) fn take_packets(&self, address: LoopbackAddress) -> VecDeque<Packet> {
+        let mut state = self.state.lock().unwrap();
+
+        let Some(inbox) = state.inboxes.get_mut(&address) else {
+            return VecDeque::new();
+        };
+
+        std::mem::take(inbox)
+    }
+
+    /// delivers a packet to an address
+    ///
+    /// returns `false` if no endpoint is bound to that address
+    pub(crate) fn deliver(&self, destination: LoopbackAddress, packet: Packet) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        let Some(inbox) = state.inboxes.get_mut(&destination) else {
+            return false;
+        };
+
+        inbox.push_back(packet);
+        true
+    }
+}
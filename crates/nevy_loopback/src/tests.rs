@@ -0,0 +1,348 @@
+use transport_interface::*;
+
+use crate::prelude::*;
+
+/// records the events fired when updating an endpoint
+struct RecordingHandler {
+    accept: bool,
+    requests: Vec<LoopbackAddress>,
+    connected: Vec<LoopbackConnectionId>,
+    disconnected: Vec<(LoopbackConnectionId, DisconnectReason)>,
+}
+
+impl RecordingHandler {
+    fn new(accept: bool) -> Self {
+        RecordingHandler {
+            accept,
+            requests: Vec::new(),
+            connected: Vec::new(),
+            disconnected: Vec::new(),
+        }
+    }
+}
+
+impl EndpointEventHandler<LoopbackEndpoint> for RecordingHandler {
+    fn connection_request<'i>(&mut self, request: LoopbackAddress) -> bool {
+        self.requests.push(request);
+        self.accept
+    }
+
+    fn connected(&mut self, connection_id: LoopbackConnectionId) {
+        self.connected.push(connection_id);
+    }
+
+    fn disconnected(&mut self, connection_id: LoopbackConnectionId, reason: DisconnectReason) {
+        self.disconnected.push((connection_id, reason));
+    }
+}
+
+/// updates both endpoints a few times so that all packets are exchanged
+fn exchange(
+    client: &mut LoopbackEndpoint,
+    client_handler: &mut RecordingHandler,
+    server: &mut LoopbackEndpoint,
+    server_handler: &mut RecordingHandler,
+) {
+    for _ in 0..4 {
+        client.update(client_handler);
+        server.update(server_handler);
+    }
+}
+
+/// connects a client to a server, returning the connection id on each endpoint
+fn connect(
+    client: &mut LoopbackEndpoint,
+    client_handler: &mut RecordingHandler,
+    server: &mut LoopbackEndpoint,
+    server_handler: &mut RecordingHandler,
+) -> (LoopbackConnectionId, LoopbackConnectionId) {
+    let (client_connection, _) = client.connect(server.local_addr()).unwrap();
+
+    exchange(client, client_handler, server, server_handler);
+
+    assert_eq!(client_handler.connected, vec![client_connection]);
+    assert_eq!(server_handler.connected.len(), 1);
+
+    (client_connection, server_handler.connected[0])
+}
+
+#[test]
+fn connects() {
+    let network = LoopbackNetwork::new();
+    let mut client = LoopbackEndpoint::new(&network, None);
+    let mut server = LoopbackEndpoint::new(&network, None);
+    let mut client_handler = RecordingHandler::new(false);
+    let mut server_handler = RecordingHandler::new(true);
+
+    let (client_connection, server_connection) = connect(
+        &mut client,
+        &mut client_handler,
+        &mut server,
+        &mut server_handler,
+    );
+
+    assert_eq!(server_handler.requests, vec![client.local_addr()]);
+
+    let client_connection = client.connection(client_connection).unwrap();
+    assert_eq!(client_connection.side(), Side::Client);
+    assert_eq!(client_connection.peer_address(), server.local_addr());
+
+    let server_connection = server.connection(server_connection).unwrap();
+    assert_eq!(server_connection.side(), Side::Server);
+    assert_eq!(server_connection.peer_address(), client.local_addr());
+}
+
+#[test]
+fn refused_connection_fails() {
+    let network = LoopbackNetwork::new();
+    let mut client = LoopbackEndpoint::new(&network, None);
+    let mut server = LoopbackEndpoint::new(&network, None);
+    let mut client_handler = RecordingHandler::new(false);
+    let mut server_handler = RecordingHandler::new(false);
+
+    let (client_connection, _) = client.connect(server.local_addr()).unwrap();
+
+    exchange(
+        &mut client,
+        &mut client_handler,
+        &mut server,
+        &mut server_handler,
+    );
+
+    assert!(client_handler.connected.is_empty());
+    assert!(server_handler.connected.is_empty());
+    assert_eq!(
+        client_handler.disconnected,
+        vec![(client_connection, DisconnectReason::Refused)]
+    );
+}
+
+#[test]
+fn connecting_to_nothing_times_out() {
+    let network = LoopbackNetwork::new();
+    let mut client = LoopbackEndpoint::new(&network, None);
+    let mut client_handler = RecordingHandler::new(false);
+
+    let missing_address = LoopbackEndpoint::new(&network, None).local_addr();
+
+    let (client_connection, _) = client.connect(missing_address).unwrap();
+    client.update(&mut client_handler);
+
+    assert_eq!(
+        client_handler.disconnected,
+        vec![(client_connection, DisconnectReason::TimedOut)]
+    );
+}
+
+#[test]
+fn stream_data_is_received() {
+    let network = LoopbackNetwork::new();
+    let mut client = LoopbackEndpoint::new(&network, None);
+    let mut server = LoopbackEndpoint::new(&network, None);
+    let mut client_handler = RecordingHandler::new(false);
+    let mut server_handler = RecordingHandler::new(true);
+
+    let (client_connection, server_connection) = connect(
+        &mut client,
+        &mut client_handler,
+        &mut server,
+        &mut server_handler,
+    );
+
+    let mut connection = client.connection_mut(client_connection).unwrap();
+    let stream_id = connection.open_stream(LoopbackDir::Uni).unwrap();
+
+    let mut stream = connection.send_stream(stream_id).unwrap();
+    assert_eq!(stream.send(b"hello loopback").unwrap(), 14);
+    stream.close(None).unwrap();
+    assert!(!stream.is_open());
+
+    let event = connection.poll_stream_events().unwrap();
+    assert!(matches!(event.event_type, StreamEventType::NewSendStream));
+    let event = connection.poll_stream_events().unwrap();
+    assert!(matches!(
+        event.event_type,
+        StreamEventType::ClosedSendStream
+    ));
+    assert!(!event.peer_generated);
+
+    exchange(
+        &mut client,
+        &mut client_handler,
+        &mut server,
+        &mut server_handler,
+    );
+
+    let mut connection = server.connection_mut(server_connection).unwrap();
+
+    let event = connection.poll_stream_events().unwrap();
+    assert!(matches!(event.event_type, StreamEventType::NewRecvStream));
+    assert!(event.peer_generated);
+    assert_eq!(event.stream_id, stream_id);
+
+    let mut stream = connection.recv_stream(stream_id).unwrap();
+    assert_eq!(stream.recv(usize::MAX).unwrap().as_ref(), b"hello loopback");
+    assert!(matches!(
+        stream.recv(usize::MAX),
+        Err(LoopbackReadError::Finished)
+    ));
+    assert!(!stream.is_open());
+
+    let stats = connection.get_stats();
+    assert_eq!(stats.remote_address, client.local_addr());
+    assert_eq!(stats.bytes_received, 14);
+}
+
+#[test]
+fn stream_window_blocks_sending() {
+    let network = LoopbackNetwork::new();
+    let config = LoopbackConfig {
+        stream_window: 4,
+        ..Default::default()
+    };
+    let mut client = LoopbackEndpoint::new(&network, Some(config));
+    let mut server = LoopbackEndpoint::new(&network, None);
+    let mut client_handler = RecordingHandler::new(false);
+    let mut server_handler = RecordingHandler::new(true);
+
+    let (client_connection, _) = connect(
+        &mut client,
+        &mut client_handler,
+        &mut server,
+        &mut server_handler,
+    );
+
+    let mut connection = client.connection_mut(client_connection).unwrap();
+    let stream_id = connection.open_stream(LoopbackDir::Uni).unwrap();
+
+    let mut stream = connection.send_stream(stream_id).unwrap();
+    assert_eq!(stream.send(b"hello").unwrap(), 4);
+    assert!(matches!(stream.send(b"o"), Err(LoopbackSendError::Blocked)));
+}
+
+#[test]
+fn disconnect_reason_is_received() {
+    let network = LoopbackNetwork::new();
+    let mut client = LoopbackEndpoint::new(&network, None);
+    let mut server = LoopbackEndpoint::new(&network, None);
+    let mut client_handler = RecordingHandler::new(false);
+    let mut server_handler = RecordingHandler::new(true);
+
+    let (client_connection, server_connection) = connect(
+        &mut client,
+        &mut client_handler,
+        &mut server,
+        &mut server_handler,
+    );
+
+    client
+        .disconnect_with_reason(client_connection, 7, b"goodbye")
+        .unwrap();
+
+    exchange(
+        &mut client,
+        &mut client_handler,
+        &mut server,
+        &mut server_handler,
+    );
+
+    assert_eq!(
+        client_handler.disconnected,
+        vec![(client_connection, DisconnectReason::LocallyClosed)]
+    );
+    assert_eq!(
+        server_handler.disconnected,
+        vec![(
+            server_connection,
+            DisconnectReason::ApplicationClosed {
+                code: 7,
+                reason: b"goodbye".as_slice().into(),
+            }
+        )]
+    );
+    assert!(client.connection(client_connection).is_none());
+    assert!(server.connection(server_connection).is_none());
+}
+
+#[test]
+fn datagrams_are_received() {
+    let network = LoopbackNetwork::new();
+    let mut client = LoopbackEndpoint::new(&network, None);
+    let mut server = LoopbackEndpoint::new(&network, None);
+    let mut client_handler = RecordingHandler::new(false);
+    let mut server_handler = RecordingHandler::new(true);
+
+    let (client_connection, server_connection) = connect(
+        &mut client,
+        &mut client_handler,
+        &mut server,
+        &mut server_handler,
+    );
+
+    let mut connection = client.connection_mut(client_connection).unwrap();
+    connection.send_datagram(b"datagram").unwrap();
+    assert!(matches!(
+        connection.send_datagram(&[0; 1201]),
+        Err(SendDatagramError::TooLarge)
+    ));
+
+    exchange(
+        &mut client,
+        &mut client_handler,
+        &mut server,
+        &mut server_handler,
+    );
+
+    let mut connection = server.connection_mut(server_connection).unwrap();
+    assert_eq!(connection.peek_datagram(), Some(b"datagram".as_slice()));
+    assert_eq!(connection.recv_datagram().unwrap().as_ref(), b"datagram");
+    assert!(connection.recv_datagram().is_none());
+}
+
+#[test]
+fn stopping_recv_stream_is_a_local_event() {
+    let network = LoopbackNetwork::new();
+    let mut client = LoopbackEndpoint::new(&network, None);
+    let mut server = LoopbackEndpoint::new(&network, None);
+    let mut client_handler = RecordingHandler::new(false);
+    let mut server_handler = RecordingHandler::new(true);
+
+    let (client_connection, server_connection) = connect(
+        &mut client,
+        &mut client_handler,
+        &mut server,
+        &mut server_handler,
+    );
+
+    let mut connection = client.connection_mut(client_connection).unwrap();
+    let stream_id = connection.open_stream(LoopbackDir::Uni).unwrap();
+    connection
+        .send_stream(stream_id)
+        .unwrap()
+        .send(b"data")
+        .unwrap();
+
+    exchange(
+        &mut client,
+        &mut client_handler,
+        &mut server,
+        &mut server_handler,
+    );
+
+    let mut connection = server.connection_mut(server_connection).unwrap();
+
+    let event = connection.poll_stream_events().unwrap();
+    assert!(matches!(event.event_type, StreamEventType::NewRecvStream));
+
+    let mut stream = connection.recv_stream(stream_id).unwrap();
+    stream.close(0).unwrap();
+    assert!(!stream.is_open());
+
+    let event = connection.poll_stream_events().unwrap();
+    assert!(matches!(
+        event.event_type,
+        StreamEventType::ClosedRecvStream
+    ));
+    assert!(!event.peer_generated);
+    assert_eq!(event.stream_id, stream_id);
+}
@@ -0,0 +1,281 @@
+//! channels decide how the messages bound to them are delivered
+//!
+//! reliable channels send messages on streams and unreliable channels send messages as datagrams.
+//! messages that aren't bound to a channel use the [DEFAULT_CHANNEL]
+
+use std::collections::HashMap;
+
+/// the name of the ordered reliable channel that every protocol has
+///
+/// messages that aren't bound to a channel are sent on this channel
+pub const DEFAULT_CHANNEL: &str = "default";
+
+/// how messages on a channel are delivered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChannelMode {
+    /// messages arrive in the order they were sent on a single stream
+    ///
+    /// a lost packet delays every message after it on the channel
+    OrderedReliable,
+    /// every message is sent on it's own stream and they may arrive in any order
+    ///
+    /// endpoints need an [EndpointMessageStreamCloseDescription](crate::serialize::EndpointMessageStreamCloseDescription)
+    UnorderedReliable,
+    /// messages are sent as datagrams and may be lost,
+    /// messages that arrive after a newer message on the same channel are dropped
+    SequencedUnreliable,
+    /// messages are sent as datagrams and may be lost or arrive in any order
+    Unreliable,
+}
+
+/// the channels of a protocol and the messages bound to them
+#[derive(Clone)]
+pub(crate) struct ChannelConfig {
+    /// the name and mode of each channel, the index is the channel's id
+    channels: Vec<(&'static str, ChannelMode)>,
+    /// message type names and the name of the channel they are bound to
+    message_channels: HashMap<&'static str, &'static str>,
+}
+
+impl ChannelConfig {
+    pub(crate) fn new() -> Self {
+        ChannelConfig {
+            channels: vec![(DEFAULT_CHANNEL, ChannelMode::OrderedReliable)],
+            message_channels: HashMap::new(),
+        }
+    }
+
+    /// panics if a channel with the same name was already added
+    pub(crate) fn add_channel(&mut self, name: &'static str, mode: ChannelMode) {
+        if self.channels.iter().any(|&(existing, _)| existing == name) {
+            panic!("a channel named \"{}\" was already added", name);
+        }
+
+        if self.channels.len() > u8::MAX as usize {
+            panic!(
+                "a protocol can't have more than {} channels",
+                u8::MAX as usize + 1
+            );
+        }
+
+        self.channels.push((name, mode));
+    }
+
+    pub(crate) fn set_message_channel(&mut self, type_name: &'static str, channel: &'static str) {
+        self.message_channels.insert(type_name, channel);
+    }
+
+    /// the mode of each channel indexed by channel id
+    pub(crate) fn modes(&self) -> Vec<ChannelMode> {
+        self.channels.iter().map(|&(_, mode)| mode).collect()
+    }
+
+    pub(crate) fn channels(&self) -> &[(&'static str, ChannelMode)] {
+        &self.channels
+    }
+
+    /// resolves the channel id of each message type
+    ///
+    /// panics if a message is bound to a channel that wasn't added
+    /// or a message type that wasn't added is bound to a channel
+    pub(crate) fn resolve(&self, type_names: impl Iterator<Item = &'static str>) -> Vec<u8> {
+        let mut bound = 0;
+
+        let channel_ids = type_names
+            .map(|type_name| {
+                let Some(&channel) = self.message_channels.get(type_name) else {
+                    return 0;
+                };
+
+                bound += 1;
+
+                let Some(channel_id) = self.channels.iter().position(|&(name, _)| name == channel)
+                else {
+                    panic!(
+                        "message \"{}\" is bound to channel \"{}\" which wasn't added",
+                        type_name, channel
+                    );
+                };
+
+                channel_id as u8
+            })
+            .collect();
+
+        if bound != self.message_channels.len() {
+            panic!("a message type was bound to a channel but wasn't added");
+        }
+
+        channel_ids
+    }
+}
+
+/// a message received as a datagram
+pub(crate) struct Datagram<'a> {
+    pub(crate) header: u16,
+    pub(crate) channel: u8,
+    /// present on [ChannelMode::SequencedUnreliable] channels
+    pub(crate) sequence: Option<u32>,
+    pub(crate) message_id: u16,
+    pub(crate) message: &'a [u8],
+}
+
+impl<'a> Datagram<'a> {
+    /// datagrams are a u16 header, a u8 channel id, a u32 sequence number on sequenced channels,
+    /// a u16 message id and then the message
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(9 + self.message.len());
+
+        buffer.extend(self.header.to_be_bytes());
+        buffer.push(self.channel);
+
+        if let Some(sequence) = self.sequence {
+            buffer.extend(sequence.to_be_bytes());
+        }
+
+        buffer.extend(self.message_id.to_be_bytes());
+        buffer.extend(self.message);
+
+        buffer
+    }
+
+    /// reads the messaging header of a datagram without decoding the rest of it
+    pub(crate) fn peek_header(datagram: &[u8]) -> Option<u16> {
+        Some(u16::from_be_bytes(datagram.get(0..2)?.try_into().ok()?))
+    }
+
+    /// returns `None` if the datagram is malformed or the channel doesn't send datagrams
+    pub(crate) fn decode(datagram: &'a [u8], channels: &[ChannelMode]) -> Option<Self> {
+        let header = u16::from_be_bytes(datagram.get(0..2)?.try_into().ok()?);
+        let channel = *datagram.get(2)?;
+
+        let (sequence, rest) = match channels.get(channel as usize)? {
+            ChannelMode::SequencedUnreliable => {
+                let sequence = u32::from_be_bytes(datagram.get(3..7)?.try_into().ok()?);
+                (Some(sequence), &datagram[7..])
+            }
+            ChannelMode::Unreliable => (None, &datagram[3..]),
+            _ => return None,
+        };
+
+        let message_id = u16::from_be_bytes(rest.get(0..2)?.try_into().ok()?);
+
+        Some(Datagram {
+            header,
+            channel,
+            sequence,
+            message_id,
+            message: &rest[2..],
+        })
+    }
+}
+
+/// returns `true` if `sequence` was sent after `last`, accounting for wrapping
+pub(crate) fn is_newer_sequence(sequence: u32, last: u32) -> bool {
+    (sequence.wrapping_sub(last) as i32) > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHANNELS: [ChannelMode; 3] = [
+        ChannelMode::OrderedReliable,
+        ChannelMode::SequencedUnreliable,
+        ChannelMode::Unreliable,
+    ];
+
+    fn datagram(channel: u8, sequence: Option<u32>) -> Datagram<'static> {
+        Datagram {
+            header: 0x1234,
+            channel,
+            sequence,
+            message_id: 7,
+            message: &[1, 2, 3],
+        }
+    }
+
+    fn assert_round_trips(datagram: Datagram) {
+        let encoded = datagram.encode();
+
+        assert_eq!(Datagram::peek_header(&encoded), Some(datagram.header));
+
+        let decoded = Datagram::decode(&encoded, &CHANNELS).unwrap();
+
+        assert_eq!(decoded.header, datagram.header);
+        assert_eq!(decoded.channel, datagram.channel);
+        assert_eq!(decoded.sequence, datagram.sequence);
+        assert_eq!(decoded.message_id, datagram.message_id);
+        assert_eq!(decoded.message, datagram.message);
+    }
+
+    #[test]
+    fn sequenced_datagrams_round_trip() {
+        assert_round_trips(datagram(1, Some(0)));
+        assert_round_trips(datagram(1, Some(u32::MAX)));
+
+        assert_eq!(datagram(1, Some(5)).encode().len(), 12);
+    }
+
+    #[test]
+    fn unreliable_datagrams_round_trip() {
+        assert_round_trips(datagram(2, None));
+
+        assert_eq!(datagram(2, None).encode().len(), 8);
+    }
+
+    #[test]
+    fn empty_messages_round_trip() {
+        assert_round_trips(Datagram {
+            message: &[],
+            ..datagram(2, None)
+        });
+    }
+
+    #[test]
+    fn truncated_datagrams_are_malformed() {
+        for encoded in [datagram(1, Some(5)).encode(), datagram(2, None).encode()] {
+            // the last three bytes are the message, anything shorter cuts into the header
+            for length in 0..encoded.len() - 3 {
+                assert!(
+                    Datagram::decode(&encoded[..length], &CHANNELS).is_none(),
+                    "length {}",
+                    length
+                );
+            }
+        }
+
+        assert_eq!(Datagram::peek_header(&[0x12]), None);
+    }
+
+    #[test]
+    fn stream_channels_dont_decode() {
+        let encoded = datagram(0, None).encode();
+
+        assert!(Datagram::decode(&encoded, &CHANNELS).is_none());
+    }
+
+    #[test]
+    fn unknown_channels_dont_decode() {
+        let encoded = datagram(3, None).encode();
+
+        assert!(Datagram::decode(&encoded, &CHANNELS).is_none());
+    }
+
+    #[test]
+    fn newer_sequences() {
+        assert!(is_newer_sequence(1, 0));
+        assert!(!is_newer_sequence(0, 1));
+        assert!(!is_newer_sequence(5, 5));
+    }
+
+    #[test]
+    fn newer_sequences_wrap() {
+        assert!(is_newer_sequence(0, u32::MAX));
+        assert!(is_newer_sequence(10, u32::MAX - 10));
+        assert!(!is_newer_sequence(u32::MAX, 0));
+
+        // half the sequence space away is considered older
+        assert!(is_newer_sequence(i32::MAX as u32, 0));
+        assert!(!is_newer_sequence(i32::MAX as u32 + 1, 0));
+    }
+}
@@ -0,0 +1,492 @@
+//! synchronizes the clocks of clients with the server
+//!
+//! the ping messages are added with [ProtocolBuilder::add_clock_sync](crate::ProtocolBuilder::add_clock_sync).
+//! connections of client endpoints periodically ping the server, which answers with it's own time.
+//! each answer gives a round trip time and an estimate of the offset between the two clocks,
+//! which are combined into the server time and tick of the [NetworkClock]
+
+use std::{collections::VecDeque, marker::PhantomData, time::Duration};
+
+use bevy::{
+    ecs::{intern::Interned, schedule::ScheduleLabel},
+    prelude::*,
+};
+use bevy_interface::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    deserialize::ReceivedMessages,
+    serialize::{QueueMessageError, SendMessages},
+};
+
+/// the default duration of a network tick, 60 ticks per second
+pub const DEFAULT_TICK_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// the default time between clock pings
+pub const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// the number of recent pings that the clock offset is estimated from
+const CLOCK_SAMPLES: usize = 8;
+
+/// the number of seconds a synced [NetworkClock] takes to correct the difference to a new estimate
+const CLOCK_CORRECTION_TIME: f64 = 1.;
+
+/// keeps a [NetworkClock] synchronized with the server for endpoints with the marker `C`
+///
+/// endpoints with a [ClockServer] answer pings and are the source of time,
+/// the connections of other endpoints ping their peer.
+/// the ping messages need to be added to the protocol with [ProtocolBuilder::add_clock_sync](crate::ProtocolBuilder::add_clock_sync)
+pub struct ClockSyncPlugin<C> {
+    _p: PhantomData<C>,
+    schedule: Interned<dyn ScheduleLabel>,
+    tick_duration: Duration,
+    ping_interval: Duration,
+}
+
+impl<C> ClockSyncPlugin<C> {
+    pub fn new(schedule: impl ScheduleLabel) -> Self {
+        ClockSyncPlugin {
+            _p: PhantomData,
+            schedule: schedule.intern(),
+            tick_duration: DEFAULT_TICK_DURATION,
+            ping_interval: DEFAULT_PING_INTERVAL,
+        }
+    }
+
+    /// sets the duration of a network tick, which should be the same on both ends of a connection
+    ///
+    /// defaults to [DEFAULT_TICK_DURATION]
+    pub fn set_tick_duration(&mut self, tick_duration: Duration) -> &mut Self {
+        self.tick_duration = tick_duration;
+        self
+    }
+
+    /// sets how often clients ping the server
+    ///
+    /// defaults to [DEFAULT_PING_INTERVAL]
+    pub fn set_ping_interval(&mut self, ping_interval: Duration) -> &mut Self {
+        self.ping_interval = ping_interval;
+        self
+    }
+}
+
+impl<C: Component> Plugin for ClockSyncPlugin<C> {
+    fn build(&self, app: &mut App) {
+        if self.tick_duration.is_zero() {
+            panic!("the network tick duration can't be zero");
+        }
+
+        app.insert_resource(NetworkClock::<C> {
+            _p: PhantomData,
+            tick_duration: self.tick_duration,
+            ping_interval: self.ping_interval,
+            time: Duration::ZERO,
+            rtt: None,
+            synced: false,
+        });
+
+        app.add_systems(
+            self.schedule,
+            (
+                insert_connection_clocks::<C>,
+                send_pings::<C>,
+                answer_pings::<C>,
+                receive_pongs::<C>,
+                update_network_clock::<C>,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Insert onto an endpoint to make it the source of time for it's connections
+#[derive(Component)]
+pub struct ClockServer;
+
+/// sent by a client to measure the round trip time and the server's clock
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ClockPing {
+    /// the client's [Time<Real>] elapsed time in seconds when the ping was sent
+    client_time: f64,
+}
+
+/// the server's answer to a [ClockPing]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ClockPong {
+    client_time: f64,
+    /// the server's [Time<Real>] elapsed time in seconds when the ping was answered
+    server_time: f64,
+}
+
+/// the estimated clock of the server for endpoints with the marker `C`
+///
+/// on servers this is the local time.
+/// on clients it's estimated from the first connection with a synchronized [ConnectionClock].
+/// while synced the clock never goes backwards,
+/// differences to a new estimate are corrected gradually
+#[derive(Resource)]
+pub struct NetworkClock<C> {
+    _p: PhantomData<C>,
+    tick_duration: Duration,
+    ping_interval: Duration,
+    time: Duration,
+    rtt: Option<Duration>,
+    synced: bool,
+}
+
+impl<C> NetworkClock<C> {
+    /// the estimated elapsed time of the server
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+
+    /// the estimated tick of the server
+    ///
+    /// ticks are `u32` to match the ticks used by prediction, and wrap after [u32::MAX] ticks.
+    /// this is the server's tick, prediction ticks count the inputs a client has simulated
+    /// and are related to server ticks by the server acknowledging them, not by this clock
+    pub fn tick(&self) -> u32 {
+        (self.time.as_nanos() / self.tick_duration.as_nanos()) as u32
+    }
+
+    /// how far the server is through it's current tick, between 0 and 1
+    pub fn tick_fraction(&self) -> f64 {
+        (self.time.as_nanos() % self.tick_duration.as_nanos()) as f64
+            / self.tick_duration.as_nanos() as f64
+    }
+
+    /// the server time that a tick starts at
+    pub fn tick_time(&self, tick: u32) -> Duration {
+        let nanos = self.tick_duration.as_nanos() * tick as u128;
+        Duration::from_nanos(nanos.try_into().unwrap_or(u64::MAX))
+    }
+
+    pub fn tick_duration(&self) -> Duration {
+        self.tick_duration
+    }
+
+    /// the round trip time to the server, `None` on servers
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
+    /// `false` until the first ping to the server has been answered
+    pub fn is_synced(&self) -> bool {
+        self.synced
+    }
+}
+
+/// the round trip time and clock offset of a client's connection to a [ClockServer]
+#[derive(Component)]
+pub struct ConnectionClock {
+    /// when the last ping was sent
+    last_ping: Option<Duration>,
+    /// the round trip time and clock offset of recent pings
+    samples: VecDeque<(f64, f64)>,
+    rtt: Option<f64>,
+    offset: Option<f64>,
+}
+
+impl ConnectionClock {
+    /// the smoothed round trip time
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt.map(Duration::from_secs_f64)
+    }
+
+    /// the estimated number of seconds that the server's clock is ahead of the local clock
+    pub fn offset(&self) -> Option<f64> {
+        self.offset
+    }
+
+    /// estimates the server's time from the local time
+    pub fn server_time(&self, local_time: Duration) -> Option<Duration> {
+        let offset = self.offset?;
+        Some(Duration::from_secs_f64(
+            (local_time.as_secs_f64() + offset).max(0.),
+        ))
+    }
+
+    fn add_sample(&mut self, rtt: f64, offset: f64) {
+        self.samples.push_back((rtt, offset));
+        if self.samples.len() > CLOCK_SAMPLES {
+            self.samples.pop_front();
+        }
+
+        self.rtt = Some(match self.rtt {
+            Some(smoothed) => smoothed + (rtt - smoothed) * 0.125,
+            None => rtt,
+        });
+
+        // the sample with the lowest round trip time was delayed the least,
+        // so it's offset is the most accurate
+        self.offset = self
+            .samples
+            .iter()
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|&(_, offset)| offset);
+    }
+}
+
+fn insert_connection_clocks<C: Component>(
+    mut commands: Commands,
+    mut connected_r: EventReader<Connected>,
+    endpoint_q: Query<(), (With<C>, Without<ClockServer>)>,
+) {
+    for &Connected {
+        endpoint_entity,
+        connection_entity,
+    } in connected_r.read()
+    {
+        if !endpoint_q.contains(endpoint_entity) {
+            continue;
+        }
+
+        // the connection may have been closed before this system ran
+        let Some(mut connection_commands) = commands.get_entity(connection_entity) else {
+            continue;
+        };
+
+        connection_commands.try_insert(ConnectionClock {
+            last_ping: None,
+            samples: VecDeque::new(),
+            rtt: None,
+            offset: None,
+        });
+    }
+}
+
+fn send_pings<C: Component>(
+    mut messages: SendMessages<C>,
+    mut connection_q: Query<(Entity, &mut ConnectionClock, &Parent)>,
+    endpoint_q: Query<(), With<C>>,
+    clock: Res<NetworkClock<C>>,
+    time: Res<Time<Real>>,
+) {
+    let elapsed = time.elapsed();
+
+    for (connection_entity, mut connection_clock, connection_parent) in connection_q.iter_mut() {
+        if !endpoint_q.contains(connection_parent.get()) {
+            continue;
+        }
+
+        if connection_clock
+            .last_ping
+            .is_some_and(|last_ping| elapsed < last_ping + clock.ping_interval)
+        {
+            continue;
+        }
+
+        let message = ClockPing {
+            client_time: elapsed.as_secs_f64(),
+        };
+
+        match messages.send(connection_entity, &message) {
+            Ok(()) => connection_clock.last_ping = Some(elapsed),
+            // try again once the message queue has been inserted
+            Err(QueueMessageError::NoConnection) => (),
+            Err(err) => {
+                warn!(
+                    "failed to send a clock ping to connection {:?}: {:?}",
+                    connection_entity, err
+                );
+                connection_clock.last_ping = Some(elapsed);
+            }
+        }
+    }
+}
+
+fn answer_pings<C: Component>(
+    mut messages: SendMessages<C>,
+    mut connection_q: Query<(Entity, &mut ReceivedMessages<ClockPing>, &Parent)>,
+    endpoint_q: Query<(), (With<C>, With<ClockServer>)>,
+    time: Res<Time<Real>>,
+) {
+    let server_time = time.elapsed().as_secs_f64();
+
+    for (connection_entity, mut pings, connection_parent) in connection_q.iter_mut() {
+        if !endpoint_q.contains(connection_parent.get()) {
+            continue;
+        }
+
+        for ClockPing { client_time } in pings.drain() {
+            let message = ClockPong {
+                client_time,
+                server_time,
+            };
+
+            if let Err(err) = messages.send(connection_entity, &message) {
+                warn!(
+                    "failed to answer a clock ping from connection {:?}: {:?}",
+                    connection_entity, err
+                );
+            }
+        }
+    }
+}
+
+fn receive_pongs<C: Component>(
+    mut connection_q: Query<(
+        &mut ConnectionClock,
+        &mut ReceivedMessages<ClockPong>,
+        &Parent,
+    )>,
+    endpoint_q: Query<(), With<C>>,
+    time: Res<Time<Real>>,
+) {
+    let local_time = time.elapsed().as_secs_f64();
+
+    for (mut connection_clock, mut pongs, connection_parent) in connection_q.iter_mut() {
+        if !endpoint_q.contains(connection_parent.get()) {
+            continue;
+        }
+
+        for ClockPong {
+            client_time,
+            server_time,
+        } in pongs.drain()
+        {
+            let rtt = (local_time - client_time).max(0.);
+
+            // assumes the ping and pong took the same time to arrive
+            let offset = server_time + rtt / 2. - local_time;
+
+            connection_clock.add_sample(rtt, offset);
+        }
+    }
+}
+
+fn update_network_clock<C: Component>(
+    mut clock: ResMut<NetworkClock<C>>,
+    server_q: Query<(), (With<C>, With<ClockServer>)>,
+    connection_q: Query<(&ConnectionClock, &Parent)>,
+    endpoint_q: Query<(), With<C>>,
+    time: Res<Time<Real>>,
+) {
+    let local_time = time.elapsed();
+
+    if !server_q.is_empty() {
+        clock.time = local_time;
+        clock.rtt = None;
+        clock.synced = true;
+        return;
+    }
+
+    let synced = connection_q
+        .iter()
+        .filter(|(_, connection_parent)| endpoint_q.contains(connection_parent.get()))
+        .find_map(|(connection_clock, _)| {
+            Some((
+                connection_clock.server_time(local_time)?,
+                connection_clock.rtt(),
+            ))
+        });
+
+    match synced {
+        Some((server_time, rtt)) => {
+            clock.time = match clock.synced {
+                true => advance_clock(clock.time, server_time, time.delta()),
+                false => server_time,
+            };
+            clock.rtt = rtt;
+            clock.synced = true;
+        }
+        None => {
+            clock.rtt = None;
+            clock.synced = false;
+        }
+    }
+}
+
+/// advances a synced clock by the local elapsed time and corrects part of it's difference to the estimate
+///
+/// the clock is clamped so that it never goes backwards
+fn advance_clock(time: Duration, estimate: Duration, delta: Duration) -> Duration {
+    let advanced = (time + delta).as_secs_f64();
+    let correction = (delta.as_secs_f64() / CLOCK_CORRECTION_TIME).min(1.);
+    let corrected = advanced + (estimate.as_secs_f64() - advanced) * correction;
+
+    Duration::from_secs_f64(corrected.max(0.)).max(time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection_clock() -> ConnectionClock {
+        ConnectionClock {
+            last_ping: None,
+            samples: VecDeque::new(),
+            rtt: None,
+            offset: None,
+        }
+    }
+
+    #[test]
+    fn first_sample_sets_rtt_and_offset() {
+        let mut clock = connection_clock();
+        assert_eq!(clock.server_time(Duration::from_secs(1)), None);
+
+        clock.add_sample(0.1, 2.);
+
+        assert_eq!(clock.rtt(), Some(Duration::from_secs_f64(0.1)));
+        assert_eq!(clock.offset(), Some(2.));
+        assert_eq!(
+            clock.server_time(Duration::from_secs(1)),
+            Some(Duration::from_secs(3))
+        );
+    }
+
+    #[test]
+    fn offset_comes_from_the_lowest_rtt_sample() {
+        let mut clock = connection_clock();
+
+        clock.add_sample(0.2, 5.);
+        clock.add_sample(0.05, 3.);
+        clock.add_sample(0.3, 7.);
+
+        assert_eq!(clock.offset(), Some(3.));
+    }
+
+    #[test]
+    fn rtt_is_smoothed() {
+        let mut clock = connection_clock();
+
+        clock.add_sample(0.1, 0.);
+        clock.add_sample(0.9, 0.);
+
+        assert_eq!(clock.rtt(), Some(Duration::from_secs_f64(0.2)));
+    }
+
+    #[test]
+    fn old_samples_are_forgotten() {
+        let mut clock = connection_clock();
+
+        clock.add_sample(0.01, 1.);
+        for _ in 0..CLOCK_SAMPLES {
+            clock.add_sample(0.5, 4.);
+        }
+
+        assert_eq!(clock.samples.len(), CLOCK_SAMPLES);
+        assert_eq!(clock.offset(), Some(4.));
+    }
+
+    #[test]
+    fn clock_corrects_towards_the_estimate() {
+        let time = Duration::from_secs(10);
+        let delta = Duration::from_millis(100);
+
+        let advanced = advance_clock(time, Duration::from_secs(12), delta);
+
+        assert!(advanced > time + delta);
+        assert!(advanced < Duration::from_secs(12));
+    }
+
+    #[test]
+    fn clock_never_goes_backwards() {
+        let time = Duration::from_secs(10);
+
+        let advanced = advance_clock(time, Duration::from_secs(5), Duration::from_millis(100));
+
+        assert_eq!(advanced, time);
+    }
+}
@@ -0,0 +1,188 @@
+//! serialization formats that can be used for messages
+//!
+//! the codec is chosen per protocol with the second type parameter of
+//! [ProtocolBuilder](crate::ProtocolBuilder), defaulting to [BincodeCodec]
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// the error returned when a codec fails to serialize or deserialize a message
+pub type CodecError = Box<dyn std::error::Error + Send + Sync>;
+
+/// a serialization format for messages
+///
+/// both ends of a connection must use the same codec
+pub trait MessageCodec: Send + Sync + 'static {
+    fn serialize<T: Serialize>(message: &T) -> Result<Vec<u8>, CodecError>;
+
+    fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError>;
+}
+
+/// serializes messages with [bincode], this is the default codec
+pub struct BincodeCodec;
+
+impl MessageCodec for BincodeCodec {
+    fn serialize<T: Serialize>(message: &T) -> Result<Vec<u8>, CodecError> {
+        Ok(bincode::serialize(message)?)
+    }
+
+    fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+/// serializes messages with [postcard]
+#[cfg(feature = "postcard")]
+pub struct PostcardCodec;
+
+#[cfg(feature = "postcard")]
+impl MessageCodec for PostcardCodec {
+    fn serialize<T: Serialize>(message: &T) -> Result<Vec<u8>, CodecError> {
+        Ok(postcard::to_allocvec(message)?)
+    }
+
+    fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError> {
+        Ok(postcard::from_bytes(bytes)?)
+    }
+}
+
+/// serializes messages as MessagePack with [rmp_serde]
+///
+/// structs are written as maps with their field names
+/// so that they can be read by other MessagePack implementations
+#[cfg(feature = "messagepack")]
+pub struct MessagePackCodec;
+
+#[cfg(feature = "messagepack")]
+impl MessageCodec for MessagePackCodec {
+    fn serialize<T: Serialize>(message: &T) -> Result<Vec<u8>, CodecError> {
+        Ok(rmp_serde::to_vec_named(message)?)
+    }
+
+    fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError> {
+        Ok(rmp_serde::from_slice(bytes)?)
+    }
+}
+
+/// serializes messages as JSON with [serde_json]
+#[cfg(feature = "json")]
+pub struct JsonCodec;
+
+#[cfg(feature = "json")]
+impl MessageCodec for JsonCodec {
+    fn serialize<T: Serialize>(message: &T) -> Result<Vec<u8>, CodecError> {
+        Ok(serde_json::to_vec(message)?)
+    }
+
+    fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Position {
+        x: f32,
+        name: String,
+        path: Vec<(i32, i32)>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Action {
+        Stop,
+        Move(Position),
+    }
+
+    fn position() -> Position {
+        Position {
+            x: 1.5,
+            name: "spawn".into(),
+            path: vec![(0, 0), (-3, 7)],
+        }
+    }
+
+    fn assert_round_trips<M: MessageCodec>() {
+        let bytes = M::serialize(&position()).unwrap();
+        assert_eq!(M::deserialize::<Position>(&bytes).unwrap(), position());
+
+        for action in [Action::Stop, Action::Move(position())] {
+            let bytes = M::serialize(&action).unwrap();
+            assert_eq!(M::deserialize::<Action>(&bytes).unwrap(), action);
+        }
+    }
+
+    fn assert_rejects_truncated<M: MessageCodec>() {
+        let bytes = M::serialize(&position()).unwrap();
+
+        assert!(M::deserialize::<Position>(&bytes[..bytes.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn bincode_round_trips() {
+        assert_round_trips::<BincodeCodec>();
+        assert_rejects_truncated::<BincodeCodec>();
+    }
+
+    #[cfg(feature = "postcard")]
+    #[test]
+    fn postcard_round_trips() {
+        assert_round_trips::<PostcardCodec>();
+        assert_rejects_truncated::<PostcardCodec>();
+    }
+
+    #[cfg(feature = "messagepack")]
+    #[test]
+    fn messagepack_round_trips() {
+        assert_round_trips::<MessagePackCodec>();
+        assert_rejects_truncated::<MessagePackCodec>();
+    }
+
+    #[cfg(feature = "messagepack")]
+    #[test]
+    fn messagepack_writes_structs_as_maps() {
+        let bytes = MessagePackCodec::serialize(&position()).unwrap();
+
+        // a fixmap with three entries, keyed by the field names
+        assert_eq!(bytes[0], 0x83);
+        assert_eq!(&bytes[1..3], [0xa1, b'x']);
+        assert!(bytes.windows(4).any(|window| window == b"name"));
+        assert!(bytes.windows(4).any(|window| window == b"path"));
+
+        // other implementations can write the fields in any order
+        #[derive(Serialize)]
+        struct Reordered {
+            path: Vec<(i32, i32)>,
+            name: String,
+            x: f32,
+        }
+
+        let reordered = MessagePackCodec::serialize(&Reordered {
+            path: vec![(0, 0), (-3, 7)],
+            name: "spawn".into(),
+            x: 1.5,
+        })
+        .unwrap();
+
+        assert_eq!(
+            MessagePackCodec::deserialize::<Position>(&reordered).unwrap(),
+            position()
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_round_trips() {
+        assert_round_trips::<JsonCodec>();
+        assert_rejects_truncated::<JsonCodec>();
+
+        let bytes = JsonCodec::serialize(&position()).unwrap();
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            r#"{"x":1.5,"name":"spawn","path":[[0,0],[-3,7]]}"#
+        );
+    }
+}
@@ -0,0 +1,527 @@
+//! delta compressed state messages
+//!
+//! delta messages are added with [ProtocolBuilder::add_delta_message](crate::ProtocolBuilder::add_delta_message)
+//! and sent with a connection's [DeltaSender].
+//! each state is encoded as the changes from the last state the peer acknowledged,
+//! or as the full state when there is no acknowledged state to compare to,
+//! such as the first state sent or after too many states were lost.
+//! received states are decoded into the connection's [DeltaReceiver] and acknowledged automatically
+
+use std::{collections::VecDeque, marker::PhantomData};
+
+use bevy::{
+    ecs::{intern::Interned, schedule::ScheduleLabel},
+    prelude::*,
+};
+use bevy_interface::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    deserialize::ReceivedMessages,
+    serialize::{QueueMessageError, SendMessages},
+};
+
+/// the default number of sent states that can be used as a baseline
+pub const DEFAULT_DELTA_HISTORY: usize = 32;
+
+/// a state that can be encoded as the changes from another state of the same type
+pub trait Delta: Sized {
+    type Delta: Serialize + DeserializeOwned + Send + Sync + 'static;
+
+    /// the changes from `baseline` to this state
+    fn delta(&self, baseline: &Self) -> Self::Delta;
+
+    /// applies changes created by [Delta::delta] to this state, giving the new state
+    fn apply_delta(&self, delta: Self::Delta) -> Self;
+}
+
+/// sends and receives the delta messages added with [ProtocolBuilder::add_delta_message](crate::ProtocolBuilder::add_delta_message)
+///
+/// built with [ProtocolBuilder::build_delta](crate::ProtocolBuilder::build_delta)
+pub struct DeltaPlugin<C> {
+    _p: PhantomData<C>,
+    schedule: Interned<dyn ScheduleLabel>,
+    messages: Vec<Box<dyn DeltaBuilder<C>>>,
+    history: usize,
+}
+
+pub(crate) trait DeltaBuilder<C>: Send + Sync + 'static {
+    fn clone_builder(&self) -> Box<dyn DeltaBuilder<C>>;
+
+    fn build(&self, schedule: Interned<dyn ScheduleLabel>, app: &mut App);
+}
+
+pub(crate) struct DeltaBuilderType<T> {
+    pub(crate) _p: PhantomData<T>,
+}
+
+impl<C> DeltaPlugin<C> {
+    pub(crate) fn new(
+        schedule: impl ScheduleLabel,
+        messages: Vec<Box<dyn DeltaBuilder<C>>>,
+        history: usize,
+    ) -> Self {
+        DeltaPlugin {
+            _p: PhantomData,
+            schedule: schedule.intern(),
+            messages,
+            history,
+        }
+    }
+}
+
+impl<C: Component> Plugin for DeltaPlugin<C> {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(DeltaHistory::<C> {
+            _p: PhantomData,
+            history: self.history.max(1),
+        });
+
+        for builder in self.messages.iter() {
+            builder.build(self.schedule, app);
+        }
+    }
+}
+
+impl<C: Component, T> DeltaBuilder<C> for DeltaBuilderType<T>
+where
+    T: Delta + Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    fn clone_builder(&self) -> Box<dyn DeltaBuilder<C>> {
+        Box::new(DeltaBuilderType::<T> { _p: PhantomData })
+    }
+
+    fn build(&self, schedule: Interned<dyn ScheduleLabel>, app: &mut App) {
+        app.add_systems(
+            schedule,
+            (
+                insert_delta_components::<C, T>,
+                receive_delta_acks::<C, T>,
+                send_deltas::<C, T>,
+                receive_deltas::<C, T>,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Contains the number of states kept as baselines for endpoints with the marker `C`
+#[derive(Resource)]
+struct DeltaHistory<C> {
+    _p: PhantomData<C>,
+    history: usize,
+}
+
+/// the message a state is sent as
+#[derive(Serialize, Deserialize)]
+#[serde(bound(serialize = "T: Serialize", deserialize = "T: DeserializeOwned"))]
+pub(crate) struct DeltaMessage<T: Delta> {
+    sequence: u32,
+    encoding: DeltaEncoding<T>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(bound(serialize = "T: Serialize", deserialize = "T: DeserializeOwned"))]
+enum DeltaEncoding<T: Delta> {
+    Full(T),
+    Delta { baseline: u32, delta: T::Delta },
+}
+
+/// acknowledges that a state was received and can be used as a baseline
+#[derive(Serialize, Deserialize)]
+pub(crate) struct DeltaAck<T> {
+    sequence: u32,
+    #[serde(skip)]
+    _p: PhantomData<T>,
+}
+
+type ReceivedDeltas<T> = ReceivedMessages<DeltaMessage<T>>;
+type ReceivedDeltaAcks<T> = ReceivedMessages<DeltaAck<T>>;
+
+/// sends delta compressed states of type `T` to a connection
+///
+/// inserted onto connections for each message added with
+/// [ProtocolBuilder::add_delta_message](crate::ProtocolBuilder::add_delta_message)
+#[derive(Component)]
+pub struct DeltaSender<T> {
+    /// the state waiting to be sent
+    pending: Option<T>,
+    next_sequence: u32,
+    /// sent states that haven't been replaced by a newer baseline, oldest first
+    sent: VecDeque<(u32, T)>,
+    /// the newest state acknowledged by the peer
+    baseline: Option<u32>,
+}
+
+impl<T> DeltaSender<T> {
+    fn new() -> Self {
+        DeltaSender {
+            pending: None,
+            next_sequence: 0,
+            sent: VecDeque::new(),
+            baseline: None,
+        }
+    }
+
+    /// sets the state to send the next time the delta systems run
+    ///
+    /// replaces a state that hasn't been sent yet
+    pub fn send(&mut self, state: T) {
+        self.pending = Some(state);
+    }
+
+    /// the sequence number of the newest state acknowledged by the peer
+    pub fn baseline(&self) -> Option<u32> {
+        self.baseline
+    }
+
+    fn baseline_state(&self) -> Option<(u32, &T)> {
+        let baseline = self.baseline?;
+
+        self.sent
+            .iter()
+            .find(|&&(sequence, _)| sequence == baseline)
+            .map(|(sequence, state)| (*sequence, state))
+    }
+
+    /// encodes a state against the acknowledged baseline, or in full if there isn't one
+    fn encode(&self, state: &T) -> DeltaEncoding<T>
+    where
+        T: Delta + Clone,
+    {
+        match self.baseline_state() {
+            Some((baseline, baseline_state)) => DeltaEncoding::Delta {
+                baseline,
+                delta: state.delta(baseline_state),
+            },
+            None => DeltaEncoding::Full(state.clone()),
+        }
+    }
+
+    fn record_sent(&mut self, sequence: u32, state: T, history: usize) {
+        self.next_sequence = sequence + 1;
+        self.sent.push_back((sequence, state));
+
+        // when too many states are lost the baseline is forgotten
+        // and the next state is sent in full
+        while self.sent.len() > history {
+            if let Some((sequence, _)) = self.sent.pop_front() {
+                if self.baseline == Some(sequence) {
+                    self.baseline = None;
+                }
+            }
+        }
+    }
+
+    fn acknowledge(&mut self, sequence: u32) {
+        if self.baseline.is_some_and(|baseline| baseline >= sequence) {
+            return;
+        }
+
+        if !self.sent.iter().any(|&(sent, _)| sent == sequence) {
+            return;
+        }
+
+        self.baseline = Some(sequence);
+
+        // older states won't be used as a baseline again
+        while self.sent.front().is_some_and(|&(sent, _)| sent < sequence) {
+            self.sent.pop_front();
+        }
+    }
+}
+
+/// receives delta compressed states of type `T` from a connection
+///
+/// inserted onto connections for each message added with
+/// [ProtocolBuilder::add_delta_message](crate::ProtocolBuilder::add_delta_message).
+/// states that arrive after a newer state are dropped
+#[derive(Component)]
+pub struct DeltaReceiver<T> {
+    /// decoded states waiting to be taken by the app
+    states: VecDeque<T>,
+    /// decoded states that can be used as a baseline, oldest first
+    received: VecDeque<(u32, T)>,
+    /// the newest state that hasn't been acknowledged yet
+    unacked: Option<u32>,
+}
+
+impl<T> DeltaReceiver<T> {
+    fn new() -> Self {
+        DeltaReceiver {
+            states: VecDeque::new(),
+            received: VecDeque::new(),
+            unacked: None,
+        }
+    }
+
+    /// drains the received states
+    pub fn drain(&mut self) -> impl Iterator<Item = T> + '_ {
+        self.states.drain(..)
+    }
+
+    /// pops the oldest received state
+    pub fn pop(&mut self) -> Option<T> {
+        self.states.pop_front()
+    }
+
+    /// the newest received state, which isn't removed by [drain](Self::drain) or [pop](Self::pop)
+    pub fn latest(&self) -> Option<&T> {
+        self.received.back().map(|(_, state)| state)
+    }
+}
+
+fn insert_delta_components<C: Component, T: Send + Sync + 'static>(
+    mut commands: Commands,
+    mut connected_r: EventReader<Connected>,
+    endpoint_q: Query<(), With<C>>,
+) {
+    for &Connected {
+        endpoint_entity,
+        connection_entity,
+    } in connected_r.read()
+    {
+        if !endpoint_q.contains(endpoint_entity) {
+            continue;
+        }
+
+        // the connection may have been closed before this system ran
+        let Some(mut connection_commands) = commands.get_entity(connection_entity) else {
+            continue;
+        };
+
+        connection_commands.try_insert((DeltaSender::<T>::new(), DeltaReceiver::<T>::new()));
+    }
+}
+
+fn receive_delta_acks<C: Component, T: Send + Sync + 'static>(
+    mut connection_q: Query<(&mut DeltaSender<T>, &mut ReceivedDeltaAcks<T>, &Parent)>,
+    endpoint_q: Query<(), With<C>>,
+) {
+    for (mut sender, mut acks, connection_parent) in connection_q.iter_mut() {
+        if !endpoint_q.contains(connection_parent.get()) {
+            continue;
+        }
+
+        for DeltaAck { sequence, .. } in acks.drain() {
+            sender.acknowledge(sequence);
+        }
+    }
+}
+
+fn send_deltas<C: Component, T>(
+    history: Res<DeltaHistory<C>>,
+    mut messages: SendMessages<C>,
+    mut connection_q: Query<(Entity, &mut DeltaSender<T>, &Parent)>,
+    endpoint_q: Query<(), With<C>>,
+) where
+    T: Delta + Clone + Serialize + Send + Sync + 'static,
+{
+    for (connection_entity, mut sender, connection_parent) in connection_q.iter_mut() {
+        if !endpoint_q.contains(connection_parent.get()) {
+            continue;
+        }
+
+        let Some(state) = sender.pending.take() else {
+            continue;
+        };
+
+        let sequence = sender.next_sequence;
+
+        let encoding = sender.encode(&state);
+
+        let message = DeltaMessage { sequence, encoding };
+
+        match messages.send(connection_entity, &message) {
+            Ok(()) => (),
+            // try again once the message queue has been inserted
+            Err(QueueMessageError::NoConnection) => {
+                sender.pending = Some(state);
+                continue;
+            }
+            Err(err) => {
+                warn!(
+                    "failed to send a delta state to connection {:?}: {:?}",
+                    connection_entity, err
+                );
+                continue;
+            }
+        }
+
+        sender.record_sent(sequence, state, history.history);
+    }
+}
+
+fn receive_deltas<C: Component, T>(
+    history: Res<DeltaHistory<C>>,
+    mut messages: SendMessages<C>,
+    mut connection_q: Query<(
+        Entity,
+        &mut DeltaReceiver<T>,
+        &mut ReceivedDeltas<T>,
+        &Parent,
+    )>,
+    endpoint_q: Query<(), With<C>>,
+) where
+    T: Delta + Clone + Serialize + Send + Sync + 'static,
+{
+    for (connection_entity, mut receiver, mut received, connection_parent) in
+        connection_q.iter_mut()
+    {
+        if !endpoint_q.contains(connection_parent.get()) {
+            continue;
+        }
+
+        let DeltaReceiver {
+            states,
+            received: baselines,
+            unacked,
+        } = &mut *receiver;
+
+        for DeltaMessage { sequence, encoding } in received.drain() {
+            if baselines
+                .back()
+                .is_some_and(|&(latest, _)| latest >= sequence)
+            {
+                continue;
+            }
+
+            let state = match encoding {
+                DeltaEncoding::Full(state) => state,
+                DeltaEncoding::Delta { baseline, delta } => {
+                    let Some((_, baseline_state)) = baselines
+                        .iter()
+                        .find(|&&(received, _)| received == baseline)
+                    else {
+                        debug!(
+                            "connection {:?} sent a delta state with unknown baseline {}",
+                            connection_entity, baseline
+                        );
+                        continue;
+                    };
+
+                    baseline_state.apply_delta(delta)
+                }
+            };
+
+            states.push_back(state.clone());
+            baselines.push_back((sequence, state));
+            *unacked = Some(sequence);
+
+            while baselines.len() > history.history {
+                baselines.pop_front();
+            }
+        }
+
+        let Some(sequence) = *unacked else {
+            continue;
+        };
+
+        let message = DeltaAck::<T> {
+            sequence,
+            _p: PhantomData,
+        };
+
+        match messages.send(connection_entity, &message) {
+            Ok(()) => *unacked = None,
+            Err(err) => warn!(
+                "failed to acknowledge a delta state from connection {:?}: {:?}",
+                connection_entity, err
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct Counter(i32);
+
+    impl Delta for Counter {
+        type Delta = i32;
+
+        fn delta(&self, baseline: &Self) -> i32 {
+            self.0 - baseline.0
+        }
+
+        fn apply_delta(&self, delta: i32) -> Self {
+            Counter(self.0 + delta)
+        }
+    }
+
+    fn send(
+        sender: &mut DeltaSender<Counter>,
+        state: i32,
+        history: usize,
+    ) -> DeltaEncoding<Counter> {
+        let state = Counter(state);
+        let encoding = sender.encode(&state);
+        let sequence = sender.next_sequence;
+        sender.record_sent(sequence, state, history);
+        encoding
+    }
+
+    #[test]
+    fn first_state_is_sent_in_full() {
+        let mut sender = DeltaSender::new();
+
+        assert!(matches!(
+            send(&mut sender, 5, DEFAULT_DELTA_HISTORY),
+            DeltaEncoding::Full(Counter(5))
+        ));
+        assert_eq!(sender.baseline(), None);
+    }
+
+    #[test]
+    fn acknowledged_states_become_the_baseline() {
+        let mut sender = DeltaSender::new();
+        send(&mut sender, 5, DEFAULT_DELTA_HISTORY);
+        send(&mut sender, 7, DEFAULT_DELTA_HISTORY);
+
+        sender.acknowledge(1);
+
+        assert_eq!(sender.baseline(), Some(1));
+        assert_eq!(sender.sent.len(), 1);
+        assert!(matches!(
+            send(&mut sender, 10, DEFAULT_DELTA_HISTORY),
+            DeltaEncoding::Delta {
+                baseline: 1,
+                delta: 3
+            }
+        ));
+    }
+
+    #[test]
+    fn stale_and_unknown_acks_are_ignored() {
+        let mut sender = DeltaSender::new();
+        send(&mut sender, 5, DEFAULT_DELTA_HISTORY);
+        send(&mut sender, 7, DEFAULT_DELTA_HISTORY);
+
+        sender.acknowledge(1);
+        sender.acknowledge(0);
+        sender.acknowledge(9);
+
+        assert_eq!(sender.baseline(), Some(1));
+    }
+
+    #[test]
+    fn lost_baseline_falls_back_to_a_full_state() {
+        let history = 2;
+        let mut sender = DeltaSender::new();
+        send(&mut sender, 1, history);
+        sender.acknowledge(0);
+
+        // the peer stops acknowledging until the baseline leaves the history
+        send(&mut sender, 2, history);
+        assert_eq!(sender.baseline(), Some(0));
+        send(&mut sender, 3, history);
+        assert_eq!(sender.baseline(), None);
+
+        assert!(matches!(
+            send(&mut sender, 4, history),
+            DeltaEncoding::Full(Counter(4))
+        ));
+    }
+}
@@ -1,116 +1,408 @@
-use std::{collections::VecDeque, marker::PhantomData};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    marker::PhantomData,
+};
 
 use bevy::{
-    ecs::{intern::Interned, schedule::ScheduleLabel},
+    ecs::{entity::MapEntities, intern::Interned, schedule::ScheduleLabel},
     prelude::*,
 };
-use bevy_interface::prelude::*;
+use bevy_interface::{connections::StreamError, prelude::*};
 use serde::de::DeserializeOwned;
 
+use crate::{
+    assign_message_ids,
+    channel::{is_newer_sequence, ChannelConfig, ChannelMode, Datagram},
+    codec::{BincodeCodec, CodecError, MessageCodec},
+    entity_map::{map_message_entities, ConnectionEntityMap},
+    handshake::{ProtocolHandshake, HANDSHAKE_MESSAGE_ID},
+    message_id_from_name, varint, NamedMessage, DEFAULT_MAX_MESSAGE_SIZE,
+};
+
 /// Adds message deserialization functionality
-pub struct MessageDeserializationPlugin<C> {
-    _p: PhantomData<C>,
+///
+/// messages are deserialized with the codec `M`
+pub struct MessageDeserializationPlugin<C, M = BincodeCodec> {
+    _p: PhantomData<(C, M)>,
     schedule: Interned<dyn ScheduleLabel>,
-    messages: Vec<Box<dyn MessageIdBuilder<C>>>,
+    /// message types and their explicit message ids
+    messages: Vec<(Option<u16>, Box<dyn MessageIdBuilder<C>>)>,
+    max_message_size: usize,
+    error_policy: MessageErrorPolicy,
+    delivery: MessageDelivery,
+    channels: ChannelConfig,
+    /// inserts a [MessageEntityMapper] for each message type that has it's entities mapped
+    entity_mappers: Vec<fn(&mut App)>,
 }
 
 trait MessageIdBuilder<C>: Send + Sync + 'static {
-    fn build(&self, schedule: Interned<dyn ScheduleLabel>, message_id: u16, app: &mut App);
+    fn type_name(&self) -> &'static str;
+
+    fn build(
+        &self,
+        schedule: Interned<dyn ScheduleLabel>,
+        message_id: u16,
+        delivery: MessageDelivery,
+        app: &mut App,
+    );
 }
 
-struct MessageIdBuilderType<T> {
-    _p: PhantomData<T>,
+struct MessageIdBuilderType<T, M> {
+    _p: PhantomData<(T, M)>,
+    /// overrides the plugin's [MessageDelivery]
+    delivery: Option<MessageDelivery>,
 }
 
-impl<C> MessageDeserializationPlugin<C> {
+impl<C, M: MessageCodec> MessageDeserializationPlugin<C, M> {
     pub fn new(schedule: impl ScheduleLabel) -> Self {
         MessageDeserializationPlugin {
             _p: PhantomData,
             schedule: schedule.intern(),
             messages: Vec::new(),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            error_policy: MessageErrorPolicy::default(),
+            delivery: MessageDelivery::default(),
+            channels: ChannelConfig::new(),
+            entity_mappers: Vec::new(),
         }
     }
+
+    /// adds a channel that messages can be received on
+    ///
+    /// channels must be added in the same order on both ends of a connection.
+    /// panics if a channel with the same name was already added
+    pub fn add_channel(&mut self, name: &'static str, mode: ChannelMode) -> &mut Self {
+        self.channels.add_channel(name, mode);
+        self
+    }
+
+    pub(crate) fn set_channels(&mut self, channels: ChannelConfig) -> &mut Self {
+        self.channels = channels;
+        self
+    }
+
+    /// sets how received messages are given to the app
+    ///
+    /// defaults to [MessageDelivery::Component]
+    pub fn set_delivery(&mut self, delivery: MessageDelivery) -> &mut Self {
+        self.delivery = delivery;
+        self
+    }
+
+    /// sets the maximum size of a serialized message that will be received
+    ///
+    /// streams that try to send larger messages will stop being read.
+    /// defaults to [DEFAULT_MAX_MESSAGE_SIZE]
+    pub fn set_max_message_size(&mut self, max_message_size: usize) -> &mut Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// sets what happens to a connection when one of it's message streams breaks the message protocol
+    ///
+    /// defaults to [MessageErrorPolicy::DropStream]
+    pub fn set_error_policy(&mut self, error_policy: MessageErrorPolicy) -> &mut Self {
+        self.error_policy = error_policy;
+        self
+    }
 }
 
-impl<C: Component> MessageDeserializationPlugin<C> {
+impl<C: Component, M: MessageCodec> MessageDeserializationPlugin<C, M> {
     /// adds a message type to the plugin, assigning it the next message id
+    ///
+    /// the id is the number of messages added before it
     pub fn add_message<T: DeserializeOwned + Send + Sync + 'static>(&mut self) -> &mut Self {
-        self.messages
-            .push(Box::new(MessageIdBuilderType::<T> { _p: PhantomData }));
+        self.add_message_with_delivery::<T>(None, None)
+    }
+
+    /// adds a message type to the plugin with an explicit message id
+    pub fn add_message_with_id<T: DeserializeOwned + Send + Sync + 'static>(
+        &mut self,
+        message_id: u16,
+    ) -> &mut Self {
+        self.add_message_with_delivery::<T>(Some(message_id), None)
+    }
 
+    /// translates the entities in a message type to local entities
+    /// with the [ConnectionEntityMap] of the connection it was received on
+    ///
+    /// the message type still needs to be added
+    pub fn map_message_entities<T: MapEntities + Send + Sync + 'static>(&mut self) -> &mut Self {
+        self.entity_mappers.push(insert_entity_mapper::<C, T>);
         self
     }
+
+    /// adds a message type that is delivered differently to the rest of the plugin's messages
+    pub(crate) fn add_message_with_delivery<T: DeserializeOwned + Send + Sync + 'static>(
+        &mut self,
+        message_id: Option<u16>,
+        delivery: Option<MessageDelivery>,
+    ) -> &mut Self {
+        self.messages.push((
+            message_id,
+            Box::new(MessageIdBuilderType::<T, M> {
+                _p: PhantomData,
+                delivery,
+            }),
+        ));
+
+        self
+    }
+
+    /// adds a message type to the plugin with a message id derived from it's [NamedMessage::MESSAGE_NAME]
+    pub fn add_named_message<T: NamedMessage + DeserializeOwned + Send + Sync + 'static>(
+        &mut self,
+    ) -> &mut Self {
+        self.add_message_with_id::<T>(message_id_from_name(T::MESSAGE_NAME))
+    }
 }
 
-impl<C: Component> Plugin for MessageDeserializationPlugin<C> {
+impl<C: Component, M: MessageCodec> Plugin for MessageDeserializationPlugin<C, M> {
     fn build(&self, app: &mut App) {
+        app.add_event::<MessageStreamError>();
+
+        let message_ids = assign_message_ids(
+            self.messages
+                .iter()
+                .map(|(message_id, builder)| (*message_id, builder.type_name())),
+        );
+
+        app.insert_resource(DeserializationConfig::<C> {
+            _p: PhantomData,
+            max_message_size: self.max_message_size,
+            error_policy: self.error_policy,
+            channels: self.channels.modes(),
+            message_ids: message_ids.iter().copied().collect(),
+        });
+
         app.add_systems(
             self.schedule,
             (
                 insert_connection_components::<C>,
                 receive_message_streams::<C>,
                 read_message_streams::<C>,
+                read_datagrams::<C>,
+                handle_message_stream_errors::<C>,
             ),
         );
 
-        for (message_id, builder) in self.messages.iter().enumerate() {
-            builder.build(self.schedule, message_id as u16, app);
+        for ((_, builder), message_id) in self.messages.iter().zip(message_ids) {
+            builder.build(self.schedule, message_id, self.delivery, app);
+        }
+
+        for insert_entity_mapper in self.entity_mappers.iter() {
+            insert_entity_mapper(app);
         }
     }
 }
 
-impl<T: DeserializeOwned + Send + Sync + 'static, C: Component> MessageIdBuilder<C>
-    for MessageIdBuilderType<T>
+impl<T: DeserializeOwned + Send + Sync + 'static, C: Component, M: MessageCodec> MessageIdBuilder<C>
+    for MessageIdBuilderType<T, M>
 {
-    fn build(&self, schedule: Interned<dyn ScheduleLabel>, message_id: u16, app: &mut App) {
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
+
+    fn build(
+        &self,
+        schedule: Interned<dyn ScheduleLabel>,
+        message_id: u16,
+        delivery: MessageDelivery,
+        app: &mut App,
+    ) {
+        let delivery = self.delivery.unwrap_or(delivery);
+
+        app.add_event::<MessageReceived<T>>();
+
         app.insert_resource(MessageId::<C, T> {
             _p: PhantomData,
             message_id,
+            deserialize: M::deserialize::<T>,
+            delivery,
         });
 
-        app.add_systems(
-            schedule,
-            (
-                deserialize_messages::<C, T>,
-                insert_connection_message_type_components::<C, T>,
-            ),
-        );
+        app.add_systems(schedule, deserialize_messages::<C, T>);
+
+        if let MessageDelivery::Component = delivery {
+            app.add_systems(schedule, insert_connection_message_type_components::<C, T>);
+        }
     }
 }
 
+/// how received messages are given to the app
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MessageDelivery {
+    /// messages are pushed to the [ReceivedMessages] component of the connection
+    #[default]
+    Component,
+    /// messages are sent as [MessageReceived] events
+    Events,
+    /// [MessageReceived] is triggered for observers of the connection entity
+    Observers,
+}
+
+/// a message received on a connection
+///
+/// sent as an event or triggered on the connection entity depending on the [MessageDelivery]
+#[derive(Event)]
+pub struct MessageReceived<T> {
+    pub connection_entity: Entity,
+    pub message: T,
+}
+
 /// Insert onto an endpoint to specify which stream header to use for message streams
+///
+/// datagrams are also prefixed with this header,
+/// received datagrams that start with a different header are left for the app to receive
 #[derive(Component)]
 pub struct EndpointMessagingHeader {
     pub header: u16,
 }
 
+/// Insert onto an endpoint to specify how message streams
+/// are stopped when they break the message protocol
+///
+/// this should describe stopping a recv stream,
+/// see [CloneableDescription::new_recv_close]
+///
+/// without it streams that break the message protocol are only no longer read from
+#[derive(Component)]
+pub struct EndpointMessageStreamStopDescription {
+    pub description: CloneableDescription,
+}
+
 /// Contains the message id of a type
 #[derive(Resource)]
 struct MessageId<C, T> {
     _p: PhantomData<(C, T)>,
     message_id: u16,
+    /// deserializes the message with the protocol's codec
+    deserialize: fn(&[u8]) -> Result<T, CodecError>,
+    delivery: MessageDelivery,
+}
+
+/// Contains the function that maps the entities of a message type
+#[derive(Resource)]
+struct MessageEntityMapper<C, T> {
+    _p: PhantomData<(C, T)>,
+    map_entities: fn(&mut T, &ConnectionEntityMap),
+}
+
+fn insert_entity_mapper<C: Component, T: MapEntities + Send + Sync + 'static>(app: &mut App) {
+    app.insert_resource(MessageEntityMapper::<C, T> {
+        _p: PhantomData,
+        map_entities: map_message_entities::<T>,
+    });
+}
+
+/// Contains the configuration of a [MessageDeserializationPlugin]
+#[derive(Resource)]
+struct DeserializationConfig<C> {
+    _p: PhantomData<C>,
+    max_message_size: usize,
+    error_policy: MessageErrorPolicy,
+    /// the mode of each channel indexed by channel id
+    channels: Vec<ChannelMode>,
+    /// the ids of every message type that was added to the plugin
+    message_ids: HashSet<u16>,
+}
+
+/// the stream key given to messages received as datagrams
+const DATAGRAM_STREAM_KEY: u64 = u64::MAX;
+
+/// what happens to a connection when one of it's message streams
+/// breaks the message protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MessageErrorPolicy {
+    /// stop the stream and keep the connection open
+    ///
+    /// the stream is stopped with the endpoint's [EndpointMessageStreamStopDescription]
+    #[default]
+    DropStream,
+    /// stop reading from the stream and disconnect the connection
+    Disconnect,
+}
+
+/// fired when a message stream breaks the message protocol
+///
+/// the stream will be stopped,
+/// or the connection will be disconnected if the policy is [MessageErrorPolicy::Disconnect]
+#[derive(Event)]
+pub struct MessageStreamError {
+    pub endpoint_entity: Entity,
+    pub connection_entity: Entity,
+    pub stream_id: BevyStreamId,
+    pub error: ReadMessageError,
 }
 
 /// Contains open receive streams that are sending messages
 #[derive(Component, Default)]
 struct ConnectionMessageStreams {
-    /// Contains the stream, and the partially read message
-    streams: Vec<(BevyStreamId, ReadMessageState)>,
+    /// the key that will be given to the next stream
+    next_stream_key: u64,
+    /// Contains the stream key, the stream, and the partially read message
+    streams: Vec<(u64, BevyStreamId, ReadMessageState)>,
+    /// the newest sequence number received on each sequenced channel
+    sequences: HashMap<u8, u32>,
 }
 
 enum ReadMessageState {
+    /// reading the u16 message id and varint message length
     ReadingHeader(Vec<u8>),
     ReadingMessage {
         message_id: u16,
-        message_length: u16,
+        message_length: usize,
         buffer: Vec<u8>,
     },
 }
 
+/// a message id and the serialized message
+type SerializedMessage = (u16, Box<[u8]>);
+
+/// a stream that message bytes are read from
+trait MessageRecvStream {
+    fn recv(&mut self, limit: usize) -> Result<Box<[u8]>, Box<dyn StreamError>>;
+}
+
+impl MessageRecvStream for BevyRecvStream<'_> {
+    fn recv(&mut self, limit: usize) -> Result<Box<[u8]>, Box<dyn StreamError>> {
+        BevyRecvStream::recv(self, limit)
+    }
+}
+
+/// a message stream broke the message protocol and can't be read from anymore
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReadMessageError {
+    /// the stream failed with a fatal error
+    StreamError,
+    /// the message length was larger than the maximum message size
+    MessageTooLarge {
+        message_length: usize,
+        max_message_size: usize,
+    },
+    /// the message length wasn't a valid varint
+    MalformedLength,
+    /// the message id isn't the id of any message type added to the plugin
+    UnknownMessage { message_id: u16 },
+    /// the message couldn't be deserialized as the type for it's message id
+    MalformedMessage {
+        message_id: u16,
+        message_type: &'static str,
+    },
+}
+
+/// a serialized message and the key of the stream it was read from
+struct ReceivedSerializedMessage {
+    stream_key: u64,
+    message: Box<[u8]>,
+}
+
 /// contains received serialized messages for a connection
 #[derive(Component, Default)]
-struct ReceivedSerializedMessages {
-    buffers: Vec<VecDeque<Box<[u8]>>>,
+pub(crate) struct ReceivedSerializedMessages {
+    buffers: HashMap<u16, VecDeque<ReceivedSerializedMessage>>,
+    /// the last protocol fingerprint sent by the peer
+    handshake: Option<Box<[u8]>>,
 }
 
 /// contains received and deserialized messages for a connection
@@ -126,15 +418,30 @@ impl ReadMessageState {
         ReadMessageState::ReadingHeader(Vec::new())
     }
 
-    fn read(&mut self, stream: &mut BevyRecvStream) -> Option<(u16, Box<[u8]>)> {
+    fn read(
+        &mut self,
+        stream: &mut impl MessageRecvStream,
+        max_message_size: usize,
+    ) -> Result<Option<SerializedMessage>, ReadMessageError> {
         loop {
             match self {
                 ReadMessageState::ReadingHeader(buffer) => {
-                    let to_read = 4 - buffer.len();
+                    let length = match buffer.get(2..) {
+                        None => None,
+                        Some(length_bytes) => varint::decode(length_bytes)
+                            .map_err(|()| ReadMessageError::MalformedLength)?,
+                    };
 
-                    if to_read == 0 {
+                    if let Some((message_length, _)) = length {
                         let message_id = u16::from_be_bytes(buffer[0..2].try_into().unwrap());
-                        let message_length = u16::from_be_bytes(buffer[2..4].try_into().unwrap());
+                        let message_length = message_length as usize;
+
+                        if message_length > max_message_size {
+                            return Err(ReadMessageError::MessageTooLarge {
+                                message_length,
+                                max_message_size,
+                            });
+                        }
 
                         *self = ReadMessageState::ReadingMessage {
                             message_id,
@@ -145,14 +452,17 @@ impl ReadMessageState {
                         continue;
                     }
 
+                    // the varint length is read one byte at a time so that no message data is consumed
+                    let to_read = 2usize.saturating_sub(buffer.len()).max(1);
+
                     match stream.recv(to_read) {
                         Ok(bytes) => buffer.extend(bytes.as_ref()),
                         Err(err) => {
                             if err.is_fatal() {
-                                panic!("fatal error reading message header");
+                                break Err(ReadMessageError::StreamError);
                             }
 
-                            break None;
+                            break Ok(None);
                         }
                     }
                 }
@@ -161,25 +471,25 @@ impl ReadMessageState {
                     message_length,
                     buffer,
                 } => {
-                    let to_read = *message_length as usize - buffer.len();
+                    let to_read = *message_length - buffer.len();
 
                     if to_read == 0 {
                         let message_id = *message_id;
-                        let buffer = std::mem::replace(buffer, Vec::default()).into_boxed_slice();
+                        let buffer = std::mem::take(buffer).into_boxed_slice();
 
                         *self = ReadMessageState::new();
 
-                        break Some((message_id, buffer));
+                        break Ok(Some((message_id, buffer)));
                     }
 
                     match stream.recv(to_read) {
                         Ok(data) => buffer.extend(data.as_ref()),
                         Err(err) => {
                             if err.is_fatal() {
-                                panic!("fatal error reading message body");
+                                break Err(ReadMessageError::StreamError);
                             }
 
-                            break None;
+                            break Ok(None);
                         }
                     }
                 }
@@ -188,23 +498,50 @@ impl ReadMessageState {
     }
 }
 
-impl ReceivedSerializedMessages {
-    fn push_message(&mut self, message_id: u16, message: Box<[u8]>) {
-        let buffer = loop {
-            if let Some(buffer) = self.buffers.get_mut(message_id as usize) {
-                break buffer;
-            } else {
-                self.buffers.push(VecDeque::new());
-            }
-        };
+impl ConnectionMessageStreams {
+    fn push_stream(&mut self, stream_id: BevyStreamId) {
+        let stream_key = self.next_stream_key;
+        self.next_stream_key += 1;
 
-        buffer.push_back(message);
+        self.streams
+            .push((stream_key, stream_id, ReadMessageState::new()));
     }
 
-    fn poll_message_received(&mut self, message_id: u16) -> Option<Box<[u8]>> {
-        let buffer = self.buffers.get_mut(message_id as usize)?;
+    /// stops reading from a stream, returning it's id if it was still being read
+    fn remove_stream(&mut self, stream_key: u64) -> Option<BevyStreamId> {
+        let index = self
+            .streams
+            .iter()
+            .position(|(key, _, _)| *key == stream_key)?;
+
+        let (_, stream_id, _) = self.streams.swap_remove(index);
+        Some(stream_id)
+    }
+}
+
+impl ReceivedSerializedMessages {
+    fn push_message(&mut self, message_id: u16, message: ReceivedSerializedMessage) {
+        self.buffers
+            .entry(message_id)
+            .or_default()
+            .push_back(message);
+    }
+
+    fn poll_message_received(&mut self, message_id: u16) -> Option<ReceivedSerializedMessage> {
+        let buffer = self.buffers.get_mut(&message_id)?;
         buffer.pop_front()
     }
+
+    /// takes the protocol fingerprint sent by the peer if it has been received
+    pub(crate) fn take_handshake(&mut self) -> Option<Box<[u8]>> {
+        self.handshake.take()
+    }
+}
+
+impl<T> Default for ReceivedMessages<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T> ReceivedMessages<T> {
@@ -239,9 +576,15 @@ fn insert_connection_components<C: Component>(
             continue;
         }
 
-        commands.entity(connection_entity).insert((
+        // the connection may have been closed before this system ran
+        let Some(mut connection_commands) = commands.get_entity(connection_entity) else {
+            continue;
+        };
+
+        connection_commands.try_insert((
             ConnectionMessageStreams::default(),
             ReceivedSerializedMessages::default(),
+            ConnectionEntityMap::default(),
         ));
     }
 }
@@ -260,27 +603,129 @@ fn insert_connection_message_type_components<C: Component, T: Send + Sync + 'sta
             continue;
         }
 
-        commands
-            .entity(connection_entity)
-            .insert(ReceivedMessages::<T>::new());
+        // the connection may have been closed before this system ran
+        let Some(mut connection_commands) = commands.get_entity(connection_entity) else {
+            continue;
+        };
+
+        connection_commands.try_insert(ReceivedMessages::<T>::new());
     }
 }
 
-fn deserialize_messages<C: Send + Sync + 'static, T: DeserializeOwned + Send + Sync + 'static>(
+/// the components of a connection that [deserialize_messages] reads from and delivers to
+type DeserializeMessagesQuery<'a, T> = (
+    Entity,
+    &'a mut ConnectionMessageStreams,
+    &'a mut ReceivedSerializedMessages,
+    Option<&'a mut ReceivedMessages<T>>,
+    Option<&'a ConnectionEntityMap>,
+    &'a Parent,
+    Option<&'a ProtocolHandshake>,
+);
+
+fn deserialize_messages<C: Component, T: DeserializeOwned + Send + Sync + 'static>(
+    mut commands: Commands,
     message_id: Res<MessageId<C, T>>,
-    mut connection_q: Query<(&mut ReceivedSerializedMessages, &mut ReceivedMessages<T>)>,
+    entity_mapper: Option<Res<MessageEntityMapper<C, T>>>,
+    mut connection_q: Query<DeserializeMessagesQuery<T>>,
+    endpoint_q: Query<(), With<C>>,
+    mut error_w: EventWriter<MessageStreamError>,
+    mut received_w: EventWriter<MessageReceived<T>>,
 ) {
-    for (mut serialized_messages, mut deserialized_messages) in connection_q.iter_mut() {
-        while let Some(bytes) = serialized_messages.poll_message_received(message_id.message_id) {
-            let Ok(deserialized) = bincode::deserialize(bytes.as_ref()) else {
-                warn!(
-                    "failed to deserialize a \"{}\" message",
-                    std::any::type_name::<T>()
-                );
-                continue;
+    for (
+        connection_entity,
+        mut streams,
+        mut serialized_messages,
+        mut deserialized_messages,
+        entity_map,
+        connection_parent,
+        handshake,
+    ) in connection_q.iter_mut()
+    {
+        if !endpoint_q.contains(connection_parent.get()) {
+            continue;
+        }
+
+        // messages are held until the peer's protocol is known to match
+        if handshake.is_some_and(|handshake| *handshake != ProtocolHandshake::Verified) {
+            continue;
+        }
+
+        // messages are held until the component has been inserted
+        if message_id.delivery == MessageDelivery::Component && deserialized_messages.is_none() {
+            continue;
+        }
+
+        while let Some(ReceivedSerializedMessage {
+            stream_key,
+            message,
+        }) = serialized_messages.poll_message_received(message_id.message_id)
+        {
+            let mut deserialized = match (message_id.deserialize)(message.as_ref()) {
+                Ok(deserialized) => deserialized,
+                Err(err) if stream_key == DATAGRAM_STREAM_KEY => {
+                    warn!(
+                        "connection {:?} sent a \"{}\" datagram that couldn't be deserialized: {}",
+                        connection_entity,
+                        std::any::type_name::<T>(),
+                        err
+                    );
+
+                    continue;
+                }
+                Err(err) => {
+                    // the stream may have already been removed by an earlier error
+                    let Some(stream_id) = streams.remove_stream(stream_key) else {
+                        continue;
+                    };
+
+                    warn!(
+                        "connection {:?} sent a \"{}\" message that couldn't be deserialized, the stream will no longer be read: {}",
+                        connection_entity,
+                        std::any::type_name::<T>(),
+                        err
+                    );
+
+                    error_w.send(MessageStreamError {
+                        endpoint_entity: connection_parent.get(),
+                        connection_entity,
+                        stream_id,
+                        error: ReadMessageError::MalformedMessage {
+                            message_id: message_id.message_id,
+                            message_type: std::any::type_name::<T>(),
+                        },
+                    });
+
+                    continue;
+                }
             };
 
-            deserialized_messages.messages.push_back(deserialized);
+            if let (Some(entity_mapper), Some(entity_map)) = (entity_mapper.as_ref(), entity_map) {
+                (entity_mapper.map_entities)(&mut deserialized, entity_map);
+            }
+
+            match message_id.delivery {
+                MessageDelivery::Component => {
+                    if let Some(deserialized_messages) = deserialized_messages.as_mut() {
+                        deserialized_messages.messages.push_back(deserialized);
+                    }
+                }
+                MessageDelivery::Events => {
+                    received_w.send(MessageReceived {
+                        connection_entity,
+                        message: deserialized,
+                    });
+                }
+                MessageDelivery::Observers => {
+                    commands.trigger_targets(
+                        MessageReceived {
+                            connection_entity,
+                            message: deserialized,
+                        },
+                        connection_entity,
+                    );
+                }
+            }
         }
     }
 }
@@ -314,9 +759,7 @@ fn receive_message_streams<C: Component>(
             continue;
         };
 
-        streams
-            .streams
-            .push((stream_id.clone(), ReadMessageState::new()));
+        streams.push_stream(stream_id.clone());
     }
 }
 
@@ -329,6 +772,8 @@ fn read_message_streams<C: Component>(
         &Parent,
     )>,
     endpoint_q: Query<(), With<C>>,
+    config: Res<DeserializationConfig<C>>,
+    mut error_w: EventWriter<MessageStreamError>,
 ) {
     for (connection_entity, mut streams, mut serialized_messages, connection_parent) in
         connection_q.iter_mut()
@@ -353,7 +798,7 @@ fn read_message_streams<C: Component>(
             continue;
         };
 
-        streams.streams.retain_mut(|(stream_id, read_state)| {
+        streams.streams.retain_mut(|(stream_key, stream_id, read_state)| {
             let Some(mut stream) = connection
                 .recv_stream(stream_id.clone())
                 .expect("shouldn't mismatch stream id")
@@ -361,11 +806,372 @@ fn read_message_streams<C: Component>(
                 return false;
             };
 
-            while let Some((message_id, message)) = read_state.read(&mut stream) {
-                serialized_messages.push_message(message_id, message);
-            }
+            loop {
+                match read_state.read(&mut stream, config.max_message_size) {
+                    Ok(Some((HANDSHAKE_MESSAGE_ID, message))) => {
+                        serialized_messages.handshake = Some(message);
+                    }
+                    Ok(Some((message_id, _))) if !config.message_ids.contains(&message_id) => {
+                        warn!(
+                            "connection {:?} sent a message with unknown id {}, the stream will no longer be read",
+                            connection_entity, message_id
+                        );
+
+                        error_w.send(MessageStreamError {
+                            endpoint_entity: connection_parent.get(),
+                            connection_entity,
+                            stream_id: stream_id.clone(),
+                            error: ReadMessageError::UnknownMessage { message_id },
+                        });
+
+                        break false;
+                    }
+                    Ok(Some((message_id, message))) => {
+                        serialized_messages.push_message(
+                            message_id,
+                            ReceivedSerializedMessage {
+                                stream_key: *stream_key,
+                                message,
+                            },
+                        );
+                    }
+                    Ok(None) => break true,
+                    Err(error) => {
+                        warn!(
+                            "connection {:?} broke the message protocol, the stream will no longer be read: {:?}",
+                            connection_entity, error
+                        );
+
+                        error_w.send(MessageStreamError {
+                            endpoint_entity: connection_parent.get(),
+                            connection_entity,
+                            stream_id: stream_id.clone(),
+                            error,
+                        });
 
-            true
+                        break false;
+                    }
+                }
+            }
         });
     }
 }
+
+fn read_datagrams<C: Component>(
+    mut connections: Connections,
+    mut connection_q: Query<(
+        Entity,
+        &mut ConnectionMessageStreams,
+        &mut ReceivedSerializedMessages,
+        &Parent,
+    )>,
+    endpoint_q: Query<&EndpointMessagingHeader, With<C>>,
+    config: Res<DeserializationConfig<C>>,
+) {
+    for (connection_entity, mut streams, mut serialized_messages, connection_parent) in
+        connection_q.iter_mut()
+    {
+        let Ok(&EndpointMessagingHeader { header }) = endpoint_q.get(connection_parent.get())
+        else {
+            continue;
+        };
+
+        let Some(mut endpoint) = connections.connection_endpoint_mut(connection_entity) else {
+            continue;
+        };
+
+        let Some(mut connection) = endpoint.connection_mut(connection_entity) else {
+            continue;
+        };
+
+        // datagrams without the messaging header are left on the connection
+        while connection
+            .peek_datagram()
+            .and_then(Datagram::peek_header)
+            .is_some_and(|incoming_header| incoming_header == header)
+        {
+            let datagram = connection
+                .recv_datagram()
+                .expect("datagram was just peeked");
+
+            let Some(Datagram {
+                channel,
+                sequence,
+                message_id,
+                message,
+                ..
+            }) = Datagram::decode(&datagram, &config.channels)
+            else {
+                debug!(
+                    "connection {:?} sent a malformed message datagram",
+                    connection_entity
+                );
+                continue;
+            };
+
+            if !config.message_ids.contains(&message_id) {
+                debug!(
+                    "connection {:?} sent a datagram with unknown message id {}",
+                    connection_entity, message_id
+                );
+                continue;
+            }
+
+            if message.len() > config.max_message_size {
+                continue;
+            }
+
+            if let Some(sequence) = sequence {
+                // drop messages that arrive after a newer message on the same channel
+                if let Some(&last) = streams.sequences.get(&channel) {
+                    if !is_newer_sequence(sequence, last) {
+                        continue;
+                    }
+                }
+
+                streams.sequences.insert(channel, sequence);
+            }
+
+            serialized_messages.push_message(
+                message_id,
+                ReceivedSerializedMessage {
+                    stream_key: DATAGRAM_STREAM_KEY,
+                    message: message.into(),
+                },
+            );
+        }
+    }
+}
+
+/// stops message streams that have broken the message protocol,
+/// or disconnects their connection if the policy is [MessageErrorPolicy::Disconnect]
+fn handle_message_stream_errors<C: Component>(
+    mut connections: Connections,
+    mut error_r: EventReader<MessageStreamError>,
+    endpoint_q: Query<Option<&EndpointMessageStreamStopDescription>, With<C>>,
+    config: Res<DeserializationConfig<C>>,
+) {
+    for MessageStreamError {
+        endpoint_entity,
+        connection_entity,
+        stream_id,
+        ..
+    } in error_r.read()
+    {
+        let Ok(stop_description) = endpoint_q.get(*endpoint_entity) else {
+            continue;
+        };
+
+        if config.error_policy == MessageErrorPolicy::Disconnect {
+            connections.disconnect(*connection_entity);
+            continue;
+        }
+
+        let Some(stop_description) = stop_description else {
+            debug!(
+                "the endpoint of connection {:?} has no EndpointMessageStreamStopDescription, the errored stream won't be stopped",
+                connection_entity
+            );
+            continue;
+        };
+
+        let Some(mut endpoint) = connections.connection_endpoint_mut(*connection_entity) else {
+            continue;
+        };
+
+        let Some(mut connection) = endpoint.connection_mut(*connection_entity) else {
+            continue;
+        };
+
+        // the stream may have already been finished by the peer
+        let Ok(Some(mut stream)) = connection.recv_stream(stream_id.clone()) else {
+            continue;
+        };
+
+        if let Err(err) = stream.close(stop_description.description.clone().into()) {
+            error!(
+                "mismatched message stream stop description for connection {:?}: {:?}",
+                connection_entity, err
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct TestStreamError {
+        fatal: bool,
+    }
+
+    impl StreamError for TestStreamError {
+        fn is_fatal(&self) -> bool {
+            self.fatal
+        }
+
+        fn into_any(self: Box<Self>) -> Box<dyn std::any::Any> {
+            self
+        }
+    }
+
+    /// a stream that gives at most `chunk_size` bytes per read
+    struct TestStream {
+        data: VecDeque<u8>,
+        chunk_size: usize,
+        fatal: bool,
+    }
+
+    impl TestStream {
+        fn new(data: &[u8], chunk_size: usize) -> Self {
+            TestStream {
+                data: data.iter().copied().collect(),
+                chunk_size,
+                fatal: false,
+            }
+        }
+    }
+
+    impl MessageRecvStream for TestStream {
+        fn recv(&mut self, limit: usize) -> Result<Box<[u8]>, Box<dyn StreamError>> {
+            if self.data.is_empty() {
+                return Err(Box::new(TestStreamError { fatal: self.fatal }));
+            }
+
+            let bytes = limit.min(self.chunk_size).min(self.data.len());
+            Ok(self.data.drain(..bytes).collect())
+        }
+    }
+
+    fn frame(message_id: u16, message: &[u8]) -> Vec<u8> {
+        let mut buffer = message_id.to_be_bytes().to_vec();
+        varint::encode(message.len() as u32, &mut buffer);
+        buffer.extend(message);
+        buffer
+    }
+
+    /// reads every message in a stream until it blocks
+    fn read_all(
+        stream: &mut TestStream,
+        max_message_size: usize,
+    ) -> Result<Vec<SerializedMessage>, ReadMessageError> {
+        let mut state = ReadMessageState::new();
+        let mut messages = Vec::new();
+
+        while let Some(message) = state.read(stream, max_message_size)? {
+            messages.push(message);
+        }
+
+        Ok(messages)
+    }
+
+    #[test]
+    fn reads_framed_messages() {
+        let mut data = frame(3, b"hello");
+        data.extend(frame(7, &[]));
+        data.extend(frame(u16::MAX - 1, &[9; 300]));
+
+        for chunk_size in [1, 2, 3, usize::MAX] {
+            let messages = read_all(&mut TestStream::new(&data, chunk_size), 1024).unwrap();
+
+            assert_eq!(
+                messages,
+                vec![
+                    (3, b"hello".as_slice().into()),
+                    (7, [].as_slice().into()),
+                    (u16::MAX - 1, [9; 300].as_slice().into()),
+                ],
+                "chunk size {}",
+                chunk_size
+            );
+        }
+    }
+
+    #[test]
+    fn doesnt_consume_the_next_message() {
+        let mut data = frame(1, b"a");
+        data.extend(frame(2, b"b"));
+        let mut stream = TestStream::new(&data, usize::MAX);
+
+        let mut state = ReadMessageState::new();
+        assert_eq!(
+            state.read(&mut stream, 16),
+            Ok(Some((1, b"a".as_slice().into())))
+        );
+        assert_eq!(
+            stream.data.iter().copied().collect::<Vec<_>>(),
+            frame(2, b"b")
+        );
+    }
+
+    #[test]
+    fn truncated_message_waits_for_more_data() {
+        let data = frame(1, b"hello");
+
+        for length in 0..data.len() {
+            let mut stream = TestStream::new(&data[..length], usize::MAX);
+            let mut state = ReadMessageState::new();
+
+            assert_eq!(state.read(&mut stream, 16), Ok(None), "length {}", length);
+
+            stream.data.extend(&data[length..]);
+            assert_eq!(
+                state.read(&mut stream, 16),
+                Ok(Some((1, b"hello".as_slice().into())))
+            );
+        }
+    }
+
+    #[test]
+    fn max_message_size_is_inclusive() {
+        let data = frame(1, &[0; 128]);
+
+        assert_eq!(
+            read_all(&mut TestStream::new(&data, 1), 128).unwrap().len(),
+            1
+        );
+        assert_eq!(
+            read_all(&mut TestStream::new(&data, 1), 127),
+            Err(ReadMessageError::MessageTooLarge {
+                message_length: 128,
+                max_message_size: 127,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_the_largest_length() {
+        let mut data = 1u16.to_be_bytes().to_vec();
+        varint::encode(u32::MAX, &mut data);
+
+        assert_eq!(
+            read_all(&mut TestStream::new(&data, 1), DEFAULT_MAX_MESSAGE_SIZE),
+            Err(ReadMessageError::MessageTooLarge {
+                message_length: u32::MAX as usize,
+                max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_lengths() {
+        let data = [0, 1, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+
+        assert_eq!(
+            read_all(&mut TestStream::new(&data, 1), usize::MAX),
+            Err(ReadMessageError::MalformedLength)
+        );
+    }
+
+    #[test]
+    fn fatal_stream_errors() {
+        let mut stream = TestStream::new(&[0, 1], usize::MAX);
+        stream.fatal = true;
+
+        assert_eq!(
+            read_all(&mut stream, 16),
+            Err(ReadMessageError::StreamError)
+        );
+    }
+}
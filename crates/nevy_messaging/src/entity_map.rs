@@ -0,0 +1,58 @@
+//! translates the entities in received messages from the peer's entities to local entities
+//!
+//! messages are mapped with [MessageDeserializationPlugin::map_message_entities](crate::deserialize::MessageDeserializationPlugin::map_message_entities)
+//! using the [ConnectionEntityMap] of the connection they were received on
+
+use bevy::{
+    ecs::entity::{EntityHashMap, EntityMapper, MapEntities},
+    prelude::*,
+};
+
+/// maps the peer's entities to local entities for a connection
+///
+/// inserted onto connections by the [MessageDeserializationPlugin](crate::deserialize::MessageDeserializationPlugin).
+/// entities that aren't in the map are translated to [Entity::PLACEHOLDER]
+#[derive(Component, Default)]
+pub struct ConnectionEntityMap {
+    entities: EntityHashMap<Entity>,
+}
+
+impl ConnectionEntityMap {
+    /// maps a peer's entity to a local entity, returning the local entity it was previously mapped to
+    pub fn insert(&mut self, remote_entity: Entity, local_entity: Entity) -> Option<Entity> {
+        self.entities.insert(remote_entity, local_entity)
+    }
+
+    /// removes a peer's entity from the map, returning the local entity it was mapped to
+    pub fn remove(&mut self, remote_entity: Entity) -> Option<Entity> {
+        self.entities.remove(&remote_entity)
+    }
+
+    /// gets the local entity that a peer's entity is mapped to
+    pub fn get(&self, remote_entity: Entity) -> Option<Entity> {
+        self.entities.get(&remote_entity).copied()
+    }
+
+    /// returns `true` if a peer's entity is mapped to a local entity
+    pub fn contains(&self, remote_entity: Entity) -> bool {
+        self.entities.contains_key(&remote_entity)
+    }
+
+    /// iterates over the peer's entities and the local entities they are mapped to
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, Entity)> + '_ {
+        self.entities
+            .iter()
+            .map(|(&remote_entity, &local_entity)| (remote_entity, local_entity))
+    }
+}
+
+impl EntityMapper for &ConnectionEntityMap {
+    fn map_entity(&mut self, entity: Entity) -> Entity {
+        self.get(entity).unwrap_or(Entity::PLACEHOLDER)
+    }
+}
+
+/// maps the entities of a message with a connection's [ConnectionEntityMap]
+pub(crate) fn map_message_entities<T: MapEntities>(message: &mut T, map: &ConnectionEntityMap) {
+    message.map_entities(&mut &*map);
+}
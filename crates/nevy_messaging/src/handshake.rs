@@ -0,0 +1,421 @@
+//! verifies that both ends of a connection were built with the same protocol
+//!
+//! when a connection is established each end opens a message stream and sends
+//! the fingerprint of it's protocol, see [ProtocolBuilder::fingerprint](crate::ProtocolBuilder::fingerprint).
+//! received messages are held until the peer's fingerprint is known to match,
+//! and peers with a different fingerprint are disconnected
+//!
+//! the handshake plugin is included in [ProtocolBuilder::build_symmetric](crate::ProtocolBuilder::build_symmetric),
+//! so it runs on every connection unless a protocol is built without it on both ends
+
+use std::{marker::PhantomData, time::Duration};
+
+use bevy::{
+    ecs::{intern::Interned, schedule::ScheduleLabel},
+    prelude::*,
+};
+use bevy_interface::prelude::*;
+
+use crate::{
+    deserialize::{EndpointMessagingHeader, ReceivedSerializedMessages},
+    serialize::{
+        finish_stream, EndpointMessageStreamCloseDescription, EndpointMessageStreamDescription,
+        MessageStreamState,
+    },
+};
+
+pub use bevy_interface::prelude::PROTOCOL_MISMATCH_CODE;
+
+/// the message id reserved for the handshake message
+pub(crate) const HANDSHAKE_MESSAGE_ID: u16 = u16::MAX;
+
+/// the default time to wait for the peer's fingerprint before disconnecting it
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// sends the protocol fingerprint when a connection is established
+/// and disconnects peers with a different fingerprint
+///
+/// endpoints need an [EndpointMessagingHeader] and an [EndpointMessageStreamDescription],
+/// and should have an [EndpointMessageStreamCloseDescription] to finish the handshake stream.
+/// a [MessageDeserializationPlugin](crate::deserialize::MessageDeserializationPlugin)
+/// needs to be added for the same endpoints.
+/// timeouts are measured with [Time<Real>], which is advanced by bevy's `TimePlugin`
+pub struct ProtocolHandshakePlugin<C> {
+    _p: PhantomData<C>,
+    schedule: Interned<dyn ScheduleLabel>,
+    fingerprint: u64,
+    timeout: Option<Duration>,
+}
+
+impl<C> ProtocolHandshakePlugin<C> {
+    pub fn new(schedule: impl ScheduleLabel, fingerprint: u64) -> Self {
+        ProtocolHandshakePlugin {
+            _p: PhantomData,
+            schedule: schedule.intern(),
+            fingerprint,
+            timeout: Some(DEFAULT_HANDSHAKE_TIMEOUT),
+        }
+    }
+
+    /// sets how long to wait for the peer's fingerprint before disconnecting it
+    ///
+    /// `None` waits until the connection closes.
+    /// defaults to [DEFAULT_HANDSHAKE_TIMEOUT]
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl<C: Component> Plugin for ProtocolHandshakePlugin<C> {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ProtocolMismatch>();
+
+        app.insert_resource(ProtocolFingerprint::<C> {
+            _p: PhantomData,
+            fingerprint: self.fingerprint,
+            timeout: self.timeout,
+        });
+
+        app.add_systems(
+            self.schedule,
+            (
+                start_handshakes::<C>,
+                send_handshakes::<C>,
+                verify_handshakes::<C>,
+                time_out_handshakes::<C>,
+            ),
+        );
+    }
+}
+
+/// Contains the fingerprint of the local protocol
+#[derive(Resource)]
+struct ProtocolFingerprint<C> {
+    _p: PhantomData<C>,
+    fingerprint: u64,
+    timeout: Option<Duration>,
+}
+
+/// the state of the protocol handshake on a connection
+///
+/// received messages won't be deserialized until the handshake is [Verified](ProtocolHandshake::Verified)
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolHandshake {
+    /// waiting for the peer's fingerprint
+    Pending,
+    /// the peer's fingerprint matches
+    Verified,
+    /// the peer's fingerprint didn't match and the connection was closed
+    Mismatched,
+    /// the peer's fingerprint wasn't received before the timeout and the connection was closed
+    TimedOut,
+}
+
+/// sends the local fingerprint on a connection
+#[derive(Component)]
+struct HandshakeSender<C> {
+    stream: Option<MessageStreamState<C>>,
+}
+
+/// the [Time<Real>] elapsed time that a pending handshake times out at
+#[derive(Component)]
+struct HandshakeDeadline<C> {
+    _p: PhantomData<C>,
+    deadline: Duration,
+}
+
+/// fired when a peer's protocol fingerprint doesn't match
+///
+/// the connection will be disconnected with [PROTOCOL_MISMATCH_CODE],
+/// and both ends will report [DisconnectReason::ProtocolMismatch]
+#[derive(Event)]
+pub struct ProtocolMismatch {
+    pub endpoint_entity: Entity,
+    pub connection_entity: Entity,
+    pub fingerprint: u64,
+    /// `None` if the peer's handshake message was malformed
+    pub peer_fingerprint: Option<u64>,
+}
+
+/// a stable 64 bit FNV-1a hasher used for protocol fingerprints
+///
+/// [std::hash::DefaultHasher] isn't used because it's output may change between rust versions
+pub(crate) struct FingerprintHasher {
+    hash: u64,
+}
+
+impl FingerprintHasher {
+    pub(crate) fn new() -> Self {
+        FingerprintHasher {
+            hash: 0xcbf2_9ce4_8422_2325,
+        }
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.hash ^= byte as u64;
+            self.hash = self.hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    /// writes a length prefixed string
+    pub(crate) fn write_str(&mut self, string: &str) {
+        self.write(&(string.len() as u64).to_be_bytes());
+        self.write(string.as_bytes());
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+fn start_handshakes<C: Component>(
+    mut commands: Commands,
+    mut connected_r: EventReader<Connected>,
+    endpoint_q: Query<(), (With<C>, With<EndpointMessagingHeader>)>,
+    fingerprint: Res<ProtocolFingerprint<C>>,
+    time: Res<Time<Real>>,
+) {
+    for &Connected {
+        endpoint_entity,
+        connection_entity,
+    } in connected_r.read()
+    {
+        if !endpoint_q.contains(endpoint_entity) {
+            continue;
+        }
+
+        // the connection may have been closed before this system ran
+        let Some(mut connection_commands) = commands.get_entity(connection_entity) else {
+            continue;
+        };
+
+        connection_commands.try_insert((
+            ProtocolHandshake::Pending,
+            HandshakeSender::<C> { stream: None },
+        ));
+
+        if let Some(timeout) = fingerprint.timeout {
+            connection_commands.try_insert(HandshakeDeadline::<C> {
+                _p: PhantomData,
+                deadline: time.elapsed() + timeout,
+            });
+        }
+    }
+}
+
+/// the components of an endpoint that configure how the handshake stream is opened and finished
+type EndpointHandshakeStreamConfig<'a> = (
+    &'a EndpointMessagingHeader,
+    Option<&'a EndpointMessageStreamDescription>,
+    Option<&'a EndpointMessageStreamCloseDescription>,
+);
+
+fn send_handshakes<C: Component>(
+    mut commands: Commands,
+    mut connections: Connections,
+    mut connection_q: Query<(Entity, &mut HandshakeSender<C>, &Parent)>,
+    endpoint_q: Query<EndpointHandshakeStreamConfig, With<C>>,
+    fingerprint: Res<ProtocolFingerprint<C>>,
+) {
+    for (connection_entity, mut sender, connection_parent) in connection_q.iter_mut() {
+        let Ok((&EndpointMessagingHeader { header }, description, close_description)) =
+            endpoint_q.get(connection_parent.get())
+        else {
+            continue;
+        };
+
+        let Some(mut endpoint) = connections.connection_endpoint_mut(connection_entity) else {
+            continue;
+        };
+
+        let Some(mut connection) = endpoint.connection_mut(connection_entity) else {
+            continue;
+        };
+
+        let finished = match sender.stream.as_mut() {
+            Some(stream) => stream.flush(&mut connection),
+            None => {
+                let Some(description) = description else {
+                    error!(
+                        "endpoint {:?} has no EndpointMessageStreamDescription, the protocol handshake can't be sent to connection {:?}",
+                        connection_parent.get(),
+                        connection_entity
+                    );
+                    commands
+                        .entity(connection_entity)
+                        .remove::<HandshakeSender<C>>();
+                    continue;
+                };
+
+                let mut stream = match MessageStreamState::new(
+                    &mut connection,
+                    description.description.clone().into(),
+                    header,
+                ) {
+                    Ok(Some(stream)) => stream,
+                    // try again next update
+                    Ok(None) => continue,
+                    Err(err) => {
+                        error!(
+                            "mismatched handshake stream description for connection {:?}: {:?}",
+                            connection_entity, err
+                        );
+                        commands
+                            .entity(connection_entity)
+                            .remove::<HandshakeSender<C>>();
+                        continue;
+                    }
+                };
+
+                let result = stream
+                    .send_serialized(
+                        &mut connection,
+                        HANDSHAKE_MESSAGE_ID,
+                        &fingerprint.fingerprint.to_be_bytes(),
+                        usize::MAX,
+                    )
+                    .map(|_| stream.ready());
+
+                sender.stream = Some(stream);
+                result
+            }
+        };
+
+        match finished {
+            Ok(false) => (),
+            Ok(true) => {
+                commands
+                    .entity(connection_entity)
+                    .remove::<HandshakeSender<C>>();
+
+                let Some(stream) = sender.stream.take() else {
+                    continue;
+                };
+
+                match close_description {
+                    Some(close_description) => finish_stream(
+                        &mut connection,
+                        connection_entity,
+                        stream,
+                        close_description,
+                    ),
+                    None => debug!(
+                        "endpoint {:?} has no EndpointMessageStreamCloseDescription, the handshake stream of connection {:?} won't be finished",
+                        connection_parent.get(),
+                        connection_entity
+                    ),
+                }
+            }
+            Err(err) => {
+                warn!(
+                    "failed to send the protocol handshake to connection {:?}: {:?}",
+                    connection_entity, err
+                );
+                commands
+                    .entity(connection_entity)
+                    .remove::<HandshakeSender<C>>();
+            }
+        }
+    }
+}
+
+fn verify_handshakes<C: Component>(
+    mut connections: Connections,
+    mut connection_q: Query<(
+        Entity,
+        &mut ProtocolHandshake,
+        &mut ReceivedSerializedMessages,
+        &Parent,
+    )>,
+    endpoint_q: Query<(), With<C>>,
+    fingerprint: Res<ProtocolFingerprint<C>>,
+    mut mismatch_w: EventWriter<ProtocolMismatch>,
+) {
+    for (connection_entity, mut handshake, mut serialized_messages, connection_parent) in
+        connection_q.iter_mut()
+    {
+        if !endpoint_q.contains(connection_parent.get()) {
+            continue;
+        }
+
+        let Some(message) = serialized_messages.take_handshake() else {
+            continue;
+        };
+
+        if *handshake != ProtocolHandshake::Pending {
+            continue;
+        }
+
+        let peer_fingerprint = <[u8; 8]>::try_from(message.as_ref())
+            .ok()
+            .map(u64::from_be_bytes);
+
+        if peer_fingerprint == Some(fingerprint.fingerprint) {
+            *handshake = ProtocolHandshake::Verified;
+            continue;
+        }
+
+        warn!(
+            "connection {:?} has a different protocol fingerprint, disconnecting",
+            connection_entity
+        );
+
+        *handshake = ProtocolHandshake::Mismatched;
+
+        mismatch_w.send(ProtocolMismatch {
+            endpoint_entity: connection_parent.get(),
+            connection_entity,
+            fingerprint: fingerprint.fingerprint,
+            peer_fingerprint,
+        });
+
+        connections.disconnect_with_reason(
+            connection_entity,
+            PROTOCOL_MISMATCH_CODE,
+            b"protocol mismatch",
+        );
+    }
+}
+
+/// disconnects connections that haven't sent their fingerprint before the timeout
+fn time_out_handshakes<C: Component>(
+    mut commands: Commands,
+    mut connections: Connections,
+    mut connection_q: Query<(Entity, &mut ProtocolHandshake, &HandshakeDeadline<C>)>,
+    time: Res<Time<Real>>,
+) {
+    let elapsed = time.elapsed();
+
+    for (connection_entity, mut handshake, deadline) in connection_q.iter_mut() {
+        if *handshake != ProtocolHandshake::Pending {
+            commands
+                .entity(connection_entity)
+                .remove::<HandshakeDeadline<C>>();
+            continue;
+        }
+
+        if elapsed < deadline.deadline {
+            continue;
+        }
+
+        warn!(
+            "connection {:?} didn't send it's protocol fingerprint in time, disconnecting",
+            connection_entity
+        );
+
+        *handshake = ProtocolHandshake::TimedOut;
+
+        commands
+            .entity(connection_entity)
+            .remove::<HandshakeDeadline<C>>();
+
+        // a peer that never sends it's fingerprint most likely doesn't have the same protocol
+        connections.disconnect_with_reason(
+            connection_entity,
+            PROTOCOL_MISMATCH_CODE,
+            b"protocol handshake timed out",
+        );
+    }
+}
@@ -1,57 +1,484 @@
-use std::marker::PhantomData;
+use std::{collections::HashMap, marker::PhantomData, time::Duration};
 
-use bevy::{ecs::schedule::ScheduleLabel, prelude::*};
-use deserialize::MessageDeserializationPlugin;
+use bevy::{
+    ecs::{entity::MapEntities, schedule::ScheduleLabel},
+    prelude::*,
+};
+use channel::{ChannelConfig, ChannelMode};
+use clock::{ClockPing, ClockPong};
+use codec::{BincodeCodec, MessageCodec};
+use delta::{
+    Delta, DeltaAck, DeltaBuilder, DeltaBuilderType, DeltaMessage, DeltaPlugin,
+    DEFAULT_DELTA_HISTORY,
+};
+use deserialize::{MessageDelivery, MessageDeserializationPlugin, MessageErrorPolicy};
+use handshake::{
+    FingerprintHasher, ProtocolHandshakePlugin, DEFAULT_HANDSHAKE_TIMEOUT, HANDSHAKE_MESSAGE_ID,
+};
+use rpc::{
+    RpcBuilder, RpcBuilderType, RpcPlugin, RpcRequestMessage, RpcResponseMessage,
+    DEFAULT_RPC_TIMEOUT,
+};
 use serde::{de::DeserializeOwned, Serialize};
 use serialize::MessageSerializationPlugin;
 
+pub mod channel;
+pub mod clock;
+pub mod codec;
+pub mod delta;
 pub mod deserialize;
+pub mod entity_map;
+pub mod handshake;
+pub mod rpc;
 pub mod serialize;
+mod varint;
 
 pub mod prelude {
-    pub use crate::serialize::{MessageId, MessageSerializationPlugin, MessageStreamState};
+    pub use crate::channel::{ChannelMode, DEFAULT_CHANNEL};
+
+    pub use crate::clock::{
+        ClockServer, ClockSyncPlugin, ConnectionClock, NetworkClock, DEFAULT_PING_INTERVAL,
+        DEFAULT_TICK_DURATION,
+    };
+
+    pub use crate::codec::{BincodeCodec, CodecError, MessageCodec};
+
+    #[cfg(feature = "json")]
+    pub use crate::codec::JsonCodec;
+    #[cfg(feature = "messagepack")]
+    pub use crate::codec::MessagePackCodec;
+    #[cfg(feature = "postcard")]
+    pub use crate::codec::PostcardCodec;
+
+    pub use crate::delta::{Delta, DeltaPlugin, DeltaReceiver, DeltaSender, DEFAULT_DELTA_HISTORY};
+
+    pub use crate::serialize::{
+        EndpointMessageStreamCloseDescription, EndpointMessageStreamDescription, MessageId,
+        MessageSerializationPlugin, MessageStreamSendError, MessageStreamState, QueueMessageError,
+        SendMessages,
+    };
 
     pub use crate::deserialize::{
-        EndpointMessagingHeader, MessageDeserializationPlugin, ReceivedMessages,
+        EndpointMessageStreamStopDescription, EndpointMessagingHeader, MessageDelivery,
+        MessageDeserializationPlugin, MessageErrorPolicy, MessageReceived, MessageStreamError,
+        ReadMessageError, ReceivedMessages,
+    };
+
+    pub use crate::entity_map::ConnectionEntityMap;
+
+    pub use crate::handshake::{
+        ProtocolHandshake, ProtocolHandshakePlugin, ProtocolMismatch, DEFAULT_HANDSHAKE_TIMEOUT,
+        PROTOCOL_MISMATCH_CODE,
+    };
+
+    pub use crate::rpc::{
+        Rpc, RpcError, RpcHandle, RpcPlugin, RpcRequestId, RpcRequestReceived, DEFAULT_RPC_TIMEOUT,
     };
 
-    pub use crate::ProtocolBuilder;
+    pub use crate::{
+        message_id_from_name, NamedMessage, ProtocolBuilder, DEFAULT_MAX_MESSAGE_SIZE,
+        DEFAULT_MAX_QUEUED_MESSAGES,
+    };
+}
+
+/// the default maximum size of a serialized message in bytes
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+/// the default maximum number of messages that can be queued for a single connection
+pub const DEFAULT_MAX_QUEUED_MESSAGES: usize = 1024;
+
+/// gives a message type a stable name that it's message id is derived from
+///
+/// the id won't change when other messages are added or removed,
+/// see [ProtocolBuilder::add_named_message]
+pub trait NamedMessage {
+    const MESSAGE_NAME: &'static str;
 }
 
-pub struct ProtocolBuilder<C> {
-    messages: Vec<Box<dyn MessageAdder<C>>>,
+/// the message id derived from a message name, see [NamedMessage]
+pub fn message_id_from_name(name: &str) -> u16 {
+    let mut hasher = FingerprintHasher::new();
+    hasher.write(name.as_bytes());
+
+    // the last id is reserved for the protocol handshake
+    (hasher.finish() % HANDSHAKE_MESSAGE_ID as u64) as u16
 }
 
-trait MessageAdder<C> {
-    fn add_serializer(&self, plugin: &mut MessageSerializationPlugin<C>);
+/// resolves the message id of each message in the order they were added
+///
+/// messages without an explicit id use the number of messages added before them.
+/// panics if two messages have the same id or a message uses the id reserved for the handshake
+pub(crate) fn assign_message_ids(
+    messages: impl Iterator<Item = (Option<u16>, &'static str)>,
+) -> Vec<u16> {
+    let mut assigned = std::collections::HashMap::new();
+    let mut message_ids = Vec::new();
+
+    for (index, (message_id, type_name)) in messages.enumerate() {
+        let message_id = message_id.unwrap_or_else(|| index.try_into().unwrap_or(u16::MAX));
+
+        if message_id == HANDSHAKE_MESSAGE_ID {
+            panic!(
+                "message \"{}\" can't use message id {} because it is reserved",
+                type_name, message_id
+            );
+        }
+
+        if let Some(existing) = assigned.insert(message_id, type_name) {
+            panic!(
+                "messages \"{}\" and \"{}\" both have message id {}",
+                existing, type_name, message_id
+            );
+        }
+
+        message_ids.push(message_id);
+    }
+
+    message_ids
+}
 
-    fn add_deserializer(&self, plugin: &mut MessageDeserializationPlugin<C>);
+/// builds matching serialization and deserialization plugins for a protocol
+///
+/// messages are serialized with the codec `M`, which must be the same on both ends of a connection
+pub struct ProtocolBuilder<C, M = BincodeCodec> {
+    messages: MessageAdders<C, M>,
+    max_message_size: usize,
+    max_queued_messages: usize,
+    error_policy: MessageErrorPolicy,
+    delivery: MessageDelivery,
+    /// message type names and the [MessageDelivery] that overrides the protocol's
+    message_deliveries: HashMap<&'static str, MessageDelivery>,
+    version: u32,
+    requests: Vec<Box<dyn RpcBuilder<C>>>,
+    rpc_timeout: Option<Duration>,
+    handshake_timeout: Option<Duration>,
+    channels: ChannelConfig,
+    entity_mappers: Vec<fn(&mut MessageDeserializationPlugin<C, M>)>,
+    deltas: Vec<Box<dyn DeltaBuilder<C>>>,
+    delta_history: usize,
+}
+
+/// the plugins built by [ProtocolBuilder::build_symmetric]
+///
+/// contains the [MessageSerializationPlugin], the [MessageDeserializationPlugin],
+/// the [RpcPlugin] that routes responses to the protocol's requests
+/// and the [ProtocolHandshakePlugin] that disconnects peers built with a different protocol
+pub type ProtocolPlugins<C, M = BincodeCodec> = (
+    MessageSerializationPlugin<C, M>,
+    MessageDeserializationPlugin<C, M>,
+    RpcPlugin<C>,
+    ProtocolHandshakePlugin<C>,
+);
+
+/// message types and their explicit message ids
+type MessageAdders<C, M> = Vec<(Option<u16>, Box<dyn MessageAdder<C, M>>)>;
+
+trait MessageAdder<C, M> {
+    fn type_name(&self) -> &'static str;
+
+    fn add_serializer(
+        &self,
+        message_id: Option<u16>,
+        plugin: &mut MessageSerializationPlugin<C, M>,
+    );
+
+    fn add_deserializer(
+        &self,
+        message_id: Option<u16>,
+        delivery: Option<MessageDelivery>,
+        plugin: &mut MessageDeserializationPlugin<C, M>,
+    );
 }
 
 struct MessageAdderType<T> {
     _p: PhantomData<T>,
 }
 
-impl<C: Component> ProtocolBuilder<C> {
+impl<C: Component, M: MessageCodec> Default for ProtocolBuilder<C, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Component, M: MessageCodec> ProtocolBuilder<C, M> {
     pub fn new() -> Self {
         ProtocolBuilder {
             messages: Vec::new(),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            max_queued_messages: DEFAULT_MAX_QUEUED_MESSAGES,
+            error_policy: MessageErrorPolicy::default(),
+            delivery: MessageDelivery::default(),
+            message_deliveries: HashMap::new(),
+            version: 0,
+            requests: Vec::new(),
+            rpc_timeout: Some(DEFAULT_RPC_TIMEOUT),
+            handshake_timeout: Some(DEFAULT_HANDSHAKE_TIMEOUT),
+            channels: ChannelConfig::new(),
+            entity_mappers: Vec::new(),
+            deltas: Vec::new(),
+            delta_history: DEFAULT_DELTA_HISTORY,
+        }
+    }
+
+    /// sets an application defined version that is included in the protocol fingerprint
+    ///
+    /// defaults to `0`
+    pub fn set_version(&mut self, version: u32) -> &mut Self {
+        self.version = version;
+        self
+    }
+
+    /// computes a fingerprint of the version, codec, channels, and the type names, ids and channels of messages
+    ///
+    /// type names come from [std::any::type_name] so both ends of a connection
+    /// should be built with the same version of rust.
+    /// panics if message ids conflict
+    pub fn fingerprint(&self) -> u64 {
+        let message_ids = assign_message_ids(
+            self.messages
+                .iter()
+                .map(|(message_id, adder)| (*message_id, adder.type_name())),
+        );
+
+        let channels = self
+            .channels
+            .resolve(self.messages.iter().map(|(_, adder)| adder.type_name()));
+
+        let mut hasher = FingerprintHasher::new();
+
+        hasher.write(&self.version.to_be_bytes());
+        hasher.write_str(std::any::type_name::<M>());
+
+        hasher.write(&(self.channels.channels().len() as u64).to_be_bytes());
+        for &(name, mode) in self.channels.channels() {
+            hasher.write_str(name);
+            hasher.write(&[mode as u8]);
         }
+
+        hasher.write(&(self.messages.len() as u64).to_be_bytes());
+        for (((_, adder), message_id), channel) in
+            self.messages.iter().zip(message_ids).zip(channels)
+        {
+            hasher.write(&message_id.to_be_bytes());
+            hasher.write_str(adder.type_name());
+            hasher.write(&[channel]);
+        }
+
+        hasher.finish()
+    }
+
+    /// sets the maximum size of a serialized message for both sending and receiving
+    ///
+    /// defaults to [DEFAULT_MAX_MESSAGE_SIZE]
+    pub fn set_max_message_size(&mut self, max_message_size: usize) -> &mut Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// sets the maximum number of messages that can be queued for a single connection,
+    /// see [MessageSerializationPlugin::set_max_queued_messages]
+    ///
+    /// defaults to [DEFAULT_MAX_QUEUED_MESSAGES]
+    pub fn set_max_queued_messages(&mut self, max_queued_messages: usize) -> &mut Self {
+        self.max_queued_messages = max_queued_messages;
+        self
+    }
+
+    /// sets what happens to a connection when one of it's message streams breaks the message protocol
+    ///
+    /// defaults to [MessageErrorPolicy::DropStream]
+    pub fn set_error_policy(&mut self, error_policy: MessageErrorPolicy) -> &mut Self {
+        self.error_policy = error_policy;
+        self
+    }
+
+    /// sets how received messages are given to the app
+    ///
+    /// requests and responses aren't affected.
+    /// defaults to [MessageDelivery::Component]
+    pub fn set_delivery(&mut self, delivery: MessageDelivery) -> &mut Self {
+        self.delivery = delivery;
+        self
     }
 
+    /// sets how received messages of one type are given to the app,
+    /// overriding [set_delivery](Self::set_delivery)
+    pub fn set_message_delivery<T: 'static>(&mut self, delivery: MessageDelivery) -> &mut Self {
+        self.message_deliveries
+            .insert(std::any::type_name::<T>(), delivery);
+        self
+    }
+
+    /// translates the entities in a message type to local entities when it's received,
+    /// see [MessageDeserializationPlugin::map_message_entities]
+    pub fn map_message_entities<T: MapEntities + Send + Sync + 'static>(&mut self) -> &mut Self {
+        self.entity_mappers.push(|plugin| {
+            plugin.map_message_entities::<T>();
+        });
+        self
+    }
+
+    /// sets how long requests wait for a response before failing with [RpcError::TimedOut](rpc::RpcError::TimedOut)
+    ///
+    /// `None` waits until the connection closes.
+    /// defaults to [DEFAULT_RPC_TIMEOUT]
+    pub fn set_rpc_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.rpc_timeout = timeout;
+        self
+    }
+
+    /// sets how long the protocol handshake waits for the peer's fingerprint before disconnecting it
+    ///
+    /// `None` waits until the connection closes.
+    /// defaults to [DEFAULT_HANDSHAKE_TIMEOUT]
+    pub fn set_handshake_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    /// adds a channel that messages can be bound to with [set_message_channel](ProtocolBuilder::set_message_channel)
+    ///
+    /// panics if a channel with the same name was already added
+    pub fn add_channel(&mut self, name: &'static str, mode: ChannelMode) -> &mut Self {
+        self.channels.add_channel(name, mode);
+        self
+    }
+
+    /// binds a message type to a channel
+    ///
+    /// messages that aren't bound are sent on the [DEFAULT_CHANNEL](channel::DEFAULT_CHANNEL).
+    /// the plugins will panic when built if the channel or message type wasn't added
+    pub fn set_message_channel<T: 'static>(&mut self, channel: &'static str) -> &mut Self {
+        self.channels
+            .set_message_channel(std::any::type_name::<T>(), channel);
+        self
+    }
+
+    /// adds a message type, assigning it the next message id
+    ///
+    /// the id is the number of messages added before it
     pub fn add_message<T: Serialize + DeserializeOwned + Send + Sync + 'static>(
         &mut self,
     ) -> &mut Self {
         self.messages
-            .push(Box::new(MessageAdderType::<T> { _p: PhantomData }));
+            .push((None, Box::new(MessageAdderType::<T> { _p: PhantomData })));
+        self
+    }
+
+    /// adds a message type with an explicit message id
+    ///
+    /// ids are checked for duplicates when the plugins are built
+    pub fn add_message_with_id<T: Serialize + DeserializeOwned + Send + Sync + 'static>(
+        &mut self,
+        message_id: u16,
+    ) -> &mut Self {
+        self.messages.push((
+            Some(message_id),
+            Box::new(MessageAdderType::<T> { _p: PhantomData }),
+        ));
+        self
+    }
+
+    /// adds a message type with a message id derived from it's [NamedMessage::MESSAGE_NAME]
+    pub fn add_named_message<
+        T: NamedMessage + Serialize + DeserializeOwned + Send + Sync + 'static,
+    >(
+        &mut self,
+    ) -> &mut Self {
+        self.add_message_with_id::<T>(message_id_from_name(T::MESSAGE_NAME))
+    }
+
+    /// adds the messages used by the [ClockSyncPlugin](clock::ClockSyncPlugin), assigning them the next two message ids
+    pub fn add_clock_sync(&mut self) -> &mut Self {
+        self.add_message::<ClockPing>();
+        self.add_message::<ClockPong>();
+        self.set_message_delivery::<ClockPing>(MessageDelivery::Component);
+        self.set_message_delivery::<ClockPong>(MessageDelivery::Component);
         self
     }
 
-    pub fn build_serialization(&self) -> MessageSerializationPlugin<C> {
+    /// adds a request type and it's response type, assigning them the next two message ids
+    ///
+    /// requests are sent with [Rpc::request](rpc::Rpc::request)
+    /// and received as [RpcRequestReceived](rpc::RpcRequestReceived) events.
+    /// panics if the request type was already added, each request type has a single response type
+    pub fn add_request<Req, Resp>(&mut self) -> &mut Self
+    where
+        Req: Serialize + DeserializeOwned + Send + Sync + 'static,
+        Resp: Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        let request_message = std::any::type_name::<RpcRequestMessage<Req>>();
+        if self
+            .messages
+            .iter()
+            .any(|(_, adder)| adder.type_name() == request_message)
+        {
+            panic!(
+                "request \"{}\" was added more than once",
+                std::any::type_name::<Req>()
+            );
+        }
+
+        // the rpc systems read requests and responses from their components
+        self.messages.push((
+            None,
+            Box::new(MessageAdderType::<RpcRequestMessage<Req>> { _p: PhantomData }),
+        ));
+        self.messages.push((
+            None,
+            Box::new(MessageAdderType::<RpcResponseMessage<Req, Resp>> { _p: PhantomData }),
+        ));
+        self.set_message_delivery::<RpcRequestMessage<Req>>(MessageDelivery::Component);
+        self.set_message_delivery::<RpcResponseMessage<Req, Resp>>(MessageDelivery::Component);
+
+        self.requests
+            .push(Box::new(RpcBuilderType::<Req, Resp> { _p: PhantomData }));
+        self
+    }
+
+    /// adds a delta compressed state message and it's acknowledgement, assigning them the next two message ids
+    ///
+    /// states are sent with a connection's [DeltaSender](delta::DeltaSender)
+    /// and received with it's [DeltaReceiver](delta::DeltaReceiver),
+    /// which are inserted by the plugin built with [build_delta](ProtocolBuilder::build_delta)
+    pub fn add_delta_message<T>(&mut self) -> &mut Self
+    where
+        T: Delta + Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        self.messages.push((
+            None,
+            Box::new(MessageAdderType::<DeltaMessage<T>> { _p: PhantomData }),
+        ));
+        self.messages.push((
+            None,
+            Box::new(MessageAdderType::<DeltaAck<T>> { _p: PhantomData }),
+        ));
+        self.set_message_delivery::<DeltaMessage<T>>(MessageDelivery::Component);
+        self.set_message_delivery::<DeltaAck<T>>(MessageDelivery::Component);
+
+        self.deltas
+            .push(Box::new(DeltaBuilderType::<T> { _p: PhantomData }));
+        self
+    }
+
+    /// sets how many sent states are kept to be used as a baseline for delta messages,
+    /// which should be the same on both ends of a connection
+    ///
+    /// states are sent in full when none of the kept states have been acknowledged.
+    /// defaults to [DEFAULT_DELTA_HISTORY]
+    pub fn set_delta_history(&mut self, history: usize) -> &mut Self {
+        self.delta_history = history;
+        self
+    }
+
+    pub fn build_serialization(&self) -> MessageSerializationPlugin<C, M> {
         let mut plugin = MessageSerializationPlugin::new();
+        plugin.set_max_message_size(self.max_message_size);
+        plugin.set_max_queued_messages(self.max_queued_messages);
+        plugin.set_channels(self.channels.clone());
 
-        for adder in self.messages.iter() {
-            adder.add_serializer(&mut plugin);
+        for (message_id, adder) in self.messages.iter() {
+            adder.add_serializer(*message_id, &mut plugin);
         }
 
         plugin
@@ -60,38 +487,97 @@ impl<C: Component> ProtocolBuilder<C> {
     pub fn build_deserialization(
         &self,
         schedule: impl ScheduleLabel,
-    ) -> MessageDeserializationPlugin<C> {
+    ) -> MessageDeserializationPlugin<C, M> {
         let mut plugin = MessageDeserializationPlugin::new(schedule);
+        plugin.set_max_message_size(self.max_message_size);
+        plugin.set_error_policy(self.error_policy);
+        plugin.set_delivery(self.delivery);
+        plugin.set_channels(self.channels.clone());
+
+        for (message_id, adder) in self.messages.iter() {
+            let delivery = self.message_deliveries.get(adder.type_name()).copied();
+            adder.add_deserializer(*message_id, delivery, &mut plugin);
+        }
 
-        for adder in self.messages.iter() {
-            adder.add_deserializer(&mut plugin);
+        for map_message_entities in self.entity_mappers.iter() {
+            map_message_entities(&mut plugin);
         }
 
         plugin
     }
 
-    pub fn build_symmetric(
-        &self,
-        schedule: impl ScheduleLabel,
-    ) -> (
-        MessageSerializationPlugin<C>,
-        MessageDeserializationPlugin<C>,
-    ) {
+    /// builds the plugin that verifies the peer was built with the same protocol, see [handshake]
+    ///
+    /// it's included in [build_symmetric](Self::build_symmetric)
+    pub fn build_handshake(&self, schedule: impl ScheduleLabel) -> ProtocolHandshakePlugin<C> {
+        let mut plugin = ProtocolHandshakePlugin::new(schedule, self.fingerprint());
+        plugin.set_timeout(self.handshake_timeout);
+        plugin
+    }
+
+    /// builds the plugin that routes responses to requests added with [add_request](ProtocolBuilder::add_request)
+    pub fn build_rpc(&self, schedule: impl ScheduleLabel) -> RpcPlugin<C> {
+        RpcPlugin::new(
+            schedule,
+            self.requests
+                .iter()
+                .map(|builder| builder.clone_builder())
+                .collect(),
+            self.rpc_timeout,
+        )
+    }
+
+    /// builds the plugin that sends and receives the messages added with [add_delta_message](ProtocolBuilder::add_delta_message)
+    pub fn build_delta(&self, schedule: impl ScheduleLabel) -> DeltaPlugin<C> {
+        DeltaPlugin::new(
+            schedule,
+            self.deltas
+                .iter()
+                .map(|builder| builder.clone_builder())
+                .collect(),
+            self.delta_history,
+        )
+    }
+
+    /// builds the serialization, deserialization, rpc and handshake plugins, see [ProtocolPlugins]
+    ///
+    /// to opt out of the protocol handshake add the plugins built by
+    /// [build_serialization](Self::build_serialization), [build_deserialization](Self::build_deserialization)
+    /// and [build_rpc](Self::build_rpc) instead, on both ends of a connection
+    pub fn build_symmetric(&self, schedule: impl ScheduleLabel + Clone) -> ProtocolPlugins<C, M> {
         (
             self.build_serialization(),
-            self.build_deserialization(schedule),
+            self.build_deserialization(schedule.clone()),
+            self.build_rpc(schedule.clone()),
+            self.build_handshake(schedule),
         )
     }
 }
 
-impl<C: Component, T: Serialize + DeserializeOwned + Send + Sync + 'static> MessageAdder<C>
-    for MessageAdderType<T>
+impl<C: Component, M: MessageCodec, T: Serialize + DeserializeOwned + Send + Sync + 'static>
+    MessageAdder<C, M> for MessageAdderType<T>
 {
-    fn add_serializer(&self, plugin: &mut MessageSerializationPlugin<C>) {
-        plugin.add_message::<T>();
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
     }
 
-    fn add_deserializer(&self, plugin: &mut MessageDeserializationPlugin<C>) {
-        plugin.add_message::<T>();
+    fn add_serializer(
+        &self,
+        message_id: Option<u16>,
+        plugin: &mut MessageSerializationPlugin<C, M>,
+    ) {
+        match message_id {
+            Some(message_id) => plugin.add_message_with_id::<T>(message_id),
+            None => plugin.add_message::<T>(),
+        };
+    }
+
+    fn add_deserializer(
+        &self,
+        message_id: Option<u16>,
+        delivery: Option<MessageDelivery>,
+        plugin: &mut MessageDeserializationPlugin<C, M>,
+    ) {
+        plugin.add_message_with_delivery::<T>(message_id, delivery);
     }
 }
@@ -0,0 +1,604 @@
+//! request/response messaging
+//!
+//! requests are added with [ProtocolBuilder::add_request](crate::ProtocolBuilder::add_request)
+//! and sent with [Rpc::request], which returns an [RpcHandle] that resolves when the response arrives.
+//! received requests are fired as [RpcRequestReceived] events and answered with [Rpc::respond]
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use bevy::{
+    ecs::{intern::Interned, schedule::ScheduleLabel, system::SystemParam},
+    prelude::*,
+};
+use bevy_interface::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    deserialize::ReceivedMessages,
+    serialize::{QueueMessageError, SendMessages},
+};
+
+/// the default time to wait for a response before a request fails with [RpcError::TimedOut]
+pub const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// routes responses to the requests added with [ProtocolBuilder::add_request](crate::ProtocolBuilder::add_request)
+///
+/// built with [ProtocolBuilder::build_rpc](crate::ProtocolBuilder::build_rpc)
+pub struct RpcPlugin<C> {
+    _p: PhantomData<C>,
+    schedule: Interned<dyn ScheduleLabel>,
+    requests: Vec<Box<dyn RpcBuilder<C>>>,
+    timeout: Option<Duration>,
+}
+
+pub(crate) trait RpcBuilder<C>: Send + Sync + 'static {
+    fn clone_builder(&self) -> Box<dyn RpcBuilder<C>>;
+
+    fn build(&self, schedule: Interned<dyn ScheduleLabel>, app: &mut App);
+}
+
+pub(crate) struct RpcBuilderType<Req, Resp> {
+    pub(crate) _p: PhantomData<(Req, Resp)>,
+}
+
+impl<C> RpcPlugin<C> {
+    pub(crate) fn new(
+        schedule: impl ScheduleLabel,
+        requests: Vec<Box<dyn RpcBuilder<C>>>,
+        timeout: Option<Duration>,
+    ) -> Self {
+        RpcPlugin {
+            _p: PhantomData,
+            schedule: schedule.intern(),
+            requests,
+            timeout,
+        }
+    }
+}
+
+impl<C: Component> Plugin for RpcPlugin<C> {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(RpcRequests::<C> {
+            _p: PhantomData,
+            next_request_id: 0,
+            timeout: self.timeout,
+            pending: HashMap::new(),
+        });
+
+        for builder in self.requests.iter() {
+            builder.build(self.schedule, app);
+        }
+    }
+}
+
+impl<Req, Resp, C: Component> RpcBuilder<C> for RpcBuilderType<Req, Resp>
+where
+    Req: Serialize + DeserializeOwned + Send + Sync + 'static,
+    Resp: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    fn clone_builder(&self) -> Box<dyn RpcBuilder<C>> {
+        Box::new(RpcBuilderType::<Req, Resp> { _p: PhantomData })
+    }
+
+    fn build(&self, schedule: Interned<dyn ScheduleLabel>, app: &mut App) {
+        app.add_event::<RpcRequestReceived<Req>>();
+
+        app.world_mut()
+            .resource_mut::<RpcRequests<C>>()
+            .pending
+            .insert(
+                TypeId::of::<RpcResponseMessage<Req, Resp>>(),
+                Box::new(PendingRequests::<Resp>(HashMap::new())),
+            );
+
+        app.add_systems(
+            schedule,
+            (
+                receive_requests::<C, Req>,
+                // responses received this update finish their requests before they can time out
+                (
+                    receive_responses::<C, Req, Resp>,
+                    update_pending_requests::<C, Req, Resp>,
+                )
+                    .chain(),
+            ),
+        );
+    }
+}
+
+/// the message a request is sent as
+#[derive(Serialize, Deserialize)]
+pub(crate) struct RpcRequestMessage<Req> {
+    request_id: u64,
+    request: Req,
+}
+
+/// the message a response is sent as
+///
+/// generic over the request so that responses to different requests with the same type don't share a message id
+#[derive(Serialize, Deserialize)]
+pub(crate) struct RpcResponseMessage<Req, Resp> {
+    request_id: u64,
+    response: Resp,
+    #[serde(skip)]
+    _p: PhantomData<Req>,
+}
+
+type ReceivedResponses<Req, Resp> = ReceivedMessages<RpcResponseMessage<Req, Resp>>;
+
+/// Contains the requests that are waiting for a response
+#[derive(Resource)]
+struct RpcRequests<C> {
+    _p: PhantomData<C>,
+    next_request_id: u64,
+    timeout: Option<Duration>,
+    /// boxed [PendingRequests] keyed by the type id of the response message
+    pending: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl<C> RpcRequests<C> {
+    fn pending_mut<Req: 'static, Resp: 'static>(&mut self) -> Option<&mut PendingRequests<Resp>> {
+        self.pending
+            .get_mut(&TypeId::of::<RpcResponseMessage<Req, Resp>>())
+            .and_then(|pending| pending.downcast_mut())
+    }
+}
+
+/// requests waiting for a response, keyed by their connection and request id
+struct PendingRequests<Resp>(HashMap<(Entity, u64), PendingRequest<Resp>>);
+
+struct PendingRequest<Resp> {
+    slot: Arc<Mutex<RpcSlot<Resp>>>,
+    /// the [Time<Real>] elapsed time that the request times out at
+    deadline: Option<Duration>,
+}
+
+/// the state shared between a [PendingRequest] and it's [RpcHandle]
+struct RpcSlot<Resp> {
+    result: Option<Result<Resp, RpcError>>,
+    finished: bool,
+    waker: Option<Waker>,
+}
+
+impl<Resp> RpcSlot<Resp> {
+    fn finish(&mut self, result: Result<Resp, RpcError>) {
+        if self.finished {
+            return;
+        }
+
+        self.finished = true;
+        self.result = Some(result);
+
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// the reason a request didn't receive a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpcError {
+    /// no response was received before the request's timeout
+    TimedOut,
+    /// the request was cancelled with [RpcHandle::cancel]
+    Cancelled,
+    /// the connection closed before a response was received
+    Disconnected,
+}
+
+/// a handle to a request sent with [Rpc::request]
+///
+/// the response can be polled with [RpcHandle::poll_response] or awaited.
+/// dropping the handle cancels the request
+pub struct RpcHandle<Resp> {
+    slot: Arc<Mutex<RpcSlot<Resp>>>,
+}
+
+impl<Resp> RpcHandle<Resp> {
+    /// takes the response if the request has finished
+    ///
+    /// returns `None` if the request is still waiting for a response or the result was already taken
+    pub fn poll_response(&self) -> Option<Result<Resp, RpcError>> {
+        self.slot.lock().unwrap().result.take()
+    }
+
+    /// returns `true` if the request has received a response, timed out, been cancelled or disconnected
+    pub fn is_finished(&self) -> bool {
+        self.slot.lock().unwrap().finished
+    }
+
+    /// cancels the request, a response that arrives later will be ignored
+    ///
+    /// does nothing if the request has already finished
+    pub fn cancel(&self) {
+        self.slot.lock().unwrap().finish(Err(RpcError::Cancelled));
+    }
+}
+
+impl<Resp> Future for RpcHandle<Resp> {
+    type Output = Result<Resp, RpcError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.slot.lock().unwrap();
+
+        if let Some(result) = slot.result.take() {
+            return Poll::Ready(result);
+        }
+
+        if slot.finished {
+            panic!("RpcHandle polled after it's result was taken");
+        }
+
+        slot.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// identifies a received request, used to send a response with [Rpc::respond]
+pub struct RpcRequestId<Req> {
+    _p: PhantomData<fn() -> Req>,
+    request_id: u64,
+}
+
+impl<Req> Clone for RpcRequestId<Req> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Req> Copy for RpcRequestId<Req> {}
+
+impl<Req> std::fmt::Debug for RpcRequestId<Req> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("RpcRequestId")
+            .field(&self.request_id)
+            .finish()
+    }
+}
+
+/// fired when a request is received
+///
+/// respond with [Rpc::respond] using the [RpcRequestId]
+#[derive(Event)]
+pub struct RpcRequestReceived<Req> {
+    pub endpoint_entity: Entity,
+    pub connection_entity: Entity,
+    pub request_id: RpcRequestId<Req>,
+    pub request: Req,
+}
+
+/// system param for sending requests and responses
+///
+/// requests and responses are queued with [SendMessages]
+#[derive(SystemParam)]
+pub struct Rpc<'w, 's, C: Component> {
+    send_messages: SendMessages<'w, 's, C>,
+    requests: ResMut<'w, RpcRequests<C>>,
+    time: Res<'w, Time<Real>>,
+}
+
+impl<'w, 's, C: Component> Rpc<'w, 's, C> {
+    /// sends a request to a connection with the timeout set on the [ProtocolBuilder](crate::ProtocolBuilder)
+    pub fn request<Req, Resp>(
+        &mut self,
+        connection_entity: Entity,
+        request: Req,
+    ) -> Result<RpcHandle<Resp>, QueueMessageError>
+    where
+        Req: Serialize + Send + Sync + 'static,
+        Resp: Send + Sync + 'static,
+    {
+        let timeout = self.requests.timeout;
+        self.request_with_timeout(connection_entity, request, timeout)
+    }
+
+    /// sends a request to a connection that fails if no response is received within `timeout`
+    ///
+    /// a timeout of `None` waits until the connection closes
+    pub fn request_with_timeout<Req, Resp>(
+        &mut self,
+        connection_entity: Entity,
+        request: Req,
+        timeout: Option<Duration>,
+    ) -> Result<RpcHandle<Resp>, QueueMessageError>
+    where
+        Req: Serialize + Send + Sync + 'static,
+        Resp: Send + Sync + 'static,
+    {
+        let request_id = self.requests.next_request_id;
+
+        let Some(pending) = self.requests.pending_mut::<Req, Resp>() else {
+            return Err(QueueMessageError::UnknownMessage);
+        };
+
+        self.send_messages.send(
+            connection_entity,
+            &RpcRequestMessage {
+                request_id,
+                request,
+            },
+        )?;
+
+        let slot = Arc::new(Mutex::new(RpcSlot {
+            result: None,
+            finished: false,
+            waker: None,
+        }));
+
+        pending.0.insert(
+            (connection_entity, request_id),
+            PendingRequest {
+                slot: slot.clone(),
+                deadline: timeout.map(|timeout| self.time.elapsed() + timeout),
+            },
+        );
+
+        self.requests.next_request_id += 1;
+
+        Ok(RpcHandle { slot })
+    }
+
+    /// sends the response to a received request
+    pub fn respond<Req, Resp>(
+        &mut self,
+        connection_entity: Entity,
+        request_id: RpcRequestId<Req>,
+        response: Resp,
+    ) -> Result<(), QueueMessageError>
+    where
+        Req: Send + Sync + 'static,
+        Resp: Serialize + Send + Sync + 'static,
+    {
+        self.send_messages.send(
+            connection_entity,
+            &RpcResponseMessage::<Req, Resp> {
+                request_id: request_id.request_id,
+                response,
+                _p: PhantomData,
+            },
+        )
+    }
+}
+
+fn receive_requests<C: Component, Req: Send + Sync + 'static>(
+    mut connection_q: Query<(
+        Entity,
+        &mut ReceivedMessages<RpcRequestMessage<Req>>,
+        &Parent,
+    )>,
+    endpoint_q: Query<(), With<C>>,
+    mut request_w: EventWriter<RpcRequestReceived<Req>>,
+) {
+    for (connection_entity, mut messages, connection_parent) in connection_q.iter_mut() {
+        if !endpoint_q.contains(connection_parent.get()) {
+            continue;
+        }
+
+        for RpcRequestMessage {
+            request_id,
+            request,
+        } in messages.drain()
+        {
+            request_w.send(RpcRequestReceived {
+                endpoint_entity: connection_parent.get(),
+                connection_entity,
+                request_id: RpcRequestId {
+                    _p: PhantomData,
+                    request_id,
+                },
+                request,
+            });
+        }
+    }
+}
+
+fn receive_responses<C: Component, Req: Send + Sync + 'static, Resp: Send + Sync + 'static>(
+    mut requests: ResMut<RpcRequests<C>>,
+    mut connection_q: Query<(Entity, &mut ReceivedResponses<Req, Resp>, &Parent)>,
+    endpoint_q: Query<(), With<C>>,
+) {
+    let Some(pending) = requests.pending_mut::<Req, Resp>() else {
+        return;
+    };
+
+    for (connection_entity, mut messages, connection_parent) in connection_q.iter_mut() {
+        if !endpoint_q.contains(connection_parent.get()) {
+            continue;
+        }
+
+        for RpcResponseMessage {
+            request_id,
+            response,
+            ..
+        } in messages.drain()
+        {
+            // the request may have already timed out or been cancelled
+            let Some(request) = pending.0.remove(&(connection_entity, request_id)) else {
+                debug!(
+                    "connection {:?} responded to unknown request {}",
+                    connection_entity, request_id
+                );
+                continue;
+            };
+
+            request.slot.lock().unwrap().finish(Ok(response));
+        }
+    }
+}
+
+/// fails requests that timed out or whose connection closed,
+/// and forgets requests that were cancelled
+fn update_pending_requests<
+    C: Component,
+    Req: Send + Sync + 'static,
+    Resp: Send + Sync + 'static,
+>(
+    mut requests: ResMut<RpcRequests<C>>,
+    connection_q: Query<(), With<BevyConnection>>,
+    time: Res<Time<Real>>,
+) {
+    let Some(pending) = requests.pending_mut::<Req, Resp>() else {
+        return;
+    };
+
+    let elapsed = time.elapsed();
+
+    pending.0.retain(|&(connection_entity, _), request| {
+        let mut slot = request.slot.lock().unwrap();
+
+        if !connection_q.contains(connection_entity) {
+            slot.finish(Err(RpcError::Disconnected));
+        } else if request.deadline.is_some_and(|deadline| elapsed >= deadline) {
+            slot.finish(Err(RpcError::TimedOut));
+        }
+
+        // the handle has been dropped
+        if Arc::strong_count(&request.slot) == 1 {
+            return false;
+        }
+
+        !slot.finished
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::ProtocolBuilder;
+
+    #[derive(Component)]
+    struct TestProtocol;
+
+    #[derive(Serialize, Deserialize)]
+    struct Request;
+
+    /// a world with the pending requests of a `Request` that is answered with a `u32`
+    fn pending_world() -> World {
+        let mut world = World::new();
+
+        world.init_resource::<Time<Real>>();
+
+        let mut pending = HashMap::<TypeId, Box<dyn Any + Send + Sync>>::new();
+        pending.insert(
+            TypeId::of::<RpcResponseMessage<Request, u32>>(),
+            Box::new(PendingRequests::<u32>(HashMap::new())),
+        );
+
+        world.insert_resource(RpcRequests::<TestProtocol> {
+            _p: PhantomData,
+            next_request_id: 0,
+            timeout: None,
+            pending,
+        });
+
+        world
+    }
+
+    /// adds a pending request and returns it's handle
+    fn add_pending(
+        world: &mut World,
+        connection_entity: Entity,
+        request_id: u64,
+    ) -> RpcHandle<u32> {
+        let slot = Arc::new(Mutex::new(RpcSlot {
+            result: None,
+            finished: false,
+            waker: None,
+        }));
+
+        world
+            .resource_mut::<RpcRequests<TestProtocol>>()
+            .pending_mut::<Request, u32>()
+            .unwrap()
+            .0
+            .insert(
+                (connection_entity, request_id),
+                PendingRequest {
+                    slot: slot.clone(),
+                    deadline: None,
+                },
+            );
+
+        RpcHandle { slot }
+    }
+
+    fn pending_requests(world: &mut World) -> Vec<u64> {
+        let mut request_ids: Vec<u64> = world
+            .resource_mut::<RpcRequests<TestProtocol>>()
+            .pending_mut::<Request, u32>()
+            .unwrap()
+            .0
+            .keys()
+            .map(|&(_, request_id)| request_id)
+            .collect();
+
+        request_ids.sort();
+        request_ids
+    }
+
+    fn update_pending(world: &mut World) {
+        world
+            .run_system_once(update_pending_requests::<TestProtocol, Request, u32>)
+            .unwrap();
+    }
+
+    #[test]
+    fn dropped_handles_are_forgotten() {
+        let mut world = pending_world();
+        let connection_entity = world.spawn(BevyConnection).id();
+
+        let kept = add_pending(&mut world, connection_entity, 0);
+        drop(add_pending(&mut world, connection_entity, 1));
+
+        update_pending(&mut world);
+
+        assert_eq!(pending_requests(&mut world), vec![0]);
+        assert!(!kept.is_finished());
+    }
+
+    #[test]
+    fn cancelled_requests_are_forgotten() {
+        let mut world = pending_world();
+        let connection_entity = world.spawn(BevyConnection).id();
+
+        let handle = add_pending(&mut world, connection_entity, 0);
+        handle.cancel();
+
+        update_pending(&mut world);
+
+        assert!(pending_requests(&mut world).is_empty());
+        assert_eq!(handle.poll_response(), Some(Err(RpcError::Cancelled)));
+    }
+
+    #[test]
+    fn requests_fail_when_their_connection_closes() {
+        let mut world = pending_world();
+        let connection_entity = world.spawn_empty().id();
+
+        let handle = add_pending(&mut world, connection_entity, 0);
+
+        update_pending(&mut world);
+
+        assert!(pending_requests(&mut world).is_empty());
+        assert_eq!(handle.poll_response(), Some(Err(RpcError::Disconnected)));
+    }
+
+    #[test]
+    #[should_panic(expected = "was added more than once")]
+    fn requests_have_a_single_response_type() {
+        let mut protocol = ProtocolBuilder::<TestProtocol>::new();
+        protocol.add_request::<Request, u32>();
+        protocol.add_request::<Request, String>();
+    }
+}
@@ -1,60 +1,463 @@
-use std::marker::PhantomData;
+use std::{
+    any::{Any, TypeId},
+    collections::{HashMap, VecDeque},
+    marker::PhantomData,
+    sync::Arc,
+};
 
-use bevy::prelude::*;
+use bevy::{
+    ecs::{intern::Interned, schedule::ScheduleLabel, system::SystemParam},
+    prelude::*,
+};
 use bevy_interface::{
     connections::StreamError, prelude::*, stream_headers::InitializeHeaderStreamError,
 };
 use serde::Serialize;
 
+use crate::{
+    assign_message_ids,
+    channel::{ChannelConfig, ChannelMode, Datagram},
+    codec::{BincodeCodec, CodecError, MessageCodec},
+    deserialize::EndpointMessagingHeader,
+    message_id_from_name, varint, NamedMessage, DEFAULT_MAX_MESSAGE_SIZE,
+    DEFAULT_MAX_QUEUED_MESSAGES,
+};
+
 /// Adds message serialization functionality
-pub struct MessageSerializationPlugin<C> {
-    _p: PhantomData<C>,
-    messages: Vec<Box<dyn MessageIdBuilder<C>>>,
+///
+/// messages are serialized with the codec `M`
+pub struct MessageSerializationPlugin<C, M = BincodeCodec> {
+    _p: PhantomData<(C, M)>,
+    schedule: Interned<dyn ScheduleLabel>,
+    /// message types and their explicit message ids
+    messages: Vec<(Option<u16>, Box<dyn MessageIdBuilder<C>>)>,
+    max_message_size: usize,
+    max_queued_messages: usize,
+    channels: ChannelConfig,
 }
 
 trait MessageIdBuilder<C>: Send + Sync + 'static {
-    fn build(&self, message_id: u16, app: &mut App);
+    fn type_name(&self) -> &'static str;
+
+    fn build(&self, message_id: u16, channel: u8, max_message_size: usize, app: &mut App);
 }
 
-struct MessageIdBuilderType<T> {
-    _p: PhantomData<T>,
+struct MessageIdBuilderType<T, M> {
+    _p: PhantomData<(T, M)>,
 }
 
-impl<C> MessageSerializationPlugin<C> {
+impl<C, M: MessageCodec> Default for MessageSerializationPlugin<C, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C, M: MessageCodec> MessageSerializationPlugin<C, M> {
     pub fn new() -> Self {
         MessageSerializationPlugin {
             _p: PhantomData,
+            schedule: PostUpdate.intern(),
             messages: Vec::new(),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            max_queued_messages: DEFAULT_MAX_QUEUED_MESSAGES,
+            channels: ChannelConfig::new(),
         }
     }
+
+    /// adds a channel that messages can be bound to with [set_message_channel](Self::set_message_channel)
+    ///
+    /// channels must be added in the same order on both ends of a connection.
+    /// panics if a channel with the same name was already added
+    pub fn add_channel(&mut self, name: &'static str, mode: ChannelMode) -> &mut Self {
+        self.channels.add_channel(name, mode);
+        self
+    }
+
+    /// binds a message type to a channel
+    ///
+    /// messages that aren't bound are sent on the [DEFAULT_CHANNEL](crate::channel::DEFAULT_CHANNEL).
+    /// bindings are only honored by [SendMessages],
+    /// [MessageStreamState] always writes to it's own reliable stream
+    pub fn set_message_channel<T: 'static>(&mut self, channel: &'static str) -> &mut Self {
+        self.channels
+            .set_message_channel(std::any::type_name::<T>(), channel);
+        self
+    }
+
+    pub(crate) fn set_channels(&mut self, channels: ChannelConfig) -> &mut Self {
+        self.channels = channels;
+        self
+    }
+
+    /// sets the schedule that messages queued with [SendMessages] are sent in
+    ///
+    /// defaults to [PostUpdate]
+    pub fn set_schedule(&mut self, schedule: impl ScheduleLabel) -> &mut Self {
+        self.schedule = schedule.intern();
+        self
+    }
+
+    /// sets the maximum size of a serialized message that will be sent
+    ///
+    /// defaults to [DEFAULT_MAX_MESSAGE_SIZE]
+    pub fn set_max_message_size(&mut self, max_message_size: usize) -> &mut Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// sets the maximum number of messages that can be queued for a single connection with [SendMessages]
+    ///
+    /// connections that aren't keeping up will refuse new messages until their queue drains.
+    /// defaults to [DEFAULT_MAX_QUEUED_MESSAGES]
+    pub fn set_max_queued_messages(&mut self, max_queued_messages: usize) -> &mut Self {
+        self.max_queued_messages = max_queued_messages;
+        self
+    }
 }
 
-impl<C: Component> MessageSerializationPlugin<C> {
+impl<C: Component, M: MessageCodec> MessageSerializationPlugin<C, M> {
     /// adds a message type to the plugin, assigning it the next message id
+    ///
+    /// the id is the number of messages added before it
     pub fn add_message<T: Serialize + Send + Sync + 'static>(&mut self) -> &mut Self {
-        self.messages
-            .push(Box::new(MessageIdBuilderType::<T> { _p: PhantomData }));
+        self.messages.push((
+            None,
+            Box::new(MessageIdBuilderType::<T, M> { _p: PhantomData }),
+        ));
 
         self
     }
+
+    /// adds a message type to the plugin with an explicit message id
+    pub fn add_message_with_id<T: Serialize + Send + Sync + 'static>(
+        &mut self,
+        message_id: u16,
+    ) -> &mut Self {
+        self.messages.push((
+            Some(message_id),
+            Box::new(MessageIdBuilderType::<T, M> { _p: PhantomData }),
+        ));
+
+        self
+    }
+
+    /// adds a message type to the plugin with a message id derived from it's [NamedMessage::MESSAGE_NAME]
+    pub fn add_named_message<T: NamedMessage + Serialize + Send + Sync + 'static>(
+        &mut self,
+    ) -> &mut Self {
+        self.add_message_with_id::<T>(message_id_from_name(T::MESSAGE_NAME))
+    }
 }
 
-impl<C: Component> Plugin for MessageSerializationPlugin<C> {
+impl<C: Component, M: MessageCodec> Plugin for MessageSerializationPlugin<C, M> {
     fn build(&self, app: &mut App) {
-        for (message_id, builder) in self.messages.iter().enumerate() {
-            builder.build(message_id as u16, app);
+        app.insert_resource(MessageIds::<C> {
+            _p: PhantomData,
+            message_ids: HashMap::new(),
+            max_queued_messages: self.max_queued_messages,
+            channels: self.channels.modes(),
+        });
+
+        app.add_systems(
+            self.schedule,
+            (insert_message_queues::<C>, send_message_queues::<C>).chain(),
+        );
+
+        let message_ids = assign_message_ids(
+            self.messages
+                .iter()
+                .map(|(message_id, builder)| (*message_id, builder.type_name())),
+        );
+
+        let channels = self
+            .channels
+            .resolve(self.messages.iter().map(|(_, builder)| builder.type_name()));
+
+        for (((_, builder), message_id), channel) in
+            self.messages.iter().zip(message_ids).zip(channels)
+        {
+            builder.build(message_id, channel, self.max_message_size, app);
         }
     }
 }
 
-impl<T: Serialize + Send + Sync + 'static, C: Component> MessageIdBuilder<C>
-    for MessageIdBuilderType<T>
+impl<T: Serialize + Send + Sync + 'static, C: Component, M: MessageCodec> MessageIdBuilder<C>
+    for MessageIdBuilderType<T, M>
 {
-    fn build(&self, message_id: u16, app: &mut App) {
-        app.insert_resource(MessageId::<C, T> {
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
+
+    fn build(&self, message_id: u16, channel: u8, max_message_size: usize, app: &mut App) {
+        let message_id = MessageId::<C, T> {
             _p: PhantomData,
             message_id,
-        });
+            channel,
+            max_message_size,
+            serialize: M::serialize::<T>,
+        };
+
+        app.insert_resource(message_id);
+
+        app.world_mut()
+            .resource_mut::<MessageIds<C>>()
+            .message_ids
+            .insert(TypeId::of::<T>(), Box::new(message_id));
+    }
+}
+
+/// Contains the [MessageId] of every message type, used by [SendMessages]
+#[derive(Resource)]
+struct MessageIds<C> {
+    _p: PhantomData<C>,
+    /// boxed [MessageId]s keyed by the type id of the message
+    message_ids: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+    max_queued_messages: usize,
+    /// the mode of each channel indexed by channel id
+    channels: Vec<ChannelMode>,
+}
+
+/// Insert onto an endpoint alongside [EndpointMessagingHeader]
+/// to specify how [SendMessages] and the protocol handshake open message streams
+///
+/// this should describe a unidirectional stream
+#[derive(Component)]
+pub struct EndpointMessageStreamDescription {
+    pub description: CloneableDescription,
+}
+
+/// Insert onto an endpoint to specify how the streams of
+/// [UnorderedReliable](ChannelMode::UnorderedReliable) channels are finished
+///
+/// this should describe gracefully finishing a send stream,
+/// see [CloneableDescription::new_send_close]
+#[derive(Component)]
+pub struct EndpointMessageStreamCloseDescription {
+    pub description: CloneableDescription,
+}
+
+/// messages queued with [SendMessages] for a connection, one queue per channel
+#[derive(Component)]
+struct MessageQueue<C> {
+    channels: Vec<ChannelQueue<C>>,
+}
+
+/// messages queued on a channel and the streams they are sent on
+///
+/// message bytes are shared between the queues of every connection they were sent to
+struct ChannelQueue<C> {
+    mode: ChannelMode,
+    messages: VecDeque<(u16, Arc<[u8]>)>,
+    /// the stream of an ordered channel
+    stream: Option<MessageStreamState<C>>,
+    /// the front message of an ordered channel has been given to `stream` but not completely written,
+    /// it is kept in the queue so that it can be resent if the stream fails
+    front_in_stream: bool,
+    /// streams of an unordered channel that haven't been completely written
+    unordered_streams: Vec<MessageStreamState<C>>,
+    /// the sequence number of the next datagram on a sequenced channel
+    next_sequence: u32,
+}
+
+/// a serialized message and the channel it will be sent on
+#[derive(Clone)]
+struct QueuedMessage {
+    channel: u8,
+    message_id: u16,
+    message: Arc<[u8]>,
+}
+
+impl<C: Send + Sync + 'static> MessageQueue<C> {
+    fn new(channels: &[ChannelMode]) -> Self {
+        MessageQueue {
+            channels: channels
+                .iter()
+                .map(|&mode| ChannelQueue {
+                    mode,
+                    messages: VecDeque::new(),
+                    stream: None,
+                    front_in_stream: false,
+                    unordered_streams: Vec::new(),
+                    next_sequence: 0,
+                })
+                .collect(),
+        }
+    }
+
+    /// the number of messages waiting to be sent on all channels
+    fn len(&self) -> usize {
+        self.channels
+            .iter()
+            .map(|channel| channel.messages.len())
+            .sum()
+    }
+
+    fn push(&mut self, message: QueuedMessage) {
+        self.channels[message.channel as usize]
+            .messages
+            .push_back((message.message_id, message.message));
+    }
+
+    /// returns `true` if there is nothing to send
+    fn is_idle(&self) -> bool {
+        self.channels.iter().all(|channel| {
+            channel.messages.is_empty()
+                && channel.unordered_streams.is_empty()
+                && channel.stream.as_ref().is_none_or(|stream| stream.ready())
+        })
+    }
+}
+
+/// system param for queueing messages to be sent to connections
+///
+/// messages are sent on the channel they are bound to.
+/// streams are opened using the endpoint's [EndpointMessageStreamDescription]
+/// and messages stay queued while a stream is blocked
+///
+/// [broadcast](SendMessages::broadcast) and [multicast](SendMessages::multicast)
+/// serialize a message once and queue it for many connections
+#[derive(SystemParam)]
+pub struct SendMessages<'w, 's, C: Component> {
+    message_ids: Res<'w, MessageIds<C>>,
+    queue_q: Query<'w, 's, (&'static mut MessageQueue<C>, &'static Parent)>,
+}
+
+/// the reason a message couldn't be queued with [SendMessages]
+#[derive(Debug)]
+pub enum QueueMessageError {
+    /// the entity isn't an established connection on an endpoint with this protocol
+    NoConnection,
+    /// the message type wasn't added to the protocol
+    UnknownMessage,
+    /// the codec failed to serialize the message
+    SerializeError(CodecError),
+    /// the serialized message was larger than the maximum message size
+    MessageTooLarge {
+        size: usize,
+        max_message_size: usize,
+    },
+    /// the connection already has the maximum number of queued messages,
+    /// see [MessageSerializationPlugin::set_max_queued_messages]
+    QueueFull,
+}
+
+impl<'w, 's, C: Component> SendMessages<'w, 's, C> {
+    /// serializes a message and queues it to be sent to a connection
+    pub fn send<T: Serialize + Send + Sync + 'static>(
+        &mut self,
+        connection_entity: Entity,
+        message: &T,
+    ) -> Result<(), QueueMessageError> {
+        let message = self.serialize(message)?;
+
+        let max_queued_messages = self.message_ids.max_queued_messages;
+        let Ok((mut queue, _)) = self.queue_q.get_mut(connection_entity) else {
+            return Err(QueueMessageError::NoConnection);
+        };
+
+        if queue.len() >= max_queued_messages {
+            return Err(QueueMessageError::QueueFull);
+        }
+
+        queue.push(message);
+
+        Ok(())
+    }
+
+    /// serializes a message once and queues it to be sent to every connection of an endpoint
+    ///
+    /// connections with a full queue are skipped.
+    /// returns the number of connections the message was queued for
+    pub fn broadcast<T: Serialize + Send + Sync + 'static>(
+        &mut self,
+        endpoint_entity: Entity,
+        message: &T,
+    ) -> Result<usize, QueueMessageError> {
+        let message = self.serialize(message)?;
+        let max_queued_messages = self.message_ids.max_queued_messages;
+
+        let mut queued = 0;
+
+        for (mut queue, connection_parent) in self.queue_q.iter_mut() {
+            if connection_parent.get() != endpoint_entity || queue.len() >= max_queued_messages {
+                continue;
+            }
+
+            queue.push(message.clone());
+            queued += 1;
+        }
+
+        Ok(queued)
+    }
+
+    /// serializes a message once and queues it to be sent to a set of connections,
+    /// such as the entities of a query filtered by a marker component
+    ///
+    /// entities that aren't connections with this protocol and connections with a full queue are skipped.
+    /// returns the number of connections the message was queued for
+    pub fn multicast<T: Serialize + Send + Sync + 'static>(
+        &mut self,
+        connection_entities: impl IntoIterator<Item = Entity>,
+        message: &T,
+    ) -> Result<usize, QueueMessageError> {
+        let message = self.serialize(message)?;
+        let max_queued_messages = self.message_ids.max_queued_messages;
+
+        let mut queued = 0;
+
+        for connection_entity in connection_entities {
+            let Ok((mut queue, _)) = self.queue_q.get_mut(connection_entity) else {
+                continue;
+            };
+
+            if queue.len() >= max_queued_messages {
+                continue;
+            }
+
+            queue.push(message.clone());
+            queued += 1;
+        }
+
+        Ok(queued)
+    }
+
+    /// the number of messages waiting to be sent to a connection
+    ///
+    /// returns `None` if the entity isn't an established connection on an endpoint with this protocol
+    pub fn queued_messages(&self, connection_entity: Entity) -> Option<usize> {
+        let (queue, _) = self.queue_q.get(connection_entity).ok()?;
+        Some(queue.len())
+    }
+
+    /// serializes a message and checks it's size
+    fn serialize<T: Serialize + Send + Sync + 'static>(
+        &self,
+        message: &T,
+    ) -> Result<QueuedMessage, QueueMessageError> {
+        let Some(message_id) = self
+            .message_ids
+            .message_ids
+            .get(&TypeId::of::<T>())
+            .and_then(|message_id| message_id.downcast_ref::<MessageId<C, T>>())
+        else {
+            return Err(QueueMessageError::UnknownMessage);
+        };
+
+        let bytes = (message_id.serialize)(message).map_err(QueueMessageError::SerializeError)?;
+
+        let max_message_size = message_id.max_message_size.min(u32::MAX as usize);
+        if bytes.len() > max_message_size {
+            return Err(QueueMessageError::MessageTooLarge {
+                size: bytes.len(),
+                max_message_size,
+            });
+        }
+
+        Ok(QueuedMessage {
+            channel: message_id.channel,
+            message_id: message_id.message_id,
+            message: bytes.into(),
+        })
     }
 }
 
@@ -64,20 +467,26 @@ impl<T: Serialize + Send + Sync + 'static, C: Component> MessageIdBuilder<C>
 pub struct MessageId<C, T> {
     _p: PhantomData<(C, T)>,
     message_id: u16,
+    /// the channel [SendMessages] sends the message on
+    channel: u8,
+    /// messages that serialize to more bytes than this are rejected
+    max_message_size: usize,
+    /// serializes the message with the protocol's codec
+    serialize: fn(&T) -> Result<Vec<u8>, CodecError>,
 }
 
 impl<C, T> Clone for MessageId<C, T> {
     fn clone(&self) -> Self {
-        MessageId {
-            _p: PhantomData,
-            message_id: self.message_id,
-        }
+        *self
     }
 }
 
 impl<C, T> Copy for MessageId<C, T> {}
 
 /// wraps a stream id and ensures that the message protocol isn't broken
+///
+/// messages are written to this stream in order whatever channel they are bound to,
+/// use [SendMessages] for messages to be sent on their channel
 pub struct MessageStreamState<C> {
     _p: PhantomData<C>,
     stream_id: HeaderStreamId,
@@ -89,6 +498,15 @@ pub enum MessageStreamSendError {
     StreamClosed,
     MismatchedConnection(MismatchedType),
     FatalSendErr(Box<dyn StreamError>),
+    /// the serialized message was larger than the maximum message size,
+    /// nothing was written to the stream
+    MessageTooLarge {
+        size: usize,
+        max_message_size: usize,
+    },
+    /// the codec failed to serialize the message,
+    /// nothing was written to the stream
+    SerializeError(CodecError),
 }
 
 impl<C: Send + Sync + 'static> MessageStreamState<C> {
@@ -141,7 +559,7 @@ impl<C: Send + Sync + 'static> MessageStreamState<C> {
         // header has been written, get the stream
         let Some(mut stream) = connection
             .send_stream(stream_id)
-            .map_err(|err| MessageStreamSendError::MismatchedConnection(err))?
+            .map_err(MessageStreamSendError::MismatchedConnection)?
         else {
             return Err(MessageStreamSendError::StreamClosed);
         };
@@ -185,18 +603,40 @@ impl<C: Send + Sync + 'static> MessageStreamState<C> {
         message_id: MessageId<C, T>,
         message: &T,
     ) -> Result<bool, MessageStreamSendError> {
-        if !self.ready() {
-            if !self.flush(connection)? {
-                return Ok(false);
-            }
+        let bytes =
+            (message_id.serialize)(message).map_err(MessageStreamSendError::SerializeError)?;
+
+        self.send_serialized(
+            connection,
+            message_id.message_id,
+            &bytes,
+            message_id.max_message_size,
+        )
+    }
+
+    /// attempts to send an already serialized message with a message id
+    pub(crate) fn send_serialized(
+        &mut self,
+        connection: &mut BevyConnectionMut,
+        message_id: u16,
+        bytes: &[u8],
+        max_message_size: usize,
+    ) -> Result<bool, MessageStreamSendError> {
+        if !self.ready() && !self.flush(connection)? {
+            return Ok(false);
         }
 
-        let message_id = message_id.message_id.to_be_bytes();
-        let bytes = bincode::serialize(message).expect("Failed to serialize message");
-        let message_length = (bytes.len() as u16).to_be_bytes();
+        let max_message_size = max_message_size.min(u32::MAX as usize);
+        if bytes.len() > max_message_size {
+            return Err(MessageStreamSendError::MessageTooLarge {
+                size: bytes.len(),
+                max_message_size,
+            });
+        }
 
-        self.buffer.extend(message_id);
-        self.buffer.extend(message_length);
+        // messages are framed as a u16 message id, a varint length and then the message
+        self.buffer.extend(message_id.to_be_bytes());
+        varint::encode(bytes.len() as u32, &mut self.buffer);
         self.buffer.extend(bytes);
 
         self.flush(connection)?;
@@ -213,3 +653,326 @@ impl<C: Send + Sync + 'static> MessageStreamState<C> {
         self.stream_id.end()
     }
 }
+
+fn insert_message_queues<C: Component>(
+    mut commands: Commands,
+    mut connected_r: EventReader<Connected>,
+    endpoint_q: Query<(), With<C>>,
+    message_ids: Res<MessageIds<C>>,
+) {
+    for &Connected {
+        endpoint_entity,
+        connection_entity,
+    } in connected_r.read()
+    {
+        if !endpoint_q.contains(endpoint_entity) {
+            continue;
+        }
+
+        // the connection may have been closed before this system ran
+        let Some(mut connection_commands) = commands.get_entity(connection_entity) else {
+            continue;
+        };
+
+        connection_commands.try_insert(MessageQueue::<C>::new(&message_ids.channels));
+    }
+}
+
+/// the components of an endpoint that configure how it's message streams are opened and finished
+type EndpointMessageStreamConfig<'a> = (
+    &'a EndpointMessagingHeader,
+    Option<&'a EndpointMessageStreamDescription>,
+    Option<&'a EndpointMessageStreamCloseDescription>,
+);
+
+fn send_message_queues<C: Component>(
+    mut connections: Connections,
+    mut connection_q: Query<(Entity, &mut MessageQueue<C>, &Parent)>,
+    endpoint_q: Query<EndpointMessageStreamConfig, With<C>>,
+) {
+    for (connection_entity, mut queue, connection_parent) in connection_q.iter_mut() {
+        if queue.is_idle() {
+            continue;
+        }
+
+        let Ok((&EndpointMessagingHeader { header }, description, close_description)) =
+            endpoint_q.get(connection_parent.get())
+        else {
+            continue;
+        };
+
+        let Some(mut endpoint) = connections.connection_endpoint_mut(connection_entity) else {
+            continue;
+        };
+
+        let Some(mut connection) = endpoint.connection_mut(connection_entity) else {
+            continue;
+        };
+
+        for (channel_id, channel) in queue.channels.iter_mut().enumerate() {
+            match channel.mode {
+                ChannelMode::OrderedReliable => {
+                    channel.send_ordered(&mut connection, connection_entity, header, description)
+                }
+                ChannelMode::UnorderedReliable => channel.send_unordered(
+                    &mut connection,
+                    connection_entity,
+                    header,
+                    description,
+                    close_description,
+                ),
+                ChannelMode::SequencedUnreliable | ChannelMode::Unreliable => channel
+                    .send_datagrams(&mut connection, connection_entity, header, channel_id as u8),
+            }
+        }
+    }
+}
+
+impl<C: Send + Sync + 'static> ChannelQueue<C> {
+    /// sends queued messages in order on a single stream
+    fn send_ordered(
+        &mut self,
+        connection: &mut BevyConnectionMut,
+        connection_entity: Entity,
+        header: u16,
+        description: Option<&EndpointMessageStreamDescription>,
+    ) {
+        let flushed = self.stream.as_ref().is_none_or(|stream| stream.ready());
+        if self.messages.is_empty() && flushed {
+            return;
+        }
+
+        let stream = match &mut self.stream {
+            Some(stream) => stream,
+            None => {
+                let Some(description) = description else {
+                    warn!(
+                        "the endpoint of connection {:?} has no EndpointMessageStreamDescription, dropping {} queued messages",
+                        connection_entity,
+                        self.messages.len()
+                    );
+                    self.messages.clear();
+                    return;
+                };
+
+                match MessageStreamState::new(
+                    connection,
+                    description.description.clone().into(),
+                    header,
+                ) {
+                    Ok(Some(stream)) => self.stream.insert(stream),
+                    // try again next update
+                    Ok(None) => return,
+                    Err(err) => {
+                        error!(
+                            "mismatched message stream description for connection {:?}, dropping {} queued messages: {:?}",
+                            connection_entity,
+                            self.messages.len(),
+                            err
+                        );
+                        self.messages.clear();
+                        return;
+                    }
+                }
+            }
+        };
+
+        if let Err(err) = send_message_queue(
+            stream,
+            connection,
+            &mut self.messages,
+            &mut self.front_in_stream,
+        ) {
+            warn!(
+                "failed to send queued messages to connection {:?}, a new stream will be opened: {:?}",
+                connection_entity, err
+            );
+            // the unwritten message is still at the front of the queue and will be resent on the new stream
+            self.stream = None;
+            self.front_in_stream = false;
+        }
+    }
+
+    /// sends each queued message on a new stream, finishing streams once they have been written
+    fn send_unordered(
+        &mut self,
+        connection: &mut BevyConnectionMut,
+        connection_entity: Entity,
+        header: u16,
+        description: Option<&EndpointMessageStreamDescription>,
+        close_description: Option<&EndpointMessageStreamCloseDescription>,
+    ) {
+        let mut index = 0;
+        while let Some(stream) = self.unordered_streams.get_mut(index) {
+            match stream.flush(connection) {
+                Ok(false) => index += 1,
+                Ok(true) => {
+                    let stream = self.unordered_streams.swap_remove(index);
+
+                    if let Some(close_description) = close_description {
+                        finish_stream(connection, connection_entity, stream, close_description);
+                    }
+                }
+                Err(err) => {
+                    warn!(
+                        "failed to send an unordered message to connection {:?}: {:?}",
+                        connection_entity, err
+                    );
+                    self.unordered_streams.swap_remove(index);
+                }
+            }
+        }
+
+        if self.messages.is_empty() {
+            return;
+        }
+
+        let (Some(description), Some(close_description)) = (description, close_description) else {
+            warn!(
+                "the endpoint of connection {:?} needs an EndpointMessageStreamDescription and an EndpointMessageStreamCloseDescription to send unordered messages, dropping {} queued messages",
+                connection_entity,
+                self.messages.len()
+            );
+            self.messages.clear();
+            return;
+        };
+
+        while !self.messages.is_empty() {
+            let mut stream = match MessageStreamState::new(
+                connection,
+                description.description.clone().into(),
+                header,
+            ) {
+                Ok(Some(stream)) => stream,
+                // no more streams can be opened right now, try again next update
+                Ok(None) => return,
+                Err(err) => {
+                    error!(
+                        "mismatched message stream description for connection {:?}, dropping {} queued messages: {:?}",
+                        connection_entity,
+                        self.messages.len(),
+                        err
+                    );
+                    self.messages.clear();
+                    return;
+                }
+            };
+
+            let Some((message_id, message)) = self.messages.pop_front() else {
+                break;
+            };
+
+            // the size was checked when the message was queued
+            match stream.send_serialized(connection, message_id, &message, usize::MAX) {
+                Ok(_) if stream.ready() => {
+                    finish_stream(connection, connection_entity, stream, close_description)
+                }
+                Ok(_) => self.unordered_streams.push(stream),
+                Err(err) => warn!(
+                    "failed to send an unordered message to connection {:?}: {:?}",
+                    connection_entity, err
+                ),
+            }
+        }
+    }
+
+    /// sends each queued message as a datagram
+    fn send_datagrams(
+        &mut self,
+        connection: &mut BevyConnectionMut,
+        connection_entity: Entity,
+        header: u16,
+        channel: u8,
+    ) {
+        while let Some((message_id, message)) = self.messages.pop_front() {
+            let sequence = match self.mode {
+                ChannelMode::SequencedUnreliable => {
+                    let sequence = self.next_sequence;
+                    self.next_sequence = sequence.wrapping_add(1);
+                    Some(sequence)
+                }
+                _ => None,
+            };
+
+            let datagram = Datagram {
+                header,
+                channel,
+                sequence,
+                message_id,
+                message: &message,
+            }
+            .encode();
+
+            match connection.send_datagram(&datagram) {
+                Ok(()) => (),
+                Err(SendDatagramError::TooLarge) => {
+                    warn!(
+                        "message {} was too large to send to connection {:?} as a datagram and was dropped",
+                        message_id, connection_entity
+                    );
+                }
+                Err(SendDatagramError::Unsupported) => {
+                    warn!(
+                        "connection {:?} doesn't support datagrams, dropping {} queued messages",
+                        connection_entity,
+                        self.messages.len() + 1
+                    );
+                    self.messages.clear();
+                }
+            }
+        }
+    }
+}
+
+/// writes queued messages to a stream in order until the stream is blocked or the queue is empty
+///
+/// messages are only removed from the queue once they have been completely written,
+/// `front_in_stream` tracks whether the front message is partially written
+fn send_message_queue<C: Send + Sync + 'static>(
+    stream: &mut MessageStreamState<C>,
+    connection: &mut BevyConnectionMut,
+    messages: &mut VecDeque<(u16, Arc<[u8]>)>,
+    front_in_stream: &mut bool,
+) -> Result<(), MessageStreamSendError> {
+    loop {
+        if !stream.flush(connection)? {
+            return Ok(());
+        }
+
+        if std::mem::take(front_in_stream) {
+            messages.pop_front();
+        }
+
+        let Some((message_id, message)) = messages.front() else {
+            return Ok(());
+        };
+
+        // the size was checked when the message was queued
+        stream.send_serialized(connection, *message_id, message, usize::MAX)?;
+
+        if stream.ready() {
+            messages.pop_front();
+        } else {
+            *front_in_stream = true;
+        }
+    }
+}
+
+/// gracefully finishes a stream that has been completely written
+pub(crate) fn finish_stream<C: Send + Sync + 'static>(
+    connection: &mut BevyConnectionMut,
+    connection_entity: Entity,
+    stream: MessageStreamState<C>,
+    close_description: &EndpointMessageStreamCloseDescription,
+) {
+    let Ok(Some(mut send_stream)) = connection.send_stream(stream.end()) else {
+        return;
+    };
+
+    if let Err(err) = send_stream.close(close_description.description.clone().into()) {
+        error!(
+            "mismatched message stream close description for connection {:?}: {:?}",
+            connection_entity, err
+        );
+    }
+}
@@ -0,0 +1,109 @@
+//! unsigned LEB128 variable length integers used for message lengths
+
+/// the maximum number of bytes a varint can take up
+pub(crate) const MAX_VARINT_LENGTH: usize = 5;
+
+/// appends a varint to a buffer
+pub(crate) fn encode(mut value: u32, buffer: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            buffer.push(byte);
+            break;
+        }
+
+        buffer.push(byte | 0x80);
+    }
+}
+
+/// attempts to decode a varint from the start of a buffer
+///
+/// returns `Ok(None)` if more bytes are needed,
+/// and `Err(())` if the varint is longer than [MAX_VARINT_LENGTH] or overflows a `u32`
+pub(crate) fn decode(buffer: &[u8]) -> Result<Option<(u32, usize)>, ()> {
+    let mut value: u64 = 0;
+
+    for (index, &byte) in buffer.iter().enumerate().take(MAX_VARINT_LENGTH) {
+        value |= ((byte & 0x7f) as u64) << (index * 7);
+
+        if byte & 0x80 == 0 {
+            let value = u32::try_from(value).map_err(|_| ())?;
+            return Ok(Some((value, index + 1)));
+        }
+    }
+
+    if buffer.len() >= MAX_VARINT_LENGTH {
+        return Err(());
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(value: u32) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        encode(value, &mut buffer);
+        buffer
+    }
+
+    #[test]
+    fn round_trips() {
+        for (value, length) in [
+            (0, 1),
+            (1, 1),
+            (127, 1),
+            (128, 2),
+            (16_383, 2),
+            (16_384, 3),
+            (u16::MAX as u32, 3),
+            (u32::MAX, MAX_VARINT_LENGTH),
+        ] {
+            let buffer = encoded(value);
+
+            assert_eq!(buffer.len(), length, "length of {}", value);
+            assert_eq!(decode(&buffer), Ok(Some((value, length))), "{}", value);
+        }
+    }
+
+    #[test]
+    fn boundary_encodings() {
+        assert_eq!(encoded(0), [0x00]);
+        assert_eq!(encoded(127), [0x7f]);
+        assert_eq!(encoded(128), [0x80, 0x01]);
+        assert_eq!(encoded(u16::MAX as u32), [0xff, 0xff, 0x03]);
+        assert_eq!(encoded(u32::MAX), [0xff, 0xff, 0xff, 0xff, 0x0f]);
+    }
+
+    #[test]
+    fn ignores_trailing_bytes() {
+        let mut buffer = encoded(300);
+        buffer.extend([1, 2, 3]);
+
+        assert_eq!(decode(&buffer), Ok(Some((300, 2))));
+    }
+
+    #[test]
+    fn truncated_needs_more_bytes() {
+        assert_eq!(decode(&[]), Ok(None));
+
+        let buffer = encoded(u32::MAX);
+        for length in 1..buffer.len() {
+            assert_eq!(decode(&buffer[..length]), Ok(None));
+        }
+    }
+
+    #[test]
+    fn rejects_oversized() {
+        // a sixth byte would be needed
+        assert_eq!(decode(&[0xff, 0xff, 0xff, 0xff, 0xff]), Err(()));
+        assert_eq!(decode(&[0xff, 0xff, 0xff, 0xff, 0xff, 0x01]), Err(()));
+
+        // five bytes that overflow a u32
+        assert_eq!(decode(&[0xff, 0xff, 0xff, 0xff, 0x10]), Err(()));
+    }
+}
@@ -0,0 +1,162 @@
+use bevy::{ecs::system::RunSystemOnce, prelude::*};
+use nevy_loopback::prelude::*;
+use nevy_messaging::prelude::*;
+
+mod common;
+
+use common::*;
+
+/// an app with a server that has `clients` connected clients,
+/// each connection can queue at most two messages
+///
+/// returns the app, the server endpoint,
+/// and the server and client end of each connection
+fn broadcast_app(clients: usize) -> (App, Entity, Vec<(Entity, Entity)>) {
+    let mut protocol = test_protocol();
+    protocol.set_max_queued_messages(2);
+
+    let mut app = endpoint_app();
+    app.add_plugins(protocol.build_symmetric(Update));
+
+    let network = LoopbackNetwork::new();
+    let server_entity = spawn_messaging_endpoint(&mut app, &network, TestProtocol);
+
+    let client_entities: Vec<_> = (0..clients)
+        .map(|_| {
+            let client_entity = spawn_messaging_endpoint(&mut app, &network, TestProtocol);
+            connect_endpoints(&mut app, client_entity, server_entity);
+            client_entity
+        })
+        .collect();
+
+    update(&mut app);
+
+    let client_connections: Vec<_> = client_entities
+        .into_iter()
+        .map(|client_entity| endpoint_connection(&mut app, client_entity))
+        .collect();
+
+    let mut server_connections = app
+        .world_mut()
+        .query::<(Entity, &Parent)>()
+        .iter(app.world())
+        .filter(|(_, parent)| parent.get() == server_entity)
+        .map(|(connection_entity, _)| connection_entity)
+        .collect::<Vec<_>>();
+
+    // loopback connections are accepted in the order they were made
+    server_connections.sort();
+
+    let connections = server_connections
+        .into_iter()
+        .zip(client_connections)
+        .collect();
+
+    (app, server_entity, connections)
+}
+
+fn chat(text: &str) -> ChatMessage {
+    ChatMessage { text: text.into() }
+}
+
+/// pops every message a connection has received
+fn received(app: &mut App, connection_entity: Entity) -> Vec<String> {
+    let mut received = app
+        .world_mut()
+        .get_mut::<ReceivedMessages<ChatMessage>>(connection_entity)
+        .unwrap();
+
+    std::iter::from_fn(|| received.pop())
+        .map(|message| message.text)
+        .collect()
+}
+
+#[test]
+fn broadcast_reaches_every_connection() {
+    let (mut app, server_entity, connections) = broadcast_app(3);
+
+    let queued = app
+        .world_mut()
+        .run_system_once(move |mut messages: SendMessages<TestProtocol>| {
+            messages.broadcast(server_entity, &chat("hello clients"))
+        })
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(queued, 3);
+
+    update(&mut app);
+
+    for (_, client_connection) in connections {
+        assert_eq!(received(&mut app, client_connection), ["hello clients"]);
+    }
+}
+
+#[test]
+fn multicast_reaches_only_the_given_connections() {
+    let (mut app, server_entity, connections) = broadcast_app(3);
+
+    let targets = [connections[0].0, connections[2].0, server_entity];
+
+    let queued = app
+        .world_mut()
+        .run_system_once(move |mut messages: SendMessages<TestProtocol>| {
+            messages.multicast(targets, &chat("hello some clients"))
+        })
+        .unwrap()
+        .unwrap();
+
+    // the endpoint isn't a connection and is skipped
+    assert_eq!(queued, 2);
+
+    update(&mut app);
+
+    assert_eq!(received(&mut app, connections[0].1), ["hello some clients"]);
+    assert!(received(&mut app, connections[1].1).is_empty());
+    assert_eq!(received(&mut app, connections[2].1), ["hello some clients"]);
+}
+
+#[test]
+fn full_connections_dont_block_the_others() {
+    let (mut app, server_entity, connections) = broadcast_app(2);
+
+    let full_connection = connections[0].0;
+    let other_connection = connections[1].0;
+
+    let (broadcast, multicast, send) = app
+        .world_mut()
+        .run_system_once(move |mut messages: SendMessages<TestProtocol>| {
+            messages.send(full_connection, &chat("first")).unwrap();
+            messages.send(full_connection, &chat("second")).unwrap();
+
+            (
+                messages.broadcast(server_entity, &chat("broadcast")),
+                messages.multicast([full_connection, other_connection], &chat("multicast")),
+                messages.send(full_connection, &chat("third")),
+            )
+        })
+        .unwrap();
+
+    assert_eq!(broadcast.unwrap(), 1);
+    assert_eq!(multicast.unwrap(), 1);
+    assert!(matches!(send, Err(QueueMessageError::QueueFull)));
+
+    update(&mut app);
+
+    assert_eq!(received(&mut app, connections[0].1), ["first", "second"]);
+    assert_eq!(
+        received(&mut app, connections[1].1),
+        ["broadcast", "multicast"]
+    );
+
+    // the queue drained, so the connection accepts messages again
+    let queued = app
+        .world_mut()
+        .run_system_once(move |mut messages: SendMessages<TestProtocol>| {
+            messages.broadcast(server_entity, &chat("again"))
+        })
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(queued, 2);
+}
@@ -0,0 +1,144 @@
+//! helpers shared by the messaging integration tests,
+//! which run a client and a server endpoint in the same app over a loopback network
+
+#![allow(dead_code)]
+
+use bevy::{ecs::system::RunSystemOnce, prelude::*, time::TimePlugin};
+use bevy_interface::prelude::*;
+use nevy_loopback::prelude::*;
+use nevy_messaging::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// marker for the endpoints of the test protocol
+#[derive(Component)]
+pub struct TestProtocol;
+
+/// the address of a test endpoint
+#[derive(Component)]
+pub struct TestAddress(pub LoopbackAddress);
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ChatMessage {
+    pub text: String,
+}
+
+/// the protocol used by most tests
+pub fn test_protocol() -> ProtocolBuilder<TestProtocol> {
+    let mut protocol = ProtocolBuilder::new();
+    protocol.add_message::<ChatMessage>();
+    protocol
+}
+
+/// an app with the time, endpoint and stream header plugins
+pub fn endpoint_app() -> App {
+    let mut app = App::new();
+
+    app.add_plugins(TimePlugin);
+    app.add_plugins(EndpointPlugin::default());
+    app.add_plugins(StreamHeaderPlugin::default());
+
+    app
+}
+
+/// an app with a client and a server endpoint using the test protocol
+pub fn messaging_app() -> (App, Entity, Entity) {
+    protocol_app(&test_protocol())
+}
+
+/// an app with a client and a server endpoint using the plugins built from `protocol`
+pub fn protocol_app(protocol: &ProtocolBuilder<TestProtocol>) -> (App, Entity, Entity) {
+    let mut app = endpoint_app();
+
+    app.add_plugins(protocol.build_symmetric(Update));
+
+    let network = LoopbackNetwork::new();
+    let server_entity = spawn_messaging_endpoint(&mut app, &network, TestProtocol);
+    let client_entity = spawn_messaging_endpoint(&mut app, &network, TestProtocol);
+    connect_endpoints(&mut app, client_entity, server_entity);
+
+    (app, client_entity, server_entity)
+}
+
+/// spawns an endpoint that sends messages with the protocol of `marker`
+pub fn spawn_messaging_endpoint(
+    app: &mut App,
+    network: &LoopbackNetwork,
+    marker: impl Component,
+) -> Entity {
+    let endpoint = LoopbackEndpoint::new(network, None);
+
+    app.world_mut()
+        .spawn((
+            marker,
+            TestAddress(endpoint.local_addr()),
+            BevyEndpoint::new(endpoint),
+            EndpointStreamHeaders,
+            EndpointMessagingHeader { header: 1 },
+            EndpointMessageStreamDescription {
+                description: CloneableDescription::new::<LoopbackStreamId>(LoopbackDir::Uni),
+            },
+            EndpointMessageStreamCloseDescription {
+                description: CloneableDescription::new_send_close::<LoopbackStreamId>(None),
+            },
+            EndpointMessageStreamStopDescription {
+                description: CloneableDescription::new_recv_close::<LoopbackStreamId>(0),
+            },
+        ))
+        .id()
+}
+
+/// connects the client endpoint to the server endpoint
+pub fn connect_endpoints(app: &mut App, client_entity: Entity, server_entity: Entity) {
+    let server_address = app.world().get::<TestAddress>(server_entity).unwrap().0;
+
+    app.world_mut()
+        .run_system_once(move |mut connections: Connections| {
+            connections
+                .connect(
+                    client_entity,
+                    Description::new_connect_description::<LoopbackEndpoint>(server_address),
+                )
+                .unwrap()
+                .unwrap();
+        })
+        .unwrap();
+}
+
+/// updates the app enough times for data to pass through every plugin's systems
+pub fn update(app: &mut App) {
+    for _ in 0..10 {
+        app.update();
+    }
+}
+
+/// the first connection of an endpoint
+pub fn endpoint_connection(app: &mut App, endpoint_entity: Entity) -> Entity {
+    app.world_mut()
+        .query_filtered::<(Entity, &Parent), With<BevyConnection>>()
+        .iter(app.world())
+        .find(|(_, parent)| parent.get() == endpoint_entity)
+        .map(|(connection_entity, _)| connection_entity)
+        .unwrap()
+}
+
+/// updates the app and collects the disconnect reasons of each endpoint
+pub fn collect_disconnects(app: &mut App) -> Vec<(Entity, DisconnectReason)> {
+    let mut disconnected = Vec::new();
+    let mut cursor = app
+        .world()
+        .resource::<Events<Disconnected>>()
+        .get_cursor_current();
+
+    for _ in 0..10 {
+        app.update();
+
+        let events = app.world().resource::<Events<Disconnected>>();
+        disconnected.extend(
+            cursor
+                .read(events)
+                .map(|event| (event.endpoint_entity, event.reason.clone())),
+        );
+    }
+
+    disconnected
+}
@@ -0,0 +1,70 @@
+use bevy::prelude::*;
+use bevy_interface::prelude::*;
+use nevy_messaging::prelude::*;
+use serde::{Deserialize, Serialize};
+
+mod common;
+
+use common::*;
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Score(u32);
+
+impl Delta for Score {
+    type Delta = u32;
+
+    fn delta(&self, _baseline: &Self) -> u32 {
+        self.0
+    }
+
+    fn apply_delta(&self, delta: u32) -> Self {
+        Score(delta)
+    }
+}
+
+/// the endpoints that a connection was closed on
+#[derive(Resource, Default)]
+struct ClosedConnections(Vec<Entity>);
+
+/// despawns every new connection before the protocol's systems see it
+fn close_new_connections(
+    mut commands: Commands,
+    mut connected_r: EventReader<Connected>,
+    mut closed: ResMut<ClosedConnections>,
+) {
+    for event in connected_r.read() {
+        commands.entity(event.connection_entity).despawn();
+        closed.0.push(event.endpoint_entity);
+    }
+}
+
+#[test]
+fn connections_closed_on_connect_are_ignored() {
+    let mut protocol = test_protocol();
+    protocol.add_clock_sync();
+    protocol.add_delta_message::<Score>();
+
+    let (mut app, client_entity, server_entity) = protocol_app(&protocol);
+
+    app.add_plugins(ClockSyncPlugin::<TestProtocol>::new(Update));
+    app.add_plugins(protocol.build_delta(Update));
+    app.world_mut()
+        .entity_mut(server_entity)
+        .insert(ClockServer);
+
+    app.init_resource::<ClosedConnections>();
+    app.add_systems(PreUpdate, close_new_connections.after(UpdateEndpoints));
+
+    update(&mut app);
+
+    let closed = &app.world().resource::<ClosedConnections>().0;
+    assert!(closed.contains(&client_entity));
+    assert!(closed.contains(&server_entity));
+
+    let connections = app
+        .world_mut()
+        .query_filtered::<(), With<BevyConnection>>()
+        .iter(app.world())
+        .count();
+    assert_eq!(connections, 0);
+}
@@ -0,0 +1,140 @@
+use bevy::{
+    ecs::{
+        entity::{EntityMapper, MapEntities},
+        system::RunSystemOnce,
+    },
+    prelude::*,
+};
+use nevy_messaging::prelude::*;
+use serde::{Deserialize, Serialize};
+
+mod common;
+
+use common::*;
+
+/// a message that refers to entities of the peer that sent it
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Follow {
+    leader: Entity,
+    followers: Vec<Entity>,
+}
+
+impl MapEntities for Follow {
+    fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+        self.leader = entity_mapper.map_entity(self.leader);
+
+        for follower in self.followers.iter_mut() {
+            *follower = entity_mapper.map_entity(*follower);
+        }
+    }
+}
+
+#[test]
+fn received_messages_have_local_entities() {
+    let mut protocol = test_protocol();
+    protocol.add_message::<Follow>();
+    protocol.map_message_entities::<Follow>();
+
+    let (mut app, client_entity, server_entity) = protocol_app(&protocol);
+
+    update(&mut app);
+
+    let client_connection = endpoint_connection(&mut app, client_entity);
+    let server_connection = endpoint_connection(&mut app, server_entity);
+
+    let remote_leader = app.world_mut().spawn_empty().id();
+    let remote_follower = app.world_mut().spawn_empty().id();
+    let unknown_follower = app.world_mut().spawn_empty().id();
+    let local_leader = app.world_mut().spawn_empty().id();
+    let local_follower = app.world_mut().spawn_empty().id();
+
+    let mut entity_map = app
+        .world_mut()
+        .get_mut::<ConnectionEntityMap>(client_connection)
+        .unwrap();
+
+    entity_map.insert(remote_leader, local_leader);
+    entity_map.insert(remote_follower, local_follower);
+
+    assert!(entity_map.contains(remote_leader));
+    assert!(!entity_map.contains(unknown_follower));
+
+    app.world_mut()
+        .run_system_once(move |mut messages: SendMessages<TestProtocol>| {
+            messages
+                .send(
+                    server_connection,
+                    &Follow {
+                        leader: remote_leader,
+                        followers: vec![remote_follower, unknown_follower],
+                    },
+                )
+                .unwrap();
+        })
+        .unwrap();
+
+    update(&mut app);
+
+    let mut received = app
+        .world_mut()
+        .get_mut::<ReceivedMessages<Follow>>(client_connection)
+        .unwrap();
+
+    assert_eq!(
+        received.pop(),
+        Some(Follow {
+            leader: local_leader,
+            followers: vec![local_follower, Entity::PLACEHOLDER],
+        })
+    );
+}
+
+#[test]
+fn unmapped_messages_keep_the_peer_entities() {
+    let mut protocol = test_protocol();
+    protocol.add_message::<Follow>();
+
+    let (mut app, client_entity, server_entity) = protocol_app(&protocol);
+
+    update(&mut app);
+
+    let client_connection = endpoint_connection(&mut app, client_entity);
+    let server_connection = endpoint_connection(&mut app, server_entity);
+
+    let remote_leader = app.world_mut().spawn_empty().id();
+    let local_leader = app.world_mut().spawn_empty().id();
+
+    app.world_mut()
+        .get_mut::<ConnectionEntityMap>(client_connection)
+        .unwrap()
+        .insert(remote_leader, local_leader);
+
+    app.world_mut()
+        .run_system_once(move |mut messages: SendMessages<TestProtocol>| {
+            messages
+                .send(
+                    server_connection,
+                    &Follow {
+                        leader: remote_leader,
+                        followers: Vec::new(),
+                    },
+                )
+                .unwrap();
+        })
+        .unwrap();
+
+    update(&mut app);
+
+    let mut received = app
+        .world_mut()
+        .get_mut::<ReceivedMessages<Follow>>(client_connection)
+        .unwrap();
+
+    assert_eq!(
+        received.pop(),
+        Some(Follow {
+            leader: remote_leader,
+            followers: Vec::new(),
+        })
+    );
+}
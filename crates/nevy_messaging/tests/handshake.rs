@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_interface::prelude::*;
+use nevy_loopback::prelude::*;
+use nevy_messaging::prelude::*;
+use serde::{Deserialize, Serialize};
+
+mod common;
+
+use common::*;
+
+/// marker for endpoints with a protocol that may differ from the test protocol
+#[derive(Component)]
+struct OtherProtocol;
+
+#[derive(Serialize, Deserialize)]
+struct OtherMessage;
+
+/// an app with a server using the test protocol,
+/// and a client using `client_protocol` and the handshake if `client_handshake` is `true`
+fn handshake_app(
+    client_protocol: ProtocolBuilder<OtherProtocol>,
+    client_handshake: bool,
+    handshake_timeout: Option<Duration>,
+) -> (App, Entity, Entity) {
+    let mut app = endpoint_app();
+
+    let mut server_protocol = test_protocol();
+    server_protocol.set_handshake_timeout(handshake_timeout);
+    app.add_plugins(server_protocol.build_symmetric(Update));
+
+    if client_handshake {
+        app.add_plugins(client_protocol.build_symmetric(Update));
+    } else {
+        app.add_plugins((
+            client_protocol.build_serialization(),
+            client_protocol.build_deserialization(Update),
+            client_protocol.build_rpc(Update),
+        ));
+    }
+
+    let network = LoopbackNetwork::new();
+    let server_entity = spawn_messaging_endpoint(&mut app, &network, TestProtocol);
+    let client_entity = spawn_messaging_endpoint(&mut app, &network, OtherProtocol);
+    connect_endpoints(&mut app, client_entity, server_entity);
+
+    (app, client_entity, server_entity)
+}
+
+#[test]
+fn matching_protocols_are_verified() {
+    let mut client_protocol = ProtocolBuilder::<OtherProtocol>::new();
+    client_protocol.add_message::<ChatMessage>();
+
+    let (mut app, client_entity, server_entity) = handshake_app(client_protocol, true, None);
+
+    assert!(collect_disconnects(&mut app).is_empty());
+
+    for endpoint_entity in [client_entity, server_entity] {
+        let connection_entity = endpoint_connection(&mut app, endpoint_entity);
+
+        assert_eq!(
+            app.world().get::<ProtocolHandshake>(connection_entity),
+            Some(&ProtocolHandshake::Verified)
+        );
+    }
+}
+
+#[test]
+fn mismatched_protocols_are_disconnected() {
+    let mut client_protocol = ProtocolBuilder::<OtherProtocol>::new();
+    client_protocol.add_message::<ChatMessage>();
+    client_protocol.add_message::<OtherMessage>();
+
+    let (mut app, client_entity, server_entity) = handshake_app(client_protocol, true, None);
+
+    let disconnected = collect_disconnects(&mut app);
+
+    assert!(disconnected.contains(&(client_entity, DisconnectReason::ProtocolMismatch)));
+    assert!(disconnected.contains(&(server_entity, DisconnectReason::ProtocolMismatch)));
+}
+
+#[test]
+fn handshake_times_out() {
+    let mut client_protocol = ProtocolBuilder::<OtherProtocol>::new();
+    client_protocol.add_message::<ChatMessage>();
+
+    // the client never sends it's fingerprint
+    let (mut app, client_entity, server_entity) =
+        handshake_app(client_protocol, false, Some(Duration::ZERO));
+
+    let disconnected = collect_disconnects(&mut app);
+
+    assert!(disconnected.contains(&(client_entity, DisconnectReason::ProtocolMismatch)));
+    assert!(disconnected.contains(&(server_entity, DisconnectReason::ProtocolMismatch)));
+}
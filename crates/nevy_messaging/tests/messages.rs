@@ -0,0 +1,88 @@
+use bevy::ecs::system::RunSystemOnce;
+use bevy_interface::prelude::*;
+use nevy_messaging::prelude::*;
+
+mod common;
+
+use common::*;
+
+#[test]
+fn messages_round_trip() {
+    let (mut app, client_entity, server_entity) = messaging_app();
+
+    update(&mut app);
+
+    let client_connection = endpoint_connection(&mut app, client_entity);
+    let server_connection = endpoint_connection(&mut app, server_entity);
+
+    assert_eq!(
+        app.world().get::<ConnectionStatus>(client_connection),
+        Some(&ConnectionStatus::Connected)
+    );
+
+    app.world_mut()
+        .run_system_once(move |mut messages: SendMessages<TestProtocol>| {
+            messages
+                .send(
+                    client_connection,
+                    &ChatMessage {
+                        text: "hello server".into(),
+                    },
+                )
+                .unwrap();
+        })
+        .unwrap();
+
+    update(&mut app);
+
+    let mut received = app
+        .world_mut()
+        .get_mut::<ReceivedMessages<ChatMessage>>(server_connection)
+        .unwrap();
+
+    assert_eq!(
+        received.pop(),
+        Some(ChatMessage {
+            text: "hello server".into(),
+        })
+    );
+    assert_eq!(received.pop(), None);
+}
+
+#[test]
+fn foreign_datagrams_are_not_taken() {
+    let (mut app, client_entity, server_entity) = messaging_app();
+
+    update(&mut app);
+
+    let client_connection = endpoint_connection(&mut app, client_entity);
+    let server_connection = endpoint_connection(&mut app, server_entity);
+
+    app.world_mut()
+        .run_system_once(move |mut connections: Connections| {
+            let mut endpoint = connections
+                .connection_endpoint_mut(client_connection)
+                .unwrap();
+            let mut connection = endpoint.connection_mut(client_connection).unwrap();
+
+            // the messaging header is 1
+            connection.send_datagram(&[0, 2, 0, 0, 0]).unwrap();
+        })
+        .unwrap();
+
+    update(&mut app);
+
+    let datagram = app
+        .world_mut()
+        .run_system_once(move |mut connections: Connections| {
+            let mut endpoint = connections
+                .connection_endpoint_mut(server_connection)
+                .unwrap();
+            let mut connection = endpoint.connection_mut(server_connection).unwrap();
+
+            connection.recv_datagram()
+        })
+        .unwrap();
+
+    assert_eq!(datagram.as_deref(), Some([0, 2, 0, 0, 0].as_slice()));
+}
@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use bevy::{ecs::system::RunSystemOnce, prelude::*};
+use nevy_messaging::prelude::*;
+use serde::{Deserialize, Serialize};
+
+mod common;
+
+use common::*;
+
+/// asks the server to double a number
+#[derive(Serialize, Deserialize)]
+struct Double(u32);
+
+/// an app with the test protocol and the `Double` request,
+/// returning the client and server connections once they are connected
+fn rpc_app() -> (App, Entity, Entity) {
+    let mut protocol = test_protocol();
+    protocol.add_request::<Double, u32>();
+
+    let (mut app, client_entity, server_entity) = protocol_app(&protocol);
+
+    update(&mut app);
+
+    let client_connection = endpoint_connection(&mut app, client_entity);
+    let server_connection = endpoint_connection(&mut app, server_entity);
+
+    (app, client_connection, server_connection)
+}
+
+fn request(
+    app: &mut App,
+    connection_entity: Entity,
+    value: u32,
+    timeout: Option<Duration>,
+) -> RpcHandle<u32> {
+    app.world_mut()
+        .run_system_once(move |mut rpc: Rpc<TestProtocol>| {
+            rpc.request_with_timeout(connection_entity, Double(value), timeout)
+                .unwrap()
+        })
+        .unwrap()
+}
+
+/// updates the app and collects the requests that were received
+fn receive_requests(app: &mut App) -> Vec<(Entity, RpcRequestId<Double>, u32)> {
+    let mut requests = Vec::new();
+    let mut cursor = app
+        .world()
+        .resource::<Events<RpcRequestReceived<Double>>>()
+        .get_cursor_current();
+
+    for _ in 0..10 {
+        app.update();
+
+        let events = app.world().resource::<Events<RpcRequestReceived<Double>>>();
+        requests.extend(
+            cursor
+                .read(events)
+                .map(|event| (event.connection_entity, event.request_id, event.request.0)),
+        );
+    }
+
+    requests
+}
+
+fn respond(app: &mut App, connection_entity: Entity, request_id: RpcRequestId<Double>, value: u32) {
+    app.world_mut()
+        .run_system_once(move |mut rpc: Rpc<TestProtocol>| {
+            rpc.respond(connection_entity, request_id, value * 2)
+                .unwrap();
+        })
+        .unwrap();
+}
+
+#[test]
+fn responses_are_routed_by_request_id() {
+    let (mut app, client_connection, server_connection) = rpc_app();
+
+    let first = request(&mut app, client_connection, 1, None);
+    let second = request(&mut app, client_connection, 2, None);
+
+    let requests = receive_requests(&mut app);
+    assert_eq!(requests.len(), 2);
+
+    // respond in the opposite order the requests were sent in
+    for &(connection_entity, request_id, value) in requests.iter().rev() {
+        assert_eq!(connection_entity, server_connection);
+        respond(&mut app, connection_entity, request_id, value);
+    }
+
+    update(&mut app);
+
+    assert_eq!(first.poll_response(), Some(Ok(2)));
+    assert_eq!(second.poll_response(), Some(Ok(4)));
+    assert_eq!(first.poll_response(), None);
+}
+
+#[test]
+fn requests_time_out() {
+    let (mut app, client_connection, _) = rpc_app();
+
+    let handle = request(&mut app, client_connection, 1, Some(Duration::ZERO));
+
+    // the server never responds
+    update(&mut app);
+
+    assert_eq!(handle.poll_response(), Some(Err(RpcError::TimedOut)));
+}
+
+#[test]
+fn responses_after_a_timeout_are_ignored() {
+    let (mut app, client_connection, _) = rpc_app();
+
+    let handle = request(&mut app, client_connection, 1, Some(Duration::ZERO));
+
+    let requests = receive_requests(&mut app);
+    assert!(handle.is_finished());
+
+    for (connection_entity, request_id, value) in requests {
+        respond(&mut app, connection_entity, request_id, value);
+    }
+
+    update(&mut app);
+
+    assert_eq!(handle.poll_response(), Some(Err(RpcError::TimedOut)));
+    assert_eq!(handle.poll_response(), None);
+}
+
+#[test]
+fn responses_to_cancelled_requests_are_ignored() {
+    let (mut app, client_connection, _) = rpc_app();
+
+    let cancelled = request(&mut app, client_connection, 1, None);
+    let dropped = request(&mut app, client_connection, 2, None);
+    let answered = request(&mut app, client_connection, 3, None);
+
+    cancelled.cancel();
+    drop(dropped);
+
+    for (connection_entity, request_id, value) in receive_requests(&mut app) {
+        respond(&mut app, connection_entity, request_id, value);
+    }
+
+    update(&mut app);
+
+    assert_eq!(cancelled.poll_response(), Some(Err(RpcError::Cancelled)));
+    assert_eq!(answered.poll_response(), Some(Ok(6)));
+}
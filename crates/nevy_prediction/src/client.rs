@@ -0,0 +1,639 @@
+//! predicts the local player's inputs and reconciles them with the server's state
+
+use std::{collections::VecDeque, marker::PhantomData};
+
+use bevy::{
+    ecs::{intern::Interned, schedule::ScheduleLabel},
+    prelude::*,
+};
+use nevy_messaging::{deserialize::ReceivedMessages, serialize::SendMessages};
+use serde::Serialize;
+
+use crate::{InputAck, InputMessage, PredictionSimulate, PredictionSystems};
+
+/// the default number of ticks of inputs and predicted state that are kept
+pub const DEFAULT_HISTORY_LENGTH: usize = 128;
+
+/// predicts inputs of type `I` and sends them to the server on connections of endpoints with the marker `C`
+///
+/// the input messages need to be added to the protocol with [add_prediction_messages](crate::add_prediction_messages)
+pub struct PredictionClientPlugin<C, I> {
+    _p: PhantomData<(C, I)>,
+    schedule: Interned<dyn ScheduleLabel>,
+    history_length: usize,
+    components: Vec<PredictedComponentFns>,
+}
+
+/// type erased functions for a predicted component
+#[derive(Clone, Copy)]
+struct PredictedComponentFns {
+    /// marks new confirmations as checked
+    /// and returns the earliest tick where the confirmed state differs from the prediction
+    check: fn(&mut World) -> Option<u32>,
+    /// sets predicted components to their state at a tick and forgets everything after it
+    rewind: fn(&mut World, u32),
+    /// records the state of predicted components at a tick
+    record: fn(&mut World, u32, usize),
+}
+
+impl<C, I> PredictionClientPlugin<C, I> {
+    pub fn new(schedule: impl ScheduleLabel) -> Self {
+        PredictionClientPlugin {
+            _p: PhantomData,
+            schedule: schedule.intern(),
+            history_length: DEFAULT_HISTORY_LENGTH,
+            components: Vec::new(),
+        }
+    }
+
+    /// sets the number of ticks of inputs and predicted state that are kept
+    ///
+    /// state confirmed for a tick older than this can't be reconciled.
+    /// defaults to [DEFAULT_HISTORY_LENGTH]
+    pub fn set_history_length(&mut self, history_length: usize) -> &mut Self {
+        self.history_length = history_length;
+        self
+    }
+
+    /// adds a component type that is predicted on [Predicted] entities
+    pub fn predict<T: Component + Clone + PartialEq>(&mut self) -> &mut Self {
+        self.components.push(PredictedComponentFns {
+            check: check_component::<T>,
+            rewind: rewind_component::<T>,
+            record: record_component::<T>,
+        });
+        self
+    }
+}
+
+impl<C: Component, I: Serialize + Default + Clone + Send + Sync + 'static> Plugin
+    for PredictionClientPlugin<C, I>
+{
+    fn build(&self, app: &mut App) {
+        app.init_schedule(PredictionSimulate);
+
+        app.init_resource::<CurrentInput<I>>();
+
+        app.insert_resource(InputHistory::<I> {
+            tick: 0,
+            acked_tick: None,
+            sent_tick: 0,
+            history_length: self.history_length,
+            inputs: VecDeque::new(),
+        });
+
+        app.insert_resource(PredictedComponents::<I> {
+            _p: PhantomData,
+            components: self.components.clone(),
+        });
+
+        app.configure_sets(
+            self.schedule,
+            (
+                PredictionSystems::Reconcile,
+                PredictionSystems::Simulate,
+                PredictionSystems::Send,
+            )
+                .chain(),
+        );
+
+        app.add_systems(
+            self.schedule,
+            (
+                receive_acks::<C, I>.before(PredictionSystems::Reconcile),
+                reconcile::<I>.in_set(PredictionSystems::Reconcile),
+                advance_tick::<I>.in_set(PredictionSystems::Simulate),
+                send_inputs::<C, I>.in_set(PredictionSystems::Send),
+            ),
+        );
+    }
+}
+
+/// Insert onto entities whose components are predicted
+#[derive(Component)]
+pub struct Predicted;
+
+/// the server's state of a predicted component at a tick
+///
+/// insert or update this when authoritative state arrives,
+/// usually for the [acked tick](InputHistory::acked_tick).
+/// each confirmation is only checked once so a new confirmation needs a newer tick
+#[derive(Component)]
+pub struct Confirmed<T> {
+    pub tick: u32,
+    pub value: T,
+}
+
+impl<T> Confirmed<T> {
+    pub fn new(tick: u32, value: T) -> Self {
+        Confirmed { tick, value }
+    }
+}
+
+/// triggered before inputs are simulated again because a prediction didn't match it's [Confirmed] state
+///
+/// observe this to rewind state that isn't predicted with components
+#[derive(Event, Debug, Clone, Copy)]
+pub struct Rollback {
+    /// the tick that predicted components were rewound to,
+    /// inputs after this tick will be simulated again
+    pub tick: u32,
+}
+
+/// the input that is simulated for the current tick
+///
+/// set this every tick before [PredictionSystems::Simulate].
+/// while inputs are being replayed it contains the replayed input
+#[derive(Resource, Default)]
+pub struct CurrentInput<I> {
+    pub input: I,
+}
+
+/// the inputs that have been simulated and the server's progress processing them
+#[derive(Resource)]
+pub struct InputHistory<I> {
+    tick: u32,
+    acked_tick: Option<u32>,
+    /// the last tick that was sent to the server on every connection,
+    /// inputs after it are sent on the next update
+    sent_tick: u32,
+    history_length: usize,
+    inputs: VecDeque<(u32, I)>,
+}
+
+impl<I> InputHistory<I> {
+    /// the tick of the last simulated input
+    ///
+    /// ticks start at 1, 0 means that no input has been simulated.
+    /// this counts the client's simulated inputs and isn't the tick of a
+    /// [NetworkClock](nevy_messaging::clock::NetworkClock)
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    /// the last tick that the server has processed the input of
+    pub fn acked_tick(&self) -> Option<u32> {
+        self.acked_tick
+    }
+
+    /// gets the input that was simulated at a tick if it is still in the history
+    pub fn get(&self, tick: u32) -> Option<&I> {
+        let &(first_tick, _) = self.inputs.front()?;
+        let (input_tick, input) = self.inputs.get(tick.checked_sub(first_tick)? as usize)?;
+        (*input_tick == tick).then_some(input)
+    }
+
+    /// iterates over the inputs that the server hasn't acknowledged yet
+    pub fn unacked(&self) -> impl Iterator<Item = (u32, &I)> + '_ {
+        self.inputs
+            .iter()
+            .filter(|(tick, _)| self.acked_tick.is_none_or(|acked_tick| *tick > acked_tick))
+            .map(|(tick, input)| (*tick, input))
+    }
+}
+
+/// Contains the functions of each predicted component
+#[derive(Resource)]
+struct PredictedComponents<I> {
+    _p: PhantomData<I>,
+    components: Vec<PredictedComponentFns>,
+}
+
+/// the recorded state of a predicted component
+#[derive(Component)]
+struct PredictionHistory<T> {
+    states: VecDeque<(u32, T)>,
+    /// the tick of the last [Confirmed] state that was checked
+    confirmed_tick: Option<u32>,
+}
+
+impl<T> PredictionHistory<T> {
+    fn get(&self, tick: u32) -> Option<&T> {
+        self.states
+            .iter()
+            .rev()
+            .find(|(state_tick, _)| *state_tick == tick)
+            .map(|(_, state)| state)
+    }
+}
+
+fn check_component<T: Component + PartialEq>(world: &mut World) -> Option<u32> {
+    let mut query = world
+        .query_filtered::<(&Confirmed<T>, &mut PredictionHistory<T>), (With<Predicted>, With<T>)>();
+
+    let mut rollback_tick: Option<u32> = None;
+
+    for (confirmed, mut history) in query.iter_mut(world) {
+        if history
+            .confirmed_tick
+            .is_some_and(|confirmed_tick| confirmed.tick <= confirmed_tick)
+        {
+            continue;
+        }
+
+        history.confirmed_tick = Some(confirmed.tick);
+
+        // confirmations that are older than the history can't be reconciled
+        let Some(predicted) = history.get(confirmed.tick) else {
+            continue;
+        };
+
+        if *predicted != confirmed.value {
+            rollback_tick =
+                Some(rollback_tick.map_or(confirmed.tick, |tick| tick.min(confirmed.tick)));
+        }
+    }
+
+    rollback_tick
+}
+
+fn rewind_component<T: Component + Clone>(world: &mut World, tick: u32) {
+    let mut query = world.query_filtered::<(
+        &mut T,
+        &mut PredictionHistory<T>,
+        Option<&Confirmed<T>>,
+    ), With<Predicted>>();
+
+    for (mut value, mut history, confirmed) in query.iter_mut(world) {
+        while history
+            .states
+            .back()
+            .is_some_and(|&(state_tick, _)| state_tick > tick)
+        {
+            history.states.pop_back();
+        }
+
+        if let Some(confirmed) = confirmed.filter(|confirmed| confirmed.tick == tick) {
+            *value = confirmed.value.clone();
+        } else if let Some((state_tick, state)) = history.states.back() {
+            if *state_tick == tick {
+                *value = state.clone();
+            }
+        }
+    }
+}
+
+fn record_component<T: Component + Clone>(world: &mut World, tick: u32, history_length: usize) {
+    let mut new_query =
+        world.query_filtered::<Entity, (With<Predicted>, With<T>, Without<PredictionHistory<T>>)>();
+    let new_entities: Vec<Entity> = new_query.iter(world).collect();
+
+    for entity in new_entities {
+        world.entity_mut(entity).insert(PredictionHistory::<T> {
+            states: VecDeque::new(),
+            confirmed_tick: None,
+        });
+    }
+
+    let mut query = world.query_filtered::<(
+        &mut T,
+        &mut PredictionHistory<T>,
+        Option<&Confirmed<T>>,
+    ), With<Predicted>>();
+
+    for (mut value, mut history, confirmed) in query.iter_mut(world) {
+        // snap to the confirmed state when it's tick is simulated again
+        if let Some(confirmed) = confirmed.filter(|confirmed| confirmed.tick == tick) {
+            *value = confirmed.value.clone();
+        }
+
+        while history
+            .states
+            .back()
+            .is_some_and(|&(state_tick, _)| state_tick >= tick)
+        {
+            history.states.pop_back();
+        }
+
+        history.states.push_back((tick, value.clone()));
+
+        while history.states.len() > history_length {
+            history.states.pop_front();
+        }
+    }
+}
+
+fn reconcile<I: Clone + Send + Sync + 'static>(world: &mut World) {
+    let components = world
+        .resource::<PredictedComponents<I>>()
+        .components
+        .clone();
+
+    let Some(rollback_tick) = components
+        .iter()
+        .filter_map(|component| (component.check)(world))
+        .min()
+    else {
+        return;
+    };
+
+    for component in components.iter() {
+        (component.rewind)(world, rollback_tick);
+    }
+
+    world.trigger(Rollback {
+        tick: rollback_tick,
+    });
+
+    let history = world.resource::<InputHistory<I>>();
+    let history_length = history.history_length;
+    let replayed: Vec<(u32, I)> = history
+        .inputs
+        .iter()
+        .filter(|(tick, _)| *tick > rollback_tick)
+        .cloned()
+        .collect();
+
+    let current_input = world.resource::<CurrentInput<I>>().input.clone();
+
+    for (tick, input) in replayed {
+        world.resource_mut::<CurrentInput<I>>().input = input;
+        world.run_schedule(PredictionSimulate);
+
+        for component in components.iter() {
+            (component.record)(world, tick, history_length);
+        }
+    }
+
+    world.resource_mut::<CurrentInput<I>>().input = current_input;
+}
+
+fn advance_tick<I: Clone + Send + Sync + 'static>(world: &mut World) {
+    let input = world.resource::<CurrentInput<I>>().input.clone();
+
+    let mut history = world.resource_mut::<InputHistory<I>>();
+    history.tick += 1;
+    let tick = history.tick;
+    let history_length = history.history_length;
+
+    history.inputs.push_back((tick, input));
+    while history.inputs.len() > history_length {
+        history.inputs.pop_front();
+    }
+
+    world.run_schedule(PredictionSimulate);
+
+    let components = world
+        .resource::<PredictedComponents<I>>()
+        .components
+        .clone();
+    for component in components.iter() {
+        (component.record)(world, tick, history_length);
+    }
+}
+
+fn send_inputs<C: Component, I: Serialize + Clone + Send + Sync + 'static>(
+    mut messages: SendMessages<C>,
+    mut history: ResMut<InputHistory<I>>,
+    connection_q: Query<(Entity, &Parent), With<ReceivedMessages<InputAck<I>>>>,
+    endpoint_q: Query<(), With<C>>,
+) {
+    let sent_tick = history.sent_tick;
+
+    // inputs that fail to send are sent again on the next update,
+    // the server drops inputs it has already received
+    let mut new_sent_tick = history.tick;
+
+    for (connection_entity, connection_parent) in connection_q.iter() {
+        if !endpoint_q.contains(connection_parent.get()) {
+            continue;
+        }
+
+        for (tick, input) in history.inputs.iter() {
+            if *tick <= sent_tick {
+                continue;
+            }
+
+            let message = InputMessage {
+                tick: *tick,
+                input: input.clone(),
+            };
+
+            if let Err(err) = messages.send(connection_entity, &message) {
+                warn!(
+                    "failed to send the input for tick {} to connection {:?}, it will be sent again: {:?}",
+                    tick, connection_entity, err
+                );
+                new_sent_tick = new_sent_tick.min(tick - 1);
+                break;
+            }
+        }
+    }
+
+    history.sent_tick = new_sent_tick;
+}
+
+fn receive_acks<C: Component, I: Send + Sync + 'static>(
+    mut history: ResMut<InputHistory<I>>,
+    mut connection_q: Query<(&mut ReceivedMessages<InputAck<I>>, &Parent)>,
+    endpoint_q: Query<(), With<C>>,
+) {
+    for (mut messages, connection_parent) in connection_q.iter_mut() {
+        if !endpoint_q.contains(connection_parent.get()) {
+            continue;
+        }
+
+        for InputAck { tick, .. } in messages.drain() {
+            if history
+                .acked_tick
+                .is_none_or(|acked_tick| tick > acked_tick)
+            {
+                history.acked_tick = Some(tick);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_interface::prelude::*;
+    use nevy_messaging::ProtocolBuilder;
+
+    use super::*;
+
+    #[derive(Component)]
+    struct TestProtocol;
+
+    #[derive(Component, Clone, PartialEq, Debug)]
+    struct Position(i32);
+
+    /// the inputs simulated in order, including replayed inputs
+    #[derive(Resource, Default)]
+    struct Simulated(Vec<i32>);
+
+    /// the ticks of every [Rollback]
+    #[derive(Resource, Default)]
+    struct Rollbacks(Vec<u32>);
+
+    fn simulate(
+        input: Res<CurrentInput<i32>>,
+        mut simulated: ResMut<Simulated>,
+        mut position_q: Query<&mut Position, With<Predicted>>,
+    ) {
+        simulated.0.push(input.input);
+
+        for mut position in position_q.iter_mut() {
+            position.0 += input.input;
+        }
+    }
+
+    /// an app that predicts the [Position] of a single entity
+    fn prediction_app(history_length: usize) -> (App, Entity) {
+        let mut protocol = ProtocolBuilder::<TestProtocol>::new();
+        crate::add_prediction_messages::<_, _, i32>(&mut protocol);
+
+        let mut plugin = PredictionClientPlugin::<TestProtocol, i32>::new(Update);
+        plugin.set_history_length(history_length);
+        plugin.predict::<Position>();
+
+        let mut app = App::new();
+        app.add_event::<Connected>();
+        app.add_plugins(protocol.build_serialization());
+        app.add_plugins(plugin);
+
+        app.init_resource::<Simulated>();
+        app.init_resource::<Rollbacks>();
+        app.add_systems(PredictionSimulate, simulate);
+        app.add_observer(
+            |trigger: Trigger<Rollback>, mut rollbacks: ResMut<Rollbacks>| {
+                rollbacks.0.push(trigger.event().tick);
+            },
+        );
+
+        let entity = app.world_mut().spawn((Predicted, Position(0))).id();
+
+        (app, entity)
+    }
+
+    /// simulates an input for the next tick
+    fn tick(app: &mut App, input: i32) {
+        app.world_mut().resource_mut::<CurrentInput<i32>>().input = input;
+        app.update();
+    }
+
+    /// simulates the inputs 1 to 5 for ticks 1 to 5, and forgets them
+    fn simulate_five_ticks(app: &mut App) {
+        for input in 1..=5 {
+            tick(app, input);
+        }
+
+        app.world_mut().resource_mut::<Simulated>().0.clear();
+    }
+
+    fn position(app: &App, entity: Entity) -> i32 {
+        app.world().get::<Position>(entity).unwrap().0
+    }
+
+    fn simulated(app: &App) -> &[i32] {
+        &app.world().resource::<Simulated>().0
+    }
+
+    fn rollbacks(app: &App) -> &[u32] {
+        &app.world().resource::<Rollbacks>().0
+    }
+
+    #[test]
+    fn predictions_are_recorded() {
+        let (mut app, entity) = prediction_app(DEFAULT_HISTORY_LENGTH);
+
+        simulate_five_ticks(&mut app);
+
+        assert_eq!(position(&app, entity), 15);
+        assert_eq!(app.world().resource::<InputHistory<i32>>().tick(), 5);
+        assert_eq!(app.world().resource::<InputHistory<i32>>().get(3), Some(&3));
+
+        let history = app
+            .world()
+            .get::<PredictionHistory<Position>>(entity)
+            .unwrap();
+        assert_eq!(history.get(2), Some(&Position(3)));
+        assert_eq!(history.get(5), Some(&Position(15)));
+    }
+
+    #[test]
+    fn matching_confirmations_dont_roll_back() {
+        let (mut app, entity) = prediction_app(DEFAULT_HISTORY_LENGTH);
+
+        simulate_five_ticks(&mut app);
+
+        app.world_mut()
+            .entity_mut(entity)
+            .insert(Confirmed::new(2, Position(3)));
+        tick(&mut app, 6);
+
+        assert!(rollbacks(&app).is_empty());
+        assert_eq!(simulated(&app), [6]);
+        assert_eq!(position(&app, entity), 21);
+    }
+
+    #[test]
+    fn mismatched_confirmations_replay_the_inputs_after_them() {
+        let (mut app, entity) = prediction_app(DEFAULT_HISTORY_LENGTH);
+
+        simulate_five_ticks(&mut app);
+
+        app.world_mut()
+            .entity_mut(entity)
+            .insert(Confirmed::new(2, Position(100)));
+        tick(&mut app, 6);
+
+        assert_eq!(rollbacks(&app), [2]);
+        // the inputs for ticks 3 to 5 are replayed before tick 6 is simulated
+        assert_eq!(simulated(&app), [3, 4, 5, 6]);
+        assert_eq!(position(&app, entity), 100 + 3 + 4 + 5 + 6);
+
+        let history = app
+            .world()
+            .get::<PredictionHistory<Position>>(entity)
+            .unwrap();
+        assert_eq!(history.get(2), Some(&Position(3)));
+        assert_eq!(history.get(3), Some(&Position(103)));
+        assert_eq!(history.get(6), Some(&Position(118)));
+
+        // each confirmation is only checked once
+        tick(&mut app, 7);
+
+        assert_eq!(rollbacks(&app), [2]);
+        assert_eq!(position(&app, entity), 125);
+    }
+
+    #[test]
+    fn the_earliest_mismatch_is_rolled_back_to() {
+        let (mut app, entity) = prediction_app(DEFAULT_HISTORY_LENGTH);
+        let other_entity = app.world_mut().spawn((Predicted, Position(0))).id();
+
+        simulate_five_ticks(&mut app);
+
+        app.world_mut()
+            .entity_mut(entity)
+            .insert(Confirmed::new(4, Position(0)));
+        app.world_mut()
+            .entity_mut(other_entity)
+            .insert(Confirmed::new(3, Position(0)));
+        tick(&mut app, 6);
+
+        assert_eq!(rollbacks(&app), [3]);
+        assert_eq!(simulated(&app), [4, 5, 6]);
+        assert_eq!(position(&app, other_entity), 4 + 5 + 6);
+        // rewound to it's prediction for tick 3, then corrected when tick 4 was replayed
+        assert_eq!(position(&app, entity), 5 + 6);
+    }
+
+    #[test]
+    fn confirmations_older_than_the_history_are_ignored() {
+        let (mut app, entity) = prediction_app(3);
+
+        simulate_five_ticks(&mut app);
+
+        assert_eq!(app.world().resource::<InputHistory<i32>>().get(1), None);
+
+        app.world_mut()
+            .entity_mut(entity)
+            .insert(Confirmed::new(1, Position(100)));
+        tick(&mut app, 6);
+
+        assert!(rollbacks(&app).is_empty());
+        assert_eq!(simulated(&app), [6]);
+        assert_eq!(position(&app, entity), 21);
+    }
+}
@@ -0,0 +1,84 @@
+//! client side prediction and server reconciliation
+//!
+//! clients gather an input every tick and simulate it immediately in the [PredictionSimulate] schedule,
+//! recording the state of their [Predicted](client::Predicted) components.
+//! inputs are sent to the server stamped with the client's tick,
+//! which processes them in order and acknowledges the last tick it processed.
+//! when authoritative state for a tick arrives as a [Confirmed](client::Confirmed) component
+//! and doesn't match what was predicted, the predicted components are rewound
+//! and every input after that tick is simulated again
+
+use std::marker::PhantomData;
+
+use bevy::{ecs::schedule::ScheduleLabel, prelude::*};
+use nevy_messaging::{codec::MessageCodec, deserialize::MessageDelivery, ProtocolBuilder};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+pub mod client;
+pub mod server;
+
+pub mod prelude {
+    pub use crate::client::{
+        Confirmed, CurrentInput, InputHistory, Predicted, PredictionClientPlugin, Rollback,
+        DEFAULT_HISTORY_LENGTH,
+    };
+
+    pub use crate::server::{
+        ConnectionInputs, PredictionServerPlugin, DEFAULT_MAX_BUFFERED_INPUTS,
+    };
+
+    pub use crate::{add_prediction_messages, PredictionSimulate, PredictionSystems};
+}
+
+/// the schedule that predicted components are simulated in on clients
+///
+/// it is run once every tick, and again for every input that is replayed after a [Rollback](client::Rollback).
+/// systems in it should read the input from [CurrentInput](client::CurrentInput)
+#[derive(ScheduleLabel, Clone, PartialEq, Eq, Debug, std::hash::Hash)]
+pub struct PredictionSimulate;
+
+/// system sets where inputs are predicted and reconciled on clients
+#[derive(Clone, PartialEq, Eq, Debug, std::hash::Hash, SystemSet)]
+pub enum PredictionSystems {
+    /// predicted components are compared with their [Confirmed](client::Confirmed) state,
+    /// and rewound and simulated again if they don't match
+    Reconcile,
+    /// the tick is advanced and the [CurrentInput](client::CurrentInput) is simulated,
+    /// the input should be set before this set
+    Simulate,
+    /// new inputs are sent to the server
+    Send,
+}
+
+/// adds the messages used for inputs of type `I` to a protocol
+///
+/// they are always received as [ReceivedMessages](nevy_messaging::deserialize::ReceivedMessages)
+/// components, regardless of the protocol's [MessageDelivery]
+pub fn add_prediction_messages<C, M, I>(protocol: &mut ProtocolBuilder<C, M>)
+where
+    C: Component,
+    M: MessageCodec,
+    I: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    protocol.add_message::<InputMessage<I>>();
+    protocol.add_message::<InputAck<I>>();
+    protocol.set_message_delivery::<InputMessage<I>>(MessageDelivery::Component);
+    protocol.set_message_delivery::<InputAck<I>>(MessageDelivery::Component);
+}
+
+/// an input sent from a client to the server
+#[derive(Serialize, Deserialize)]
+pub(crate) struct InputMessage<I> {
+    pub(crate) tick: u32,
+    pub(crate) input: I,
+}
+
+/// the last tick the server processed an input for
+///
+/// generic over the input so that different input types don't share a message id
+#[derive(Serialize, Deserialize)]
+pub(crate) struct InputAck<I> {
+    pub(crate) tick: u32,
+    #[serde(skip)]
+    pub(crate) _p: PhantomData<I>,
+}
@@ -0,0 +1,213 @@
+//! buffers the inputs received from clients and acknowledges the ticks that were processed
+
+use std::{collections::VecDeque, marker::PhantomData};
+
+use bevy::{
+    ecs::{intern::Interned, schedule::ScheduleLabel},
+    prelude::*,
+};
+use bevy_interface::prelude::*;
+use nevy_messaging::{deserialize::ReceivedMessages, serialize::SendMessages};
+
+use crate::{InputAck, InputMessage};
+
+/// the default number of unprocessed inputs that are buffered for a connection
+pub const DEFAULT_MAX_BUFFERED_INPUTS: usize = 64;
+
+/// receives inputs of type `I` from the connections of endpoints with the marker `C`
+///
+/// the input messages need to be added to the protocol with [add_prediction_messages](crate::add_prediction_messages)
+pub struct PredictionServerPlugin<C, I> {
+    _p: PhantomData<(C, I)>,
+    schedule: Interned<dyn ScheduleLabel>,
+    max_buffered_inputs: usize,
+}
+
+impl<C, I> PredictionServerPlugin<C, I> {
+    pub fn new(schedule: impl ScheduleLabel) -> Self {
+        PredictionServerPlugin {
+            _p: PhantomData,
+            schedule: schedule.intern(),
+            max_buffered_inputs: DEFAULT_MAX_BUFFERED_INPUTS,
+        }
+    }
+
+    /// sets the maximum number of unprocessed inputs buffered for a connection
+    ///
+    /// when more inputs arrive the oldest are dropped.
+    /// defaults to [DEFAULT_MAX_BUFFERED_INPUTS]
+    pub fn set_max_buffered_inputs(&mut self, max_buffered_inputs: usize) -> &mut Self {
+        self.max_buffered_inputs = max_buffered_inputs;
+        self
+    }
+}
+
+impl<C: Component, I: Send + Sync + 'static> Plugin for PredictionServerPlugin<C, I> {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InputBufferConfig::<C, I> {
+            _p: PhantomData,
+            max_buffered_inputs: self.max_buffered_inputs,
+        });
+
+        app.add_systems(
+            self.schedule,
+            (
+                insert_connection_inputs::<C, I>,
+                receive_inputs::<C, I>,
+                send_acks::<C, I>,
+            ),
+        );
+    }
+}
+
+type ReceivedInputs<I> = ReceivedMessages<InputMessage<I>>;
+
+/// Contains the configuration of a [PredictionServerPlugin]
+#[derive(Resource)]
+struct InputBufferConfig<C, I> {
+    _p: PhantomData<(C, I)>,
+    max_buffered_inputs: usize,
+}
+
+/// the inputs received from a connection that haven't been processed yet
+///
+/// inputs are processed by taking them with [pop](ConnectionInputs::pop),
+/// the tick of the last popped input is acknowledged to the client.
+/// if inputs aren't processed fast enough the oldest are dropped,
+/// see [PredictionServerPlugin::set_max_buffered_inputs]
+#[derive(Component)]
+pub struct ConnectionInputs<I> {
+    inputs: VecDeque<(u32, I)>,
+    /// the tick of the last received input
+    received_tick: Option<u32>,
+    processed_tick: Option<u32>,
+    acked_tick: Option<u32>,
+}
+
+impl<I> ConnectionInputs<I> {
+    /// takes the next input and it's tick, marking it as processed
+    pub fn pop(&mut self) -> Option<(u32, I)> {
+        let (tick, input) = self.inputs.pop_front()?;
+        self.processed_tick = Some(tick);
+        Some((tick, input))
+    }
+
+    /// the next input and it's tick without processing it
+    pub fn peek(&self) -> Option<(u32, &I)> {
+        self.inputs.front().map(|(tick, input)| (*tick, input))
+    }
+
+    /// the number of inputs waiting to be processed
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// the tick of the last input that was processed
+    ///
+    /// authoritative state sent to the client should be confirmed for this tick
+    pub fn processed_tick(&self) -> Option<u32> {
+        self.processed_tick
+    }
+}
+
+fn insert_connection_inputs<C: Component, I: Send + Sync + 'static>(
+    mut commands: Commands,
+    mut connected_r: EventReader<Connected>,
+    endpoint_q: Query<(), With<C>>,
+) {
+    for &Connected {
+        endpoint_entity,
+        connection_entity,
+    } in connected_r.read()
+    {
+        if !endpoint_q.contains(endpoint_entity) {
+            continue;
+        }
+
+        // the connection may have been closed before this system ran
+        let Some(mut connection_commands) = commands.get_entity(connection_entity) else {
+            continue;
+        };
+
+        connection_commands.try_insert(ConnectionInputs::<I> {
+            inputs: VecDeque::new(),
+            received_tick: None,
+            processed_tick: None,
+            acked_tick: None,
+        });
+    }
+}
+
+fn receive_inputs<C: Component, I: Send + Sync + 'static>(
+    mut connection_q: Query<(&mut ConnectionInputs<I>, &mut ReceivedInputs<I>, &Parent)>,
+    endpoint_q: Query<(), With<C>>,
+    config: Res<InputBufferConfig<C, I>>,
+) {
+    for (mut inputs, mut messages, connection_parent) in connection_q.iter_mut() {
+        if !endpoint_q.contains(connection_parent.get()) {
+            continue;
+        }
+
+        for InputMessage { tick, input } in messages.drain() {
+            // inputs that arrive late or twice are dropped
+            if inputs
+                .received_tick
+                .is_some_and(|received_tick| tick <= received_tick)
+            {
+                continue;
+            }
+
+            inputs.received_tick = Some(tick);
+            inputs.inputs.push_back((tick, input));
+        }
+
+        let overflow = inputs
+            .inputs
+            .len()
+            .saturating_sub(config.max_buffered_inputs);
+        if overflow > 0 {
+            debug!(
+                "dropping {} unprocessed inputs from a connection that sent more than {}",
+                overflow, config.max_buffered_inputs
+            );
+            inputs.inputs.drain(..overflow);
+        }
+    }
+}
+
+fn send_acks<C: Component, I: Send + Sync + 'static>(
+    mut messages: SendMessages<C>,
+    mut connection_q: Query<(Entity, &mut ConnectionInputs<I>, &Parent)>,
+    endpoint_q: Query<(), With<C>>,
+) {
+    for (connection_entity, mut inputs, connection_parent) in connection_q.iter_mut() {
+        if !endpoint_q.contains(connection_parent.get()) {
+            continue;
+        }
+
+        let Some(processed_tick) = inputs.processed_tick else {
+            continue;
+        };
+
+        if inputs.acked_tick == Some(processed_tick) {
+            continue;
+        }
+
+        let message = InputAck::<I> {
+            tick: processed_tick,
+            _p: PhantomData,
+        };
+
+        match messages.send(connection_entity, &message) {
+            Ok(()) => inputs.acked_tick = Some(processed_tick),
+            Err(err) => warn!(
+                "failed to acknowledge tick {} to connection {:?}: {:?}",
+                processed_tick, connection_entity, err
+            ),
+        }
+    }
+}
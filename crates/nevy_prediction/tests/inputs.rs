@@ -0,0 +1,196 @@
+use bevy::{ecs::system::RunSystemOnce, prelude::*, time::TimePlugin};
+use bevy_interface::prelude::*;
+use nevy_loopback::prelude::*;
+use nevy_messaging::prelude::*;
+use nevy_prediction::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// marker for the client endpoint
+#[derive(Component)]
+struct ClientProtocol;
+
+/// marker for the server endpoints
+#[derive(Component)]
+struct ServerProtocol;
+
+/// the address of a test endpoint
+#[derive(Component)]
+struct TestAddress(LoopbackAddress);
+
+/// a message that takes up room in a connection's message queue
+#[derive(Serialize, Deserialize)]
+struct Filler;
+
+fn protocol<C: Component>(max_queued_messages: usize) -> ProtocolBuilder<C> {
+    let mut protocol = ProtocolBuilder::new();
+    add_prediction_messages::<_, _, u32>(&mut protocol);
+    protocol.add_message::<Filler>();
+    protocol.set_max_queued_messages(max_queued_messages);
+    protocol
+}
+
+/// the input of every tick is ten times the tick
+fn set_input(history: Res<InputHistory<u32>>, mut input: ResMut<CurrentInput<u32>>) {
+    input.input = (history.tick() + 1) * 10;
+}
+
+/// an app with a client that predicts inputs and is connected to `servers` servers
+///
+/// returns the app, the client endpoint and the server endpoints
+fn input_app(max_queued_messages: usize, servers: usize) -> (App, Entity, Vec<Entity>) {
+    let mut app = App::new();
+
+    app.add_plugins(TimePlugin);
+    app.add_plugins(EndpointPlugin::default());
+    app.add_plugins(StreamHeaderPlugin::default());
+    app.add_plugins(protocol::<ClientProtocol>(max_queued_messages).build_symmetric(Update));
+    app.add_plugins(protocol::<ServerProtocol>(max_queued_messages).build_symmetric(Update));
+    app.add_plugins(PredictionClientPlugin::<ClientProtocol, u32>::new(Update));
+    app.add_plugins(PredictionServerPlugin::<ServerProtocol, u32>::new(Update));
+    app.add_systems(Update, set_input.before(PredictionSystems::Simulate));
+
+    let network = LoopbackNetwork::new();
+    let client_entity = spawn_endpoint(&mut app, &network, ClientProtocol);
+
+    let server_entities = (0..servers)
+        .map(|_| {
+            let server_entity = spawn_endpoint(&mut app, &network, ServerProtocol);
+            let server_address = app.world().get::<TestAddress>(server_entity).unwrap().0;
+
+            app.world_mut()
+                .run_system_once(move |mut connections: Connections| {
+                    connections
+                        .connect(
+                            client_entity,
+                            Description::new_connect_description::<LoopbackEndpoint>(
+                                server_address,
+                            ),
+                        )
+                        .unwrap()
+                        .unwrap();
+                })
+                .unwrap();
+
+            server_entity
+        })
+        .collect();
+
+    update(&mut app);
+
+    (app, client_entity, server_entities)
+}
+
+fn spawn_endpoint(app: &mut App, network: &LoopbackNetwork, marker: impl Component) -> Entity {
+    let endpoint = LoopbackEndpoint::new(network, None);
+
+    app.world_mut()
+        .spawn((
+            marker,
+            TestAddress(endpoint.local_addr()),
+            BevyEndpoint::new(endpoint),
+            EndpointStreamHeaders,
+            EndpointMessagingHeader { header: 1 },
+            EndpointMessageStreamDescription {
+                description: CloneableDescription::new::<LoopbackStreamId>(LoopbackDir::Uni),
+            },
+            EndpointMessageStreamCloseDescription {
+                description: CloneableDescription::new_send_close::<LoopbackStreamId>(None),
+            },
+            EndpointMessageStreamStopDescription {
+                description: CloneableDescription::new_recv_close::<LoopbackStreamId>(0),
+            },
+        ))
+        .id()
+}
+
+/// updates the app enough times for data to pass through every plugin's systems
+fn update(app: &mut App) {
+    for _ in 0..10 {
+        app.update();
+    }
+}
+
+/// the connections of an endpoint
+fn endpoint_connections(app: &mut App, endpoint_entity: Entity) -> Vec<Entity> {
+    app.world_mut()
+        .query_filtered::<(Entity, &Parent), With<BevyConnection>>()
+        .iter(app.world())
+        .filter(|(_, parent)| parent.get() == endpoint_entity)
+        .map(|(connection_entity, _)| connection_entity)
+        .collect()
+}
+
+/// processes every input a server has received from the client,
+/// asserting that they arrived in order without gaps or duplicates
+///
+/// returns the tick of the last input
+fn process_inputs(app: &mut App, server_entity: Entity) -> u32 {
+    let connection_entity = endpoint_connections(app, server_entity)[0];
+
+    let mut inputs = app
+        .world_mut()
+        .get_mut::<ConnectionInputs<u32>>(connection_entity)
+        .unwrap();
+
+    let ticks: Vec<u32> = std::iter::from_fn(|| inputs.pop())
+        .map(|(tick, input)| {
+            assert_eq!(input, tick * 10);
+            tick
+        })
+        .collect();
+
+    assert!(!ticks.is_empty());
+    assert!(
+        ticks.windows(2).all(|ticks| ticks[1] == ticks[0] + 1),
+        "{:?}",
+        ticks
+    );
+
+    *ticks.last().unwrap()
+}
+
+#[test]
+fn inputs_are_received_in_order_and_acknowledged() {
+    let (mut app, _, server_entities) = input_app(DEFAULT_MAX_QUEUED_MESSAGES, 1);
+
+    update(&mut app);
+
+    let processed_tick = process_inputs(&mut app, server_entities[0]);
+
+    update(&mut app);
+
+    let history = app.world().resource::<InputHistory<u32>>();
+    assert_eq!(history.acked_tick(), Some(processed_tick));
+    assert!(history.unacked().all(|(tick, _)| tick > processed_tick));
+
+    // inputs keep arriving after the ones that were processed
+    update(&mut app);
+
+    let next_tick = process_inputs(&mut app, server_entities[0]);
+    assert!(next_tick > processed_tick);
+}
+
+#[test]
+fn inputs_that_fail_to_send_are_received_once() {
+    let (mut app, client_entity, server_entities) = input_app(1, 2);
+
+    update(&mut app);
+
+    // fill the queue of one connection so that the next input fails to send to it,
+    // it's sent again to every connection on the next update
+    let full_connection = endpoint_connections(&mut app, client_entity)[0];
+    app.world_mut()
+        .run_system_once(move |mut messages: SendMessages<ClientProtocol>| {
+            messages.send(full_connection, &Filler).unwrap();
+        })
+        .unwrap();
+
+    update(&mut app);
+
+    let ticks: Vec<u32> = server_entities
+        .iter()
+        .map(|&server_entity| process_inputs(&mut app, server_entity))
+        .collect();
+
+    assert_eq!(ticks[0], ticks[1]);
+}
@@ -48,8 +48,12 @@ fn main() {
                 self.connections.push(connection_id);
             }
 
-            fn disconnected(&mut self, _connection_id: <QuinnEndpoint as Endpoint>::ConnectionId) {
-                println!("disconnection");
+            fn disconnected(
+                &mut self,
+                _connection_id: <QuinnEndpoint as Endpoint>::ConnectionId,
+                reason: DisconnectReason,
+            ) {
+                println!("disconnection: {:?}", reason);
             }
         }
 
@@ -74,8 +74,8 @@ fn main() {
                 self.connections.insert(connection_id, HashMap::new());
             }
 
-            fn disconnected(&mut self, connection_id: QuinnConnectionId) {
-                println!("disconnection");
+            fn disconnected(&mut self, connection_id: QuinnConnectionId, reason: DisconnectReason) {
+                println!("disconnection: {:?}", reason);
                 self.connections.remove(&connection_id);
             }
         }
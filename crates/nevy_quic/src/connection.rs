@@ -1,4 +1,8 @@
-use std::collections::{HashSet, VecDeque};
+use std::{
+    collections::{HashSet, VecDeque},
+    net::SocketAddr,
+    time::Duration,
+};
 
 use transport_interface::*;
 
@@ -13,6 +17,34 @@ pub struct QuinnConnection {
     pub(crate) stream_events: VecDeque<StreamEvent<QuinnStreamId>>,
     pub(crate) open_send_streams: HashSet<QuinnStreamId>,
     pub(crate) open_recv_streams: HashSet<QuinnStreamId>,
+    /// the handler has been told that the connection was lost
+    pub(crate) disconnected: bool,
+    /// a datagram taken from quinn by `peek_datagram` that hasn't been received yet
+    pub(crate) peeked_datagram: Option<bytes::Bytes>,
+    /// the application close code the connection was closed with locally
+    pub(crate) local_close_code: Option<u64>,
+}
+
+/// statistics for a [QuinnConnection]
+#[derive(Debug, Clone)]
+pub struct QuinnConnectionStats {
+    pub remote_address: SocketAddr,
+    /// the current best estimate of the round trip time
+    pub rtt: Duration,
+    /// the current congestion window in bytes
+    pub congestion_window: u64,
+    /// the number of udp bytes sent, including packet overhead
+    pub bytes_sent: u64,
+    /// the number of udp bytes received, including packet overhead
+    pub bytes_received: u64,
+    /// the number of udp datagrams sent
+    pub packets_sent: u64,
+    /// the number of udp datagrams received
+    pub packets_received: u64,
+    pub lost_packets: u64,
+    pub lost_bytes: u64,
+    /// the largest packet size known to be supported by the current path
+    pub path_mtu: u16,
 }
 
 impl QuinnConnection {
@@ -26,6 +58,9 @@ impl QuinnConnection {
             stream_events: VecDeque::new(),
             open_send_streams: HashSet::new(),
             open_recv_streams: HashSet::new(),
+            disconnected: false,
+            peeked_datagram: None,
+            local_close_code: None,
         }
     }
 
@@ -49,11 +84,17 @@ impl QuinnConnection {
             match app_event {
                 quinn_proto::Event::HandshakeDataReady => (),
                 quinn_proto::Event::Connected => handler.connected(self.connection_id),
-                quinn_proto::Event::ConnectionLost { reason: _ } => {
-                    handler.disconnected(self.connection_id);
+                quinn_proto::Event::ConnectionLost { reason } => {
+                    self.disconnected = true;
+                    handler.disconnected(
+                        self.connection_id,
+                        disconnect_reason(reason, self.local_close_code),
+                    );
                 }
                 quinn_proto::Event::Stream(_s) => {}
+                // datagrams are polled with `recv_datagram`
                 quinn_proto::Event::DatagramReceived => {}
+                // datagrams are sent with `drop` enabled so sending never blocks
                 quinn_proto::Event::DatagramsUnblocked => {}
             }
         }
@@ -94,10 +135,64 @@ impl QuinnConnection {
     pub fn side(&self) -> quinn_proto::Side {
         self.connection.side()
     }
+
+    pub fn remote_address(&self) -> SocketAddr {
+        self.connection.remote_address()
+    }
+}
+
+/// converts a quinn connection error to a transport agnostic [DisconnectReason]
+fn disconnect_reason(
+    error: quinn_proto::ConnectionError,
+    local_close_code: Option<u64>,
+) -> DisconnectReason {
+    use quinn_proto::{ConnectionError, TransportErrorCode};
+
+    // tls alerts are sent as crypto error codes in the range 0x100 to 0x1ff
+    let is_crypto = |code: TransportErrorCode| (0x100..0x200).contains(&u64::from(code));
+
+    match error {
+        ConnectionError::LocallyClosed => DisconnectReason::locally_closed(local_close_code),
+        ConnectionError::ApplicationClosed(close) => DisconnectReason::application_closed(
+            close.error_code.into_inner(),
+            close.reason.to_vec().into(),
+        ),
+        ConnectionError::ConnectionClosed(close)
+            if close.error_code == TransportErrorCode::CONNECTION_REFUSED =>
+        {
+            DisconnectReason::Refused
+        }
+        ConnectionError::ConnectionClosed(close) if is_crypto(close.error_code) => {
+            DisconnectReason::TlsFailure {
+                reason: String::from_utf8_lossy(&close.reason).into(),
+            }
+        }
+        ConnectionError::ConnectionClosed(close) => DisconnectReason::TransportError {
+            code: close.error_code.into(),
+            reason: String::from_utf8_lossy(&close.reason).into(),
+        },
+        ConnectionError::TransportError(error) if is_crypto(error.code) => {
+            DisconnectReason::TlsFailure {
+                reason: error.reason,
+            }
+        }
+        ConnectionError::TransportError(error) => DisconnectReason::TransportError {
+            code: error.code.into(),
+            reason: error.reason,
+        },
+        ConnectionError::Reset => DisconnectReason::Reset,
+        ConnectionError::TimedOut => DisconnectReason::TimedOut,
+        error @ (ConnectionError::VersionMismatch | ConnectionError::CidsExhausted) => {
+            DisconnectReason::Other(error.to_string())
+        }
+    }
 }
 
 impl<'c> ConnectionMut<'c> for &'c mut QuinnConnection {
-    type NonMut<'b> = &'b QuinnConnection where Self: 'b;
+    type NonMut<'b>
+        = &'b QuinnConnection
+    where
+        Self: 'b;
 
     type StreamType = QuinnStreamId;
 
@@ -105,19 +200,72 @@ impl<'c> ConnectionMut<'c> for &'c mut QuinnConnection {
         self
     }
 
-    fn disconnect(&mut self) {
+    /// quic error codes are limited to 62 bits, larger codes are saturated
+    fn disconnect_with_reason(&mut self, code: u64, reason: &[u8]) {
+        self.local_close_code.get_or_insert(code);
         self.connection.close(
             std::time::Instant::now(),
-            Default::default(),
-            Default::default(),
+            quinn_proto::VarInt::from_u64(code).unwrap_or(quinn_proto::VarInt::MAX),
+            bytes::Bytes::copy_from_slice(reason),
         );
     }
+
+    /// queues a datagram to be sent
+    ///
+    /// if the outgoing datagram buffer is full the oldest unsent datagrams will be dropped
+    fn send_datagram(&mut self, data: &[u8]) -> Result<(), SendDatagramError> {
+        match self
+            .connection
+            .datagrams()
+            .send(bytes::Bytes::copy_from_slice(data), true)
+        {
+            Ok(()) => Ok(()),
+            Err(quinn_proto::SendDatagramError::UnsupportedByPeer)
+            | Err(quinn_proto::SendDatagramError::Disabled) => Err(SendDatagramError::Unsupported),
+            Err(quinn_proto::SendDatagramError::TooLarge) => Err(SendDatagramError::TooLarge),
+            Err(quinn_proto::SendDatagramError::Blocked(_)) => {
+                unreachable!("will never block when dropping old datagrams")
+            }
+        }
+    }
+
+    fn recv_datagram(&mut self) -> Option<Box<[u8]>> {
+        self.peeked_datagram
+            .take()
+            .or_else(|| self.connection.datagrams().recv())
+            .map(|bytes| bytes.as_ref().into())
+    }
+
+    fn peek_datagram(&mut self) -> Option<&[u8]> {
+        if self.peeked_datagram.is_none() {
+            self.peeked_datagram = self.connection.datagrams().recv();
+        }
+
+        self.peeked_datagram.as_deref()
+    }
+
+    fn max_datagram_size(&mut self) -> Option<usize> {
+        self.connection.datagrams().max_size()
+    }
 }
 
 impl<'c> ConnectionRef<'c> for &'c QuinnConnection {
-    type ConnectionStats = std::net::SocketAddr;
+    type ConnectionStats = QuinnConnectionStats;
 
-    fn get_stats(&self) -> std::net::SocketAddr {
-        self.connection.remote_address()
+    fn get_stats(&self) -> QuinnConnectionStats {
+        let stats = self.connection.stats();
+
+        QuinnConnectionStats {
+            remote_address: self.connection.remote_address(),
+            rtt: self.connection.rtt(),
+            congestion_window: stats.path.cwnd,
+            bytes_sent: stats.udp_tx.bytes,
+            bytes_received: stats.udp_rx.bytes,
+            packets_sent: stats.udp_tx.datagrams,
+            packets_received: stats.udp_rx.datagrams,
+            lost_packets: stats.path.lost_packets,
+            lost_bytes: stats.path.lost_bytes,
+            path_mtu: self.connection.current_mtu(),
+        }
     }
 }
@@ -262,6 +262,14 @@ impl QuinnEndpoint {
 
             connection.accept_streams();
 
+            // quinn doesn't report connections that were closed locally as lost
+            if drained && !connection.disconnected {
+                handler.disconnected(
+                    connection_id,
+                    DisconnectReason::locally_closed(connection.local_close_code),
+                );
+            }
+
             !drained
         });
     }
@@ -280,12 +288,18 @@ impl Endpoint for QuinnEndpoint {
 
     type IncomingConnectionInfo<'a> = &'a quinn_proto::Incoming;
 
+    type RemoteAddress = SocketAddr;
+
     // Processes timeouts, received datagrams and other events.
     fn update(&mut self, handler: &mut impl EndpointEventHandler<Self>) {
         self.receive_datagrams(handler);
         self.update_connections(handler);
     }
 
+    fn remote_address(info: &&quinn_proto::Incoming) -> SocketAddr {
+        info.remote_address()
+    }
+
     // Retrieve a reference to a particular [QuinnConnection].
     fn connection<'c>(
         &'c self,
@@ -0,0 +1,162 @@
+//! applies received replication messages to local entities
+//!
+//! server entities are mapped to their local entities in the connection's [ConnectionEntityMap],
+//! so messages that reference them can be mapped with [ProtocolBuilder::map_message_entities](nevy_messaging::ProtocolBuilder::map_message_entities)
+
+use bevy::prelude::*;
+use nevy_messaging::{deserialize::ReceivedMessages, entity_map::ConnectionEntityMap};
+
+use crate::{server::ReplicationServer, EntityAction, ReplicationMessage, ReplicationRegistry};
+
+/// inserted onto entities that were replicated from a server
+#[derive(Component, Debug, Clone, Copy)]
+pub struct RemoteEntity {
+    pub connection_entity: Entity,
+    pub server_entity: Entity,
+}
+
+pub(crate) fn apply_replication<C: Component>(
+    mut commands: Commands,
+    registry: Res<ReplicationRegistry<C>>,
+    mut connection_q: Query<(
+        Entity,
+        &mut ConnectionEntityMap,
+        &mut ReceivedMessages<ReplicationMessage>,
+        &Parent,
+    )>,
+    endpoint_q: Query<(), (With<C>, Without<ReplicationServer>)>,
+) {
+    for (connection_entity, mut map, mut messages, connection_parent) in connection_q.iter_mut() {
+        if !endpoint_q.contains(connection_parent.get()) {
+            continue;
+        }
+
+        for ReplicationMessage { actions } in messages.drain() {
+            for action in actions {
+                apply_action(
+                    &mut commands,
+                    &registry,
+                    connection_entity,
+                    &mut map,
+                    action,
+                );
+            }
+        }
+    }
+}
+
+fn apply_action<C>(
+    commands: &mut Commands,
+    registry: &ReplicationRegistry<C>,
+    connection_entity: Entity,
+    map: &mut ConnectionEntityMap,
+    action: EntityAction,
+) {
+    let entity = match action {
+        EntityAction::Spawn(entity)
+        | EntityAction::Despawn(entity)
+        | EntityAction::Insert { entity, .. }
+        | EntityAction::Remove { entity, .. } => entity,
+    };
+
+    let Ok(server_entity) = Entity::try_from_bits(entity) else {
+        warn!(
+            "connection {:?} sent an invalid entity {}",
+            connection_entity, entity
+        );
+        return;
+    };
+
+    if let EntityAction::Spawn(_) = action {
+        if map.contains(server_entity) {
+            return;
+        }
+
+        let local_entity = commands
+            .spawn(RemoteEntity {
+                connection_entity,
+                server_entity,
+            })
+            .id();
+
+        map.insert(server_entity, local_entity);
+        return;
+    }
+
+    if let EntityAction::Despawn(_) = action {
+        if let Some(local_entity) = map.remove(server_entity) {
+            // the entity may have been despawned locally
+            if let Some(mut local_commands) = commands.get_entity(local_entity) {
+                local_commands.despawn();
+            }
+        }
+        return;
+    }
+
+    let Some(local_entity) = map.get(server_entity) else {
+        return;
+    };
+
+    // the entity may have been despawned locally
+    let Some(mut local_commands) = commands.get_entity(local_entity) else {
+        return;
+    };
+
+    match action {
+        EntityAction::Insert {
+            component_id,
+            component,
+            ..
+        } => {
+            let Some(fns) = registry.components.get(component_id as usize) else {
+                warn!(
+                    "connection {:?} sent an unknown replicated component id {}",
+                    connection_entity, component_id
+                );
+                return;
+            };
+
+            if let Err(err) = (fns.insert)(&mut local_commands, &component) {
+                warn!(
+                    "failed to deserialize replicated component {} from connection {:?}: {:?}",
+                    component_id, connection_entity, err
+                );
+            }
+        }
+        EntityAction::Remove { component_id, .. } => {
+            if let Some(fns) = registry.components.get(component_id as usize) {
+                (fns.remove)(&mut local_commands);
+            }
+        }
+        EntityAction::Spawn(_) | EntityAction::Despawn(_) => (),
+    }
+}
+
+/// despawns the local copies of replicated entities when a connection's [ConnectionEntityMap] is removed
+pub(crate) fn despawn_remote_entities<C: Component>(
+    trigger: Trigger<OnRemove, ConnectionEntityMap>,
+    mut commands: Commands,
+    connection_q: Query<(&ConnectionEntityMap, &Parent)>,
+    endpoint_q: Query<(), (With<C>, Without<ReplicationServer>)>,
+    remote_q: Query<&RemoteEntity>,
+) {
+    let Ok((map, connection_parent)) = connection_q.get(trigger.entity()) else {
+        return;
+    };
+
+    if !endpoint_q.contains(connection_parent.get()) {
+        return;
+    }
+
+    for (_, local_entity) in map.iter() {
+        // entities mapped by the app are left alone
+        if !remote_q
+            .get(local_entity)
+            .is_ok_and(|remote| remote.connection_entity == trigger.entity())
+        {
+            continue;
+        }
+
+        commands.entity(local_entity).despawn();
+    }
+}
@@ -0,0 +1,235 @@
+//! replicates entities and their components from a server to it's clients
+//!
+//! entities marked with [Replicated](server::Replicated) on the server are spawned on every client
+//! they are relevant to, along with the components added with [ReplicationPlugin::replicate].
+//! inserts, changes and removals of those components are sent as they happen,
+//! and the client's copy is despawned when the server entity is despawned or stops being relevant.
+//!
+//! relevance is decided by the [ConnectionInterest](nevy_interest::ConnectionInterest) of each connection.
+//! connections without one, such as when no [InterestPlugin](nevy_interest::InterestPlugin) is added,
+//! are sent every replicated entity except those with a [VisibleTo](server::VisibleTo) that doesn't contain them.
+//!
+//! once a connection has a [ConnectionInterest](nevy_interest::ConnectionInterest) it only sees the entities a strategy marks as relevant,
+//! so entities that were previously replicated to everyone by default are hidden.
+//! to migrate, insert [AlwaysRelevant](nevy_interest::AlwaysRelevant) onto entities that every connection should see
+//! and add the [ExplicitInterest](nevy_interest::explicit::ExplicitInterest) strategy to keep honoring [VisibleTo](server::VisibleTo)
+
+use std::marker::PhantomData;
+
+use bevy::{
+    ecs::{intern::Interned, schedule::ScheduleLabel},
+    prelude::*,
+};
+use nevy_interest::InterestSystems;
+use nevy_messaging::{
+    codec::{BincodeCodec, CodecError, MessageCodec},
+    deserialize::MessageDelivery,
+    ProtocolBuilder,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+pub mod client;
+pub mod server;
+
+pub mod prelude {
+    pub use crate::server::{Replicated, ReplicationServer, VisibleTo};
+
+    pub use crate::client::RemoteEntity;
+
+    pub use crate::{add_replication_messages, ReplicationPlugin, ReplicationSystems};
+}
+
+/// replicates entities from endpoints with a [ReplicationServer](server::ReplicationServer)
+/// to the connections of other endpoints with the marker `C`
+///
+/// both ends of a connection need to replicate the same components in the same order,
+/// and the replication messages need to be added to the protocol with [add_replication_messages].
+/// components are serialized with the codec `M`
+pub struct ReplicationPlugin<C, M = BincodeCodec> {
+    _p: PhantomData<(C, M)>,
+    schedule: Interned<dyn ScheduleLabel>,
+    components: Vec<Box<dyn ComponentBuilder<C>>>,
+}
+
+trait ComponentBuilder<C>: Send + Sync + 'static {
+    fn build(&self, schedule: Interned<dyn ScheduleLabel>, component_id: u16, app: &mut App);
+}
+
+struct ComponentBuilderType<T, M> {
+    _p: PhantomData<(T, M)>,
+}
+
+impl<C, M: MessageCodec> ReplicationPlugin<C, M> {
+    pub fn new(schedule: impl ScheduleLabel) -> Self {
+        ReplicationPlugin {
+            _p: PhantomData,
+            schedule: schedule.intern(),
+            components: Vec::new(),
+        }
+    }
+}
+
+impl<C: Component, M: MessageCodec> ReplicationPlugin<C, M> {
+    /// adds a component type to be replicated, assigning it the next component id
+    ///
+    /// panics if more than [u16::MAX] components are added
+    pub fn replicate<T: Component + Serialize + DeserializeOwned>(&mut self) -> &mut Self {
+        if self.components.len() >= u16::MAX as usize {
+            panic!(
+                "can't replicate more than {} component types",
+                u16::MAX as usize
+            );
+        }
+
+        self.components
+            .push(Box::new(ComponentBuilderType::<T, M> { _p: PhantomData }));
+        self
+    }
+}
+
+impl<C: Component, M: MessageCodec> Plugin for ReplicationPlugin<C, M> {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ReplicationRegistry::<C> {
+            _p: PhantomData,
+            components: Vec::new(),
+        });
+
+        app.configure_sets(
+            self.schedule,
+            (
+                ReplicationSystems::Visibility,
+                ReplicationSystems::Collect,
+                ReplicationSystems::Send,
+            )
+                .chain(),
+        );
+
+        app.configure_sets(
+            self.schedule,
+            InterestSystems::Compute.before(ReplicationSystems::Visibility),
+        );
+
+        app.add_systems(
+            self.schedule,
+            (
+                server::insert_client_states::<C>,
+                server::update_visibility::<C>.in_set(ReplicationSystems::Visibility),
+                server::send_replication::<C>.in_set(ReplicationSystems::Send),
+            ),
+        );
+
+        app.add_systems(
+            self.schedule,
+            client::apply_replication::<C>.in_set(ReplicationSystems::Receive),
+        );
+
+        app.add_observer(client::despawn_remote_entities::<C>);
+
+        for (component_id, builder) in self.components.iter().enumerate() {
+            builder.build(self.schedule, component_id as u16, app);
+        }
+    }
+}
+
+impl<C: Component, T: Component + Serialize + DeserializeOwned, M: MessageCodec> ComponentBuilder<C>
+    for ComponentBuilderType<T, M>
+{
+    fn build(&self, schedule: Interned<dyn ScheduleLabel>, component_id: u16, app: &mut App) {
+        app.insert_resource(ReplicatedComponent::<C, T> {
+            _p: PhantomData,
+            component_id,
+            serialize: M::serialize::<T>,
+        });
+
+        app.world_mut()
+            .resource_mut::<ReplicationRegistry<C>>()
+            .components
+            .push(ComponentFns {
+                insert: insert_component::<T, M>,
+                remove: remove_component::<T>,
+            });
+
+        app.add_systems(
+            schedule,
+            server::collect_changes::<C, T>.in_set(ReplicationSystems::Collect),
+        );
+    }
+}
+
+/// system sets where entities are replicated
+#[derive(Clone, PartialEq, Eq, Debug, std::hash::Hash, SystemSet)]
+pub enum ReplicationSystems {
+    /// received replication messages are applied on clients
+    Receive,
+    /// entities are spawned and despawned on clients as their visibility changes
+    Visibility,
+    /// component changes are collected for each client
+    Collect,
+    /// collected changes are queued to be sent to each client
+    Send,
+}
+
+/// adds the messages used by [ReplicationPlugin] to a protocol
+///
+/// they are always received as [ReceivedMessages](nevy_messaging::deserialize::ReceivedMessages)
+/// components, regardless of the protocol's [MessageDelivery]
+pub fn add_replication_messages<C: Component, M: MessageCodec>(
+    protocol: &mut ProtocolBuilder<C, M>,
+) {
+    protocol.add_message::<ReplicationMessage>();
+    protocol.set_message_delivery::<ReplicationMessage>(MessageDelivery::Component);
+}
+
+/// the changes to a client's replicated entities since the last message
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ReplicationMessage {
+    pub(crate) actions: Vec<EntityAction>,
+}
+
+/// entities are identified by the bits of the server's [Entity]
+#[derive(Serialize, Deserialize)]
+pub(crate) enum EntityAction {
+    Spawn(u64),
+    Despawn(u64),
+    Insert {
+        entity: u64,
+        component_id: u16,
+        component: Vec<u8>,
+    },
+    Remove {
+        entity: u64,
+        component_id: u16,
+    },
+}
+
+/// Contains the component id and serialization function of a replicated component
+#[derive(Resource)]
+pub(crate) struct ReplicatedComponent<C, T> {
+    _p: PhantomData<(C, T)>,
+    pub(crate) component_id: u16,
+    pub(crate) serialize: fn(&T) -> Result<Vec<u8>, CodecError>,
+}
+
+/// Contains the functions that apply each replicated component indexed by component id
+#[derive(Resource)]
+pub(crate) struct ReplicationRegistry<C> {
+    _p: PhantomData<C>,
+    pub(crate) components: Vec<ComponentFns>,
+}
+
+pub(crate) struct ComponentFns {
+    pub(crate) insert: fn(&mut EntityCommands, &[u8]) -> Result<(), CodecError>,
+    pub(crate) remove: fn(&mut EntityCommands),
+}
+
+fn insert_component<T: Component + DeserializeOwned, M: MessageCodec>(
+    entity: &mut EntityCommands,
+    bytes: &[u8],
+) -> Result<(), CodecError> {
+    entity.try_insert(M::deserialize::<T>(bytes)?);
+    Ok(())
+}
+
+fn remove_component<T: Component>(entity: &mut EntityCommands) {
+    entity.remove::<T>();
+}
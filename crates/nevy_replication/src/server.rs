@@ -0,0 +1,235 @@
+//! collects and sends the changes to replicated entities for each client
+
+use std::marker::PhantomData;
+
+use bevy::{
+    ecs::entity::{EntityHashMap, EntityHashSet},
+    prelude::*,
+};
+use bevy_interface::prelude::*;
+use nevy_interest::ConnectionInterest;
+use nevy_messaging::serialize::{QueueMessageError, SendMessages};
+
+use crate::{EntityAction, ReplicatedComponent, ReplicationMessage};
+
+pub use nevy_interest::explicit::VisibleTo;
+
+/// Insert onto an endpoint to replicate entities to it's connections
+#[derive(Component)]
+pub struct ReplicationServer;
+
+/// Insert onto an entity to replicate it to clients
+///
+/// removing this component despawns the entity on clients
+#[derive(Component)]
+pub struct Replicated;
+
+/// the replicated entities of a connection on a [ReplicationServer]
+#[derive(Component)]
+pub(crate) struct ReplicationClientState<C> {
+    _p: PhantomData<C>,
+    /// the entities that are spawned on the client
+    spawned: EntityHashSet,
+    /// entities that were spawned this update and need all their components sent
+    new_entities: EntityHashSet,
+    /// actions waiting to be sent, in the order they happened
+    ///
+    /// actions that couldn't be queued are kept here and sent on a later update
+    actions: Vec<EntityAction>,
+}
+
+pub(crate) fn insert_client_states<C: Component>(
+    mut commands: Commands,
+    mut connected_r: EventReader<Connected>,
+    endpoint_q: Query<(), (With<C>, With<ReplicationServer>)>,
+) {
+    for &Connected {
+        endpoint_entity,
+        connection_entity,
+    } in connected_r.read()
+    {
+        if !endpoint_q.contains(endpoint_entity) {
+            continue;
+        }
+
+        // the connection may have been closed before this system ran
+        let Some(mut connection_commands) = commands.get_entity(connection_entity) else {
+            continue;
+        };
+
+        connection_commands.try_insert(ReplicationClientState::<C> {
+            _p: PhantomData,
+            spawned: EntityHashSet::default(),
+            new_entities: EntityHashSet::default(),
+            actions: Vec::new(),
+        });
+    }
+}
+
+pub(crate) fn update_visibility<C: Component>(
+    replicated_q: Query<(Entity, Option<&VisibleTo>), With<Replicated>>,
+    mut client_q: Query<(
+        Entity,
+        &mut ReplicationClientState<C>,
+        Option<&ConnectionInterest>,
+    )>,
+) {
+    for (connection_entity, mut client, interest) in client_q.iter_mut() {
+        let ReplicationClientState {
+            spawned,
+            new_entities,
+            actions,
+            ..
+        } = &mut *client;
+
+        for (entity, visible_to) in replicated_q.iter() {
+            // connections without interest management fall back to `VisibleTo`
+            let visible = match interest {
+                Some(interest) => interest.contains(entity),
+                None => visible_to.is_none_or(|visible_to| visible_to.contains(connection_entity)),
+            };
+
+            if visible && spawned.insert(entity) {
+                actions.push(EntityAction::Spawn(entity.to_bits()));
+                new_entities.insert(entity);
+            } else if !visible && spawned.remove(&entity) {
+                actions.push(EntityAction::Despawn(entity.to_bits()));
+            }
+        }
+
+        // entities that were despawned or are no longer replicated
+        spawned.retain(|&entity| {
+            if replicated_q.contains(entity) {
+                return true;
+            }
+
+            actions.push(EntityAction::Despawn(entity.to_bits()));
+            false
+        });
+    }
+}
+
+pub(crate) fn collect_changes<C: Component, T: Component>(
+    component: Res<ReplicatedComponent<C, T>>,
+    mut removed_r: RemovedComponents<T>,
+    component_q: Query<(Entity, Ref<T>), With<Replicated>>,
+    mut client_q: Query<&mut ReplicationClientState<C>>,
+) {
+    let component_id = component.component_id;
+
+    // serialized lazily so that each component is serialized at most once
+    let mut serialized = EntityHashMap::<Option<Vec<u8>>>::default();
+    let mut serialize = |entity: Entity, value: &T| {
+        serialized
+            .entry(entity)
+            .or_insert_with(|| match (component.serialize)(value) {
+                Ok(bytes) => Some(bytes),
+                Err(err) => {
+                    error!(
+                        "failed to serialize replicated component \"{}\" on {:?}: {:?}",
+                        std::any::type_name::<T>(),
+                        entity,
+                        err
+                    );
+                    None
+                }
+            })
+            .clone()
+    };
+
+    let removed: Vec<Entity> = removed_r.read().collect();
+
+    for mut client in client_q.iter_mut() {
+        let ReplicationClientState {
+            spawned,
+            new_entities,
+            actions,
+            ..
+        } = &mut *client;
+
+        for (entity, value) in component_q.iter() {
+            if !spawned.contains(&entity) {
+                continue;
+            }
+
+            if !value.is_changed() && !new_entities.contains(&entity) {
+                continue;
+            }
+
+            let Some(bytes) = serialize(entity, &value) else {
+                continue;
+            };
+
+            actions.push(EntityAction::Insert {
+                entity: entity.to_bits(),
+                component_id,
+                component: bytes,
+            });
+        }
+
+        for &entity in removed.iter() {
+            // new entities are sent with only the components they have
+            if !spawned.contains(&entity) || new_entities.contains(&entity) {
+                continue;
+            }
+
+            // the component was inserted again
+            if component_q.contains(entity) {
+                continue;
+            }
+
+            actions.push(EntityAction::Remove {
+                entity: entity.to_bits(),
+                component_id,
+            });
+        }
+    }
+}
+
+pub(crate) fn send_replication<C: Component>(
+    mut messages: SendMessages<C>,
+    mut client_q: Query<(Entity, &mut ReplicationClientState<C>)>,
+) {
+    for (connection_entity, mut client) in client_q.iter_mut() {
+        client.new_entities.clear();
+
+        // keep collecting actions until the connection's message queue is inserted
+        if messages.queued_messages(connection_entity).is_none() {
+            continue;
+        }
+
+        // the number of actions sent in the next message,
+        // halved whenever a message is too large
+        let mut batch_size = client.actions.len();
+
+        while !client.actions.is_empty() {
+            batch_size = batch_size.min(client.actions.len());
+
+            let message = ReplicationMessage {
+                actions: client.actions.drain(..batch_size).collect(),
+            };
+
+            let Err(err) = messages.send(connection_entity, &message) else {
+                continue;
+            };
+
+            match err {
+                QueueMessageError::MessageTooLarge { .. } if batch_size > 1 => {
+                    client.actions.splice(0..0, message.actions);
+                    batch_size /= 2;
+                }
+                QueueMessageError::QueueFull => {
+                    // unsent actions are kept and sent on a later update
+                    client.actions.splice(0..0, message.actions);
+                    break;
+                }
+                err => {
+                    error!(
+                        "failed to send replication to connection {:?}, it's entities will be out of sync: {:?}",
+                        connection_entity, err
+                    );
+                }
+            }
+        }
+    }
+}
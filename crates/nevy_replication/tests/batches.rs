@@ -0,0 +1,62 @@
+use bevy::prelude::*;
+use nevy_replication::prelude::*;
+
+mod common;
+
+use common::*;
+
+/// spawns replicated entities with a label of `label_len` bytes
+fn spawn_labelled(app: &mut App, count: usize, label_len: usize) {
+    for _ in 0..count {
+        app.world_mut()
+            .spawn((Replicated, Label("a".repeat(label_len))));
+    }
+}
+
+/// the number of client copies that have received their label
+fn labelled_copies(app: &mut App) -> usize {
+    app.world_mut()
+        .query_filtered::<(), (With<RemoteEntity>, With<Label>)>()
+        .iter(app.world())
+        .count()
+}
+
+#[test]
+fn messages_that_are_too_large_are_split() {
+    let mut protocol = test_protocol();
+    protocol.set_max_message_size(256);
+
+    let mut app = replication_app(&protocol);
+    connect(&mut app);
+
+    // every spawn and insert fits in a message, but all of them together don't
+    spawn_labelled(&mut app, 40, 64);
+
+    update(&mut app);
+
+    assert_eq!(labelled_copies(&mut app), 40);
+}
+
+#[test]
+fn actions_that_dont_fit_the_queue_are_sent_later() {
+    let mut protocol = test_protocol();
+    protocol.set_max_message_size(256);
+    protocol.set_max_queued_messages(1);
+
+    let mut app = replication_app(&protocol);
+    connect(&mut app);
+
+    spawn_labelled(&mut app, 40, 64);
+
+    // a single message is sent each update
+    update(&mut app);
+
+    let partial = labelled_copies(&mut app);
+    assert!(0 < partial && partial < 40, "{} copies", partial);
+
+    for _ in 0..10 {
+        update(&mut app);
+    }
+
+    assert_eq!(labelled_copies(&mut app), 40);
+}
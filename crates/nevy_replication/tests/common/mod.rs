@@ -0,0 +1,143 @@
+//! helpers shared by the replication integration tests,
+//! which run a client and a server endpoint in the same app over a loopback network
+
+#![allow(dead_code)]
+
+use bevy::{ecs::system::RunSystemOnce, prelude::*, time::TimePlugin};
+use bevy_interface::prelude::*;
+use nevy_loopback::prelude::*;
+use nevy_messaging::prelude::*;
+use nevy_replication::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// marker for the endpoints of the test protocol
+#[derive(Component)]
+pub struct TestProtocol;
+
+/// the address of a test endpoint
+#[derive(Component)]
+pub struct TestAddress(pub LoopbackAddress);
+
+#[derive(Component, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Health(pub u32);
+
+#[derive(Component, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Label(pub String);
+
+/// the protocol used by most tests
+pub fn test_protocol() -> ProtocolBuilder<TestProtocol> {
+    let mut protocol = ProtocolBuilder::new();
+    add_replication_messages(&mut protocol);
+    protocol
+}
+
+/// an app that replicates [Health] and [Label] with the plugins built from `protocol`
+///
+/// endpoints are spawned with [connect]
+pub fn replication_app(protocol: &ProtocolBuilder<TestProtocol>) -> App {
+    let mut app = App::new();
+
+    app.add_plugins(TimePlugin);
+    app.add_plugins(EndpointPlugin::default());
+    app.add_plugins(StreamHeaderPlugin::default());
+    app.add_plugins(protocol.build_symmetric(Update));
+
+    let mut replication = ReplicationPlugin::<TestProtocol>::new(Update);
+    replication.replicate::<Health>();
+    replication.replicate::<Label>();
+    app.add_plugins(replication);
+
+    app
+}
+
+/// spawns a server and a client endpoint, connects them and updates the app
+///
+/// returns the server's connection to the client
+pub fn connect(app: &mut App) -> Entity {
+    let server_entity = spawn_endpoints(app);
+
+    update(app);
+
+    app.world_mut()
+        .query_filtered::<(Entity, &Parent), With<BevyConnection>>()
+        .iter(app.world())
+        .find(|(_, parent)| parent.get() == server_entity)
+        .map(|(connection_entity, _)| connection_entity)
+        .unwrap()
+}
+
+/// spawns a server and a client endpoint and starts connecting them
+///
+/// returns the server endpoint
+pub fn spawn_endpoints(app: &mut App) -> Entity {
+    let network = LoopbackNetwork::new();
+    let server_entity = spawn_endpoint(app, &network);
+    let client_entity = spawn_endpoint(app, &network);
+
+    app.world_mut()
+        .entity_mut(server_entity)
+        .insert(ReplicationServer);
+
+    let server_address = app.world().get::<TestAddress>(server_entity).unwrap().0;
+
+    app.world_mut()
+        .run_system_once(move |mut connections: Connections| {
+            connections
+                .connect(
+                    client_entity,
+                    Description::new_connect_description::<LoopbackEndpoint>(server_address),
+                )
+                .unwrap()
+                .unwrap();
+        })
+        .unwrap();
+
+    server_entity
+}
+
+fn spawn_endpoint(app: &mut App, network: &LoopbackNetwork) -> Entity {
+    let endpoint = LoopbackEndpoint::new(network, None);
+
+    app.world_mut()
+        .spawn((
+            TestProtocol,
+            TestAddress(endpoint.local_addr()),
+            BevyEndpoint::new(endpoint),
+            EndpointStreamHeaders,
+            EndpointMessagingHeader { header: 1 },
+            EndpointMessageStreamDescription {
+                description: CloneableDescription::new::<LoopbackStreamId>(LoopbackDir::Uni),
+            },
+            EndpointMessageStreamCloseDescription {
+                description: CloneableDescription::new_send_close::<LoopbackStreamId>(None),
+            },
+            EndpointMessageStreamStopDescription {
+                description: CloneableDescription::new_recv_close::<LoopbackStreamId>(0),
+            },
+        ))
+        .id()
+}
+
+/// updates the app enough times for data to pass through every plugin's systems
+pub fn update(app: &mut App) {
+    for _ in 0..10 {
+        app.update();
+    }
+}
+
+/// the client's copy of a server entity
+pub fn client_copy(app: &mut App, server_entity: Entity) -> Option<Entity> {
+    app.world_mut()
+        .query::<(Entity, &RemoteEntity)>()
+        .iter(app.world())
+        .find(|(_, remote)| remote.server_entity == server_entity)
+        .map(|(entity, _)| entity)
+}
+
+/// the number of entities the client has copies of
+pub fn client_copies(app: &mut App) -> usize {
+    app.world_mut()
+        .query::<&RemoteEntity>()
+        .iter(app.world())
+        .count()
+}
@@ -0,0 +1,137 @@
+use bevy::prelude::*;
+use bevy_interface::prelude::*;
+use nevy_replication::prelude::*;
+
+mod common;
+
+use common::*;
+
+#[test]
+fn entities_are_spawned_with_their_components() {
+    let mut app = replication_app(&test_protocol());
+    connect(&mut app);
+
+    let server_entity = app
+        .world_mut()
+        .spawn((Replicated, Health(10), Label("crate".into())))
+        .id();
+
+    // not replicated
+    app.world_mut().spawn((Health(5), Label("prop".into())));
+
+    update(&mut app);
+
+    assert_eq!(client_copies(&mut app), 1);
+
+    let client_entity = client_copy(&mut app, server_entity).unwrap();
+    let client = app.world().entity(client_entity);
+
+    assert_eq!(client.get::<Health>(), Some(&Health(10)));
+    assert_eq!(client.get::<Label>(), Some(&Label("crate".into())));
+}
+
+#[test]
+fn component_changes_are_replicated() {
+    let mut app = replication_app(&test_protocol());
+    connect(&mut app);
+
+    let server_entity = app.world_mut().spawn((Replicated, Health(10))).id();
+
+    update(&mut app);
+
+    let client_entity = client_copy(&mut app, server_entity).unwrap();
+    assert_eq!(app.world().get::<Label>(client_entity), None);
+
+    app.world_mut()
+        .entity_mut(server_entity)
+        .insert(Label("crate".into()))
+        .get_mut::<Health>()
+        .unwrap()
+        .0 = 3;
+
+    update(&mut app);
+
+    let client = app.world().entity(client_entity);
+    assert_eq!(client.get::<Health>(), Some(&Health(3)));
+    assert_eq!(client.get::<Label>(), Some(&Label("crate".into())));
+
+    app.world_mut().entity_mut(server_entity).remove::<Health>();
+
+    update(&mut app);
+
+    let client = app.world().entity(client_entity);
+    assert_eq!(client.get::<Health>(), None);
+    assert_eq!(client.get::<Label>(), Some(&Label("crate".into())));
+}
+
+#[test]
+fn despawns_are_replicated() {
+    let mut app = replication_app(&test_protocol());
+    connect(&mut app);
+
+    let despawned = app.world_mut().spawn((Replicated, Health(10))).id();
+    let unreplicated = app.world_mut().spawn((Replicated, Health(10))).id();
+
+    update(&mut app);
+
+    assert_eq!(client_copies(&mut app), 2);
+
+    app.world_mut().despawn(despawned);
+    app.world_mut()
+        .entity_mut(unreplicated)
+        .remove::<Replicated>();
+
+    update(&mut app);
+
+    assert_eq!(client_copies(&mut app), 0);
+}
+
+#[test]
+fn locally_despawned_copies_are_ignored() {
+    let mut app = replication_app(&test_protocol());
+    connect(&mut app);
+
+    let changed = app.world_mut().spawn((Replicated, Health(10))).id();
+    let despawned = app.world_mut().spawn((Replicated, Health(10))).id();
+
+    update(&mut app);
+
+    for server_entity in [changed, despawned] {
+        let client_entity = client_copy(&mut app, server_entity).unwrap();
+        app.world_mut().despawn(client_entity);
+    }
+
+    app.world_mut().get_mut::<Health>(changed).unwrap().0 = 3;
+    app.world_mut().despawn(despawned);
+
+    update(&mut app);
+
+    assert_eq!(client_copies(&mut app), 0);
+}
+
+/// despawns every new connection before the replication systems see it
+fn close_new_connections(mut commands: Commands, mut connected_r: EventReader<Connected>) {
+    for event in connected_r.read() {
+        commands.entity(event.connection_entity).despawn();
+    }
+}
+
+#[test]
+fn connections_closed_on_connect_are_ignored() {
+    let mut app = replication_app(&test_protocol());
+    app.add_systems(PreUpdate, close_new_connections.after(UpdateEndpoints));
+
+    app.world_mut().spawn((Replicated, Health(10)));
+
+    spawn_endpoints(&mut app);
+    update(&mut app);
+
+    let connections = app
+        .world_mut()
+        .query_filtered::<(), With<BevyConnection>>()
+        .iter(app.world())
+        .count();
+    assert_eq!(connections, 0);
+
+    assert_eq!(client_copies(&mut app), 0);
+}
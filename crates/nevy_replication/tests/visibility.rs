@@ -0,0 +1,102 @@
+use bevy::prelude::*;
+use nevy_interest::prelude::*;
+use nevy_replication::prelude::*;
+
+mod common;
+
+use common::*;
+
+#[test]
+fn visible_to_hides_entities_from_other_connections() {
+    let mut app = replication_app(&test_protocol());
+    let connection_entity = connect(&mut app);
+
+    let hidden = app
+        .world_mut()
+        .spawn((Replicated, Health(10), VisibleTo::default()))
+        .id();
+    let visible = app.world_mut().spawn((Replicated, Health(10))).id();
+
+    update(&mut app);
+
+    assert!(client_copy(&mut app, hidden).is_none());
+    assert!(client_copy(&mut app, visible).is_some());
+
+    app.world_mut()
+        .get_mut::<VisibleTo>(hidden)
+        .unwrap()
+        .insert(connection_entity);
+
+    update(&mut app);
+
+    let client_entity = client_copy(&mut app, hidden).unwrap();
+    assert_eq!(app.world().get::<Health>(client_entity), Some(&Health(10)));
+
+    app.world_mut()
+        .get_mut::<VisibleTo>(hidden)
+        .unwrap()
+        .remove(connection_entity);
+
+    update(&mut app);
+
+    assert!(client_copy(&mut app, hidden).is_none());
+    assert!(client_copy(&mut app, visible).is_some());
+}
+
+#[test]
+fn connection_interest_decides_visibility() {
+    let mut app = replication_app(&test_protocol());
+    app.add_plugins(InterestPlugin::<TestProtocol>::new(Update));
+    connect(&mut app);
+
+    let relevant = app
+        .world_mut()
+        .spawn((Replicated, Health(10), AlwaysRelevant))
+        .id();
+    // only relevant entities are replicated once a connection has interest
+    let irrelevant = app.world_mut().spawn((Replicated, Health(10))).id();
+
+    update(&mut app);
+
+    assert!(client_copy(&mut app, relevant).is_some());
+    assert!(client_copy(&mut app, irrelevant).is_none());
+
+    app.world_mut()
+        .entity_mut(relevant)
+        .remove::<AlwaysRelevant>();
+    app.world_mut()
+        .entity_mut(irrelevant)
+        .insert(AlwaysRelevant);
+
+    update(&mut app);
+
+    assert!(client_copy(&mut app, relevant).is_none());
+
+    let client_entity = client_copy(&mut app, irrelevant).unwrap();
+    assert_eq!(app.world().get::<Health>(client_entity), Some(&Health(10)));
+}
+
+#[test]
+fn explicit_interest_honors_visible_to() {
+    let mut app = replication_app(&test_protocol());
+
+    let mut interest = InterestPlugin::<TestProtocol>::new(Update);
+    interest.add_strategy(ExplicitInterest);
+    app.add_plugins(interest);
+
+    let connection_entity = connect(&mut app);
+
+    let visible = app
+        .world_mut()
+        .spawn((Replicated, Health(10), VisibleTo::new([connection_entity])))
+        .id();
+    let hidden = app
+        .world_mut()
+        .spawn((Replicated, Health(10), VisibleTo::default()))
+        .id();
+
+    update(&mut app);
+
+    assert!(client_copy(&mut app, visible).is_some());
+    assert!(client_copy(&mut app, hidden).is_none());
+}
@@ -17,10 +17,14 @@ impl Endpoint for WasmEndpoint {
     /// Browsers cannot currently accept connections from peers.
     type IncomingConnectionInfo<'i> = ();
 
+    type RemoteAddress = ();
+
     fn update(&mut self, handler: &mut impl transport_interface::EndpointEventHandler<Self>) {
         todo!()
     }
 
+    fn remote_address(_info: &()) {}
+
     fn connection<'c>(
         &'c self,
         id: Self::ConnectionId,
@@ -55,8 +55,9 @@ fn main() {
             fn disconnected(
                 &mut self,
                 _connection_id: <WebTransportEndpoint as Endpoint>::ConnectionId,
+                reason: DisconnectReason,
             ) {
-                println!("disconnection");
+                println!("disconnection: {:?}", reason);
             }
         }
 
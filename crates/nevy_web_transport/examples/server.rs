@@ -78,8 +78,9 @@ fn main() {
             fn disconnected(
                 &mut self,
                 connection_id: <WebTransportEndpoint as Endpoint>::ConnectionId,
+                reason: DisconnectReason,
             ) {
-                println!("disconnection");
+                println!("disconnection: {:?}", reason);
                 self.connections.remove(&connection_id);
             }
         }
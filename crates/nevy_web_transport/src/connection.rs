@@ -94,14 +94,14 @@ impl<'c> WebTransportConnectionMut<'c> {
 
     /// transitions to the failed state and disconnects the quic connection
     fn fail(&mut self) {
-        trace!("{} | -> Failed", self.get_stats());
+        trace!("{} | -> Failed", self.quinn.remote_address());
         self.web_transport.state = ConnectionState::Failed;
         self.quinn.disconnect();
     }
 
     /// transitions to the connected state and fires the connected event
     fn success(&mut self, handler: &mut impl EndpointEventHandler<WebTransportEndpoint>) {
-        trace!("{} | -> Connected", self.get_stats());
+        trace!("{} | -> Connected", self.quinn.remote_address());
         self.web_transport.state = ConnectionState::Connected;
         handler.connected(self.connection_id);
     }
@@ -118,7 +118,10 @@ impl<'c> WebTransportConnectionMut<'c> {
                     if let (ConnectionState::ServerWaitConnectStream, true) =
                         (&self.web_transport.state, peer_generated)
                     {
-                        trace!("{} | -> ServerReadConnectRequest", self.get_stats());
+                        trace!(
+                            "{} | -> ServerReadConnectRequest",
+                            self.quinn.remote_address()
+                        );
                         self.web_transport.state =
                             ConnectionState::ServerReadConnectRequest(stream_id, Vec::new());
                         continue;
@@ -143,7 +146,7 @@ impl<'c> WebTransportConnectionMut<'c> {
                         (&self.web_transport.state, peer_generated)
                     {
                         // Client opened uni stream to send Settings.
-                        trace!("{} | -> ServerReadSettings", self.get_stats());
+                        trace!("{} | -> ServerReadSettings", self.quinn.remote_address());
                         self.web_transport.state =
                             ConnectionState::ServerReadSettings(stream_id, Vec::new());
                         continue;
@@ -155,7 +158,7 @@ impl<'c> WebTransportConnectionMut<'c> {
                         // Server opened uni stream to send settings response.
                         trace!(
                             "{} | -> ClientReceiveSettingsResponse",
-                            self.as_ref().get_stats()
+                            self.quinn.remote_address()
                         );
                         self.web_transport.state =
                             ConnectionState::ClientReceiveSettingsResponse(stream_id, Vec::new());
@@ -213,7 +216,10 @@ impl<'c> WebTransportConnectionMut<'c> {
                 }
 
                 if buffer.is_empty() {
-                    trace!("{} | -> ClientWaitSettingsResponse", self.get_stats());
+                    trace!(
+                        "{} | -> ClientWaitSettingsResponse",
+                        self.quinn.remote_address()
+                    );
                     self.web_transport.state = ConnectionState::ClientWaitSettingsResponse;
                 }
             }
@@ -238,7 +244,7 @@ impl<'c> WebTransportConnectionMut<'c> {
 
                         connect_req.encode(&mut buffer);
 
-                        trace!("{} | -> ClientSendConnect", self.get_stats());
+                        trace!("{} | -> ClientSendConnect", self.quinn.remote_address());
                         self.web_transport.state =
                             ConnectionState::ClientSendConnect(stream_id, buffer);
                     }
@@ -258,7 +264,10 @@ impl<'c> WebTransportConnectionMut<'c> {
                 buffer.clear();
 
                 if buffer.is_empty() {
-                    trace!("{} | ->  ClientReceiveConnectResponse", self.get_stats());
+                    trace!(
+                        "{} | ->  ClientReceiveConnectResponse",
+                        self.quinn.remote_address()
+                    );
                     self.web_transport.state =
                         ConnectionState::ClientReceiveConnectResponse(stream_id, buffer);
                 }
@@ -364,15 +373,15 @@ impl<'c> ConnectionMut<'c> for WebTransportConnectionMut<'c> {
         WebTransportConnectionRef { quinn: self.quinn }
     }
 
-    fn disconnect(&mut self) {
-        self.quinn.disconnect();
+    fn disconnect_with_reason(&mut self, code: u64, reason: &[u8]) {
+        self.quinn.disconnect_with_reason(code, reason);
     }
 }
 
 impl<'c> ConnectionRef<'c> for WebTransportConnectionRef<'c> {
-    type ConnectionStats = std::net::SocketAddr;
+    type ConnectionStats = QuinnConnectionStats;
 
-    fn get_stats(&self) -> std::net::SocketAddr {
+    fn get_stats(&self) -> QuinnConnectionStats {
         self.quinn.get_stats()
     }
 }
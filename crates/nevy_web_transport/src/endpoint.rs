@@ -40,10 +40,12 @@ impl Endpoint for WebTransportEndpoint {
 
     type IncomingConnectionInfo<'a> = &'a quinn_proto::Incoming;
 
+    type RemoteAddress = SocketAddr;
+
     fn update(&mut self, handler: &mut impl EndpointEventHandler<WebTransportEndpoint>) {
         struct QuinnEventHandler<'a> {
             connections: Vec<QuinnConnectionId>,
-            disconnections: Vec<QuinnConnectionId>,
+            disconnections: Vec<(QuinnConnectionId, DisconnectReason)>,
             on_request: &'a mut dyn EndpointEventHandler<WebTransportEndpoint>,
         }
 
@@ -56,8 +58,8 @@ impl Endpoint for WebTransportEndpoint {
                 self.connections.push(connection_id);
             }
 
-            fn disconnected(&mut self, connection_id: QuinnConnectionId) {
-                self.disconnections.push(connection_id);
+            fn disconnected(&mut self, connection_id: QuinnConnectionId, reason: DisconnectReason) {
+                self.disconnections.push((connection_id, reason));
             }
         }
 
@@ -79,8 +81,8 @@ impl Endpoint for WebTransportEndpoint {
             self.connection_mut(connection_id).unwrap().connected();
         }
 
-        for connection_id in quinn_handler.disconnections {
-            handler.disconnected(connection_id);
+        for (connection_id, reason) in quinn_handler.disconnections {
+            handler.disconnected(connection_id, reason);
             self.connections.remove(&connection_id);
         }
 
@@ -98,6 +100,10 @@ impl Endpoint for WebTransportEndpoint {
         }
     }
 
+    fn remote_address(info: &&quinn_proto::Incoming) -> SocketAddr {
+        info.remote_address()
+    }
+
     fn connection<'c>(
         &'c self,
         id: Self::ConnectionId,
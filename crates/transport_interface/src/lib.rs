@@ -19,6 +19,9 @@ pub trait Endpoint {
 
     type IncomingConnectionInfo<'i>;
 
+    /// the address of a peer, see [remote_address](Endpoint::remote_address)
+    type RemoteAddress: std::fmt::Debug + Send + Sync + 'static;
+
     /// Polls this endpoint and progresses its internal state.
     /// This may have a different effect on different endpoints, but generally it will also consume data from the underlying mechanism (socket).
     fn update(&mut self, handler: &mut impl EndpointEventHandler<Self>);
@@ -32,6 +35,9 @@ pub trait Endpoint {
     /// Retrieves a connection reference mutably from the endpoint given its unique id.
     fn connection_mut<'a>(&'a mut self, id: Self::ConnectionId) -> Option<Self::Connection<'a>>;
 
+    /// gets the address of the peer that sent an incoming connection request
+    fn remote_address(info: &Self::IncomingConnectionInfo<'_>) -> Self::RemoteAddress;
+
     /// creates a connection described by `info`
     fn connect<'c>(
         &'c mut self,
@@ -47,8 +53,27 @@ pub trait Endpoint {
             Err(())
         }
     }
+
+    /// Closes a connection matching the provided id with an application error code and reason.
+    fn disconnect_with_reason(
+        &mut self,
+        id: Self::ConnectionId,
+        code: u64,
+        reason: &[u8],
+    ) -> Result<(), UnknownConnection> {
+        if let Some(mut connection) = self.connection_mut(id) {
+            connection.disconnect_with_reason(code, reason);
+            Ok(())
+        } else {
+            Err(UnknownConnection)
+        }
+    }
 }
 
+/// returned when a connection id doesn't belong to an open connection of the endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownConnection;
+
 /// implement this trait on a type to handle events when updating an [Endpoint]
 ///
 /// make sure to override the [connection_request](EndpointEventHandler::connection_request)
@@ -71,7 +96,56 @@ where
 
     fn connected(&mut self, connection_id: E::ConnectionId);
 
-    fn disconnected(&mut self, connection_id: E::ConnectionId);
+    fn disconnected(&mut self, connection_id: E::ConnectionId, reason: DisconnectReason);
+}
+
+/// the reason a connection was disconnected,
+/// given to [EndpointEventHandler::disconnected]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// the connection was closed locally
+    LocallyClosed,
+    /// the peer closed the connection with an application close code and reason
+    ApplicationClosed { code: u64, reason: Box<[u8]> },
+    /// the peer refused the connection attempt
+    Refused,
+    /// the peer didn't respond for too long
+    TimedOut,
+    /// the tls handshake failed or the peer's certificate was rejected
+    TlsFailure { reason: String },
+    /// the connection was aborted because of a transport level error, either locally or by the peer
+    TransportError { code: u64, reason: String },
+    /// the peer lost the connection state, usually because it restarted
+    Reset,
+    /// either end closed the connection with [PROTOCOL_MISMATCH_CODE]
+    /// because the ends were built with different message protocols
+    ProtocolMismatch,
+    /// a transport specific reason that doesn't fit any of the other variants
+    Other(String),
+}
+
+/// the application close code reserved for closing connections with a mismatched message protocol
+///
+/// connections closed with this code are reported as [DisconnectReason::ProtocolMismatch] on both ends
+pub const PROTOCOL_MISMATCH_CODE: u64 = 0x6e65_7679;
+
+impl DisconnectReason {
+    /// the reason for a connection that the peer closed with an application close code
+    pub fn application_closed(code: u64, reason: Box<[u8]>) -> Self {
+        match code {
+            PROTOCOL_MISMATCH_CODE => DisconnectReason::ProtocolMismatch,
+            code => DisconnectReason::ApplicationClosed { code, reason },
+        }
+    }
+
+    /// the reason for a connection that was closed locally,
+    /// with the application close code if it is known
+    pub fn locally_closed(code: Option<u64>) -> Self {
+        match code {
+            Some(PROTOCOL_MISMATCH_CODE) => DisconnectReason::ProtocolMismatch,
+            _ => DisconnectReason::LocallyClosed,
+        }
+    }
 }
 
 /// contains all the operations that can be made with a mutable reference to connection state with a lifetime of `'c`
@@ -87,7 +161,15 @@ pub trait ConnectionMut<'c> {
     fn as_ref<'b>(&'b self) -> Self::NonMut<'b>;
 
     /// disconnect the client
-    fn disconnect(&mut self);
+    fn disconnect(&mut self) {
+        self.disconnect_with_reason(0, &[]);
+    }
+
+    /// disconnect the client with an application error code and reason
+    /// which the peer will receive as [DisconnectReason::ApplicationClosed]
+    ///
+    /// transports may truncate the code or reason if they are too large to send
+    fn disconnect_with_reason(&mut self, code: u64, reason: &[u8]);
 
     fn get_stats<'b>(&'b self) -> <Self::NonMut<'b> as ConnectionRef<'b>>::ConnectionStats {
         self.as_ref().get_stats()
@@ -121,11 +203,40 @@ pub trait ConnectionMut<'c> {
     fn poll_stream_events(&mut self) -> Option<StreamEvent<Self::StreamType>> {
         Self::StreamType::poll_events(self)
     }
+
+    /// queues an unreliable and unordered datagram to be sent
+    ///
+    /// the default implementation is for transports that don't support datagrams
+    #[allow(unused_variables)]
+    fn send_datagram(&mut self, data: &[u8]) -> Result<(), SendDatagramError> {
+        Err(SendDatagramError::Unsupported)
+    }
+
+    /// receives the next datagram if one is available
+    ///
+    /// the default implementation is for transports that don't support datagrams
+    fn recv_datagram(&mut self) -> Option<Box<[u8]>> {
+        None
+    }
+
+    /// returns the next datagram without receiving it, if one is available
+    ///
+    /// the default implementation is for transports that don't support datagrams
+    fn peek_datagram(&mut self) -> Option<&[u8]> {
+        None
+    }
+
+    /// the maximum size of a datagram that can currently be sent
+    ///
+    /// returns [None] if datagrams are not supported by the transport or the peer
+    fn max_datagram_size(&mut self) -> Option<usize> {
+        None
+    }
 }
 
 /// contains all the operations that can be made with a reference to connection state with a lifetime of `'c`
 pub trait ConnectionRef<'c> {
-    type ConnectionStats: std::fmt::Debug;
+    type ConnectionStats: std::fmt::Debug + Send + Sync + 'static;
 
     fn get_stats(&self) -> Self::ConnectionStats;
 }
@@ -214,6 +325,15 @@ pub trait RecvStreamMut<'s> {
     fn is_open(&self) -> bool;
 }
 
+/// errors that can occur when sending a datagram
+#[derive(Debug)]
+pub enum SendDatagramError {
+    /// datagrams aren't supported by the transport, or have been disabled locally or by the peer
+    Unsupported,
+    /// the datagram is larger than [max_datagram_size](ConnectionMut::max_datagram_size)
+    TooLarge,
+}
+
 /// events fired by streams
 pub struct StreamEvent<S> {
     pub stream_id: S,
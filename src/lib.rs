@@ -4,12 +4,27 @@ pub use bevy_interface as bevy;
 
 pub use nevy_messaging as messaging;
 
+#[cfg(feature = "replication")]
+pub use nevy_replication as replication;
+
+#[cfg(feature = "prediction")]
+pub use nevy_prediction as prediction;
+
+#[cfg(feature = "interpolation")]
+pub use nevy_interpolation as interpolation;
+
+#[cfg(feature = "interest")]
+pub use nevy_interest as interest;
+
 #[cfg(feature = "quic")]
 pub use nevy_quic as quic;
 
 #[cfg(feature = "web_transport")]
 pub use nevy_web_transport as web_transport;
 
+#[cfg(feature = "loopback")]
+pub use nevy_loopback as loopback;
+
 pub mod prelude {
     pub use transport_interface::*;
 
@@ -17,9 +32,24 @@ pub mod prelude {
 
     pub use nevy_messaging::prelude::*;
 
+    #[cfg(feature = "replication")]
+    pub use nevy_replication::prelude::*;
+
+    #[cfg(feature = "prediction")]
+    pub use nevy_prediction::prelude::*;
+
+    #[cfg(feature = "interpolation")]
+    pub use nevy_interpolation::prelude::*;
+
+    #[cfg(feature = "interest")]
+    pub use nevy_interest::prelude::*;
+
     #[cfg(feature = "quic")]
     pub use nevy_quic::prelude::*;
 
     #[cfg(feature = "web_transport")]
     pub use nevy_web_transport::prelude::*;
+
+    #[cfg(feature = "loopback")]
+    pub use nevy_loopback::prelude::*;
 }